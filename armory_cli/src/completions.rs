@@ -0,0 +1,410 @@
+//! Completion scripts for `cargo armory`, generated from the CLI's own
+//! definition so new commands and flags complete without touching them.
+//! Where the shell allows it they hook into cargo's completions, leaving
+//! every other cargo command to those.
+
+use clap::{ArgAction, ValueEnum, ValueHint};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// What an option takes after it.
+enum Takes {
+    Nothing,
+    Value,
+    Path,
+    OneOf(Vec<String>),
+}
+
+struct Opt {
+    long: Option<String>,
+    short: Option<char>,
+    help: String,
+    value_name: String,
+    takes: Takes,
+    /// Whether it can be given more than once, like `-vv`.
+    repeats: bool,
+}
+
+impl Opt {
+    /// How it's written, short first.
+    fn names(&self) -> Vec<String> {
+        let short = self.short.map(|short| format!("-{}", short));
+        short.into_iter().chain(self.long.iter().map(|long| format!("--{}", long))).collect()
+    }
+}
+
+struct Positional {
+    name: String,
+    /// The values it takes, if they're picked from a list.
+    values: Vec<String>,
+    many: bool,
+}
+
+struct Cmd {
+    name: String,
+    help: String,
+    opts: Vec<Opt>,
+    positionals: Vec<Positional>,
+}
+
+fn first_line(text: Option<&clap::builder::StyledStr>) -> String {
+    text.and_then(|text| text.to_string().lines().next().map(str::to_string)).unwrap_or_default()
+}
+
+fn describe(cmd: &clap::Command) -> Cmd {
+    let mut opts = Vec::new();
+    let mut positionals = Vec::new();
+    for arg in cmd.get_arguments().filter(|arg| !arg.is_hide_set()) {
+        let values: Vec<String> = arg
+            .get_possible_values()
+            .iter()
+            .filter(|value| !value.is_hide_set())
+            .map(|value| value.get_name().to_string())
+            .collect();
+        let value_name = arg
+            .get_value_names()
+            .and_then(|names| names.first())
+            .map_or_else(|| arg.get_id().to_string(), |name| name.to_string());
+        if arg.is_positional() {
+            let many = arg.get_num_args().is_some_and(|range| range.max_values() > 1);
+            positionals.push(Positional { name: value_name.to_lowercase(), values, many });
+            continue;
+        }
+        let takes = match arg.get_value_hint() {
+            _ if !arg.get_action().takes_values() => Takes::Nothing,
+            _ if !values.is_empty() => Takes::OneOf(values),
+            ValueHint::AnyPath | ValueHint::FilePath | ValueHint::DirPath => Takes::Path,
+            _ => Takes::Value,
+        };
+        opts.push(Opt {
+            long: arg.get_long().map(str::to_string),
+            short: arg.get_short(),
+            help: first_line(arg.get_help()),
+            value_name,
+            takes,
+            repeats: matches!(arg.get_action(), ArgAction::Count | ArgAction::Append),
+        });
+    }
+    Cmd { name: cmd.get_name().to_string(), help: first_line(cmd.get_about()), opts, positionals }
+}
+
+/// Prints the completion script for `shell`, built from `cargo`, the
+/// definition of the whole CLI.
+pub fn print(shell: Shell, mut cargo: clap::Command) {
+    cargo.build();
+    let armory = cargo.find_subcommand("armory").expect("The CLI has no armory command");
+    let top = describe(armory);
+    let commands: Vec<Cmd> = armory.get_subcommands().filter(|cmd| !cmd.is_hide_set()).map(describe).collect();
+    let script = match shell {
+        Shell::Bash => bash(&top, &commands),
+        Shell::Zsh => zsh(&top, &commands),
+        Shell::Fish => fish(&top, &commands),
+        Shell::Powershell => powershell(&top, &commands),
+    };
+    print!("{}", script);
+}
+
+fn bash(top: &Cmd, commands: &[Cmd]) -> String {
+    // the words completing `cmd`, and what's completed after each option
+    // taking a value
+    fn case(cmd: &Cmd, extra: &[&str]) -> String {
+        let mut words: Vec<String> = cmd.opts.iter().flat_map(Opt::names).collect();
+        words.extend(extra.iter().map(|word| word.to_string()));
+        words.extend(cmd.positionals.iter().flat_map(|positional| positional.values.clone()));
+        let mut case = String::from("            case \"$prev\" in\n");
+        for opt in &cmd.opts {
+            let reply = match &opt.takes {
+                Takes::Nothing => continue,
+                Takes::Value => "return".to_string(),
+                Takes::Path => "COMPREPLY=($(compgen -f -- \"$cur\")); return".to_string(),
+                Takes::OneOf(values) => {
+                    format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return", values.join(" "))
+                }
+            };
+            case.push_str(&format!("                {}) {} ;;\n", opt.names().join("|"), reply));
+        }
+        case.push_str("            esac\n");
+        case.push_str(&format!("            words=\"{}\"\n", words.join(" ")));
+        case
+    }
+
+    let names: Vec<&str> = commands.iter().map(|cmd| cmd.name.as_str()).collect();
+    let global_values: Vec<String> = top
+        .opts
+        .iter()
+        .filter(|opt| !matches!(opt.takes, Takes::Nothing))
+        .flat_map(Opt::names)
+        .collect();
+    let mut cases = format!("        \"\")\n{}            ;;\n", case(top, &names));
+    for cmd in commands {
+        cases.push_str(&format!("        {})\n{}            ;;\n", cmd.name, case(cmd, &[])));
+    }
+    format!(
+        r#"# Completions for `cargo armory`. Source this after cargo's own
+# completions, e.g. from ~/.bashrc.
+_cargo_armory() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}" cmd= words i
+    # everything but `cargo armory` is left to cargo's own completions
+    if [[ $COMP_CWORD -lt 2 || ${{COMP_WORDS[1]}} != armory ]]; then
+        declare -F _cargo >/dev/null && _cargo "$@"
+        return
+    fi
+    for ((i = 2; i < COMP_CWORD; i++)); do
+        case "${{COMP_WORDS[i]}}" in
+            {}) ((i++)) ;;
+            {}) cmd="${{COMP_WORDS[i]}}"; break ;;
+        esac
+    done
+    case "$cmd" in
+{}    esac
+    COMPREPLY=($(compgen -W "$words" -- "$cur"))
+}}
+if ! declare -F _cargo >/dev/null && declare -F _completion_loader >/dev/null; then
+    _completion_loader cargo
+fi
+complete -F _cargo_armory -o bashdefault -o default cargo
+"#,
+        global_values.join("|"),
+        names.join("|"),
+        cases
+    )
+}
+
+fn zsh_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\'' => escaped.push_str("'\\''"),
+            '\\' | '[' | ']' | ':' | '$' | '`' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn zsh(top: &Cmd, commands: &[Cmd]) -> String {
+    // the `_arguments` specs for `cmd`'s options and positionals
+    fn specs(cmd: &Cmd) -> Vec<String> {
+        let mut specs = Vec::new();
+        for opt in &cmd.opts {
+            let takes_value = !matches!(opt.takes, Takes::Nothing);
+            let mut names = Vec::new();
+            if let Some(short) = opt.short {
+                names.push(format!("-{}{}", short, if takes_value { "+" } else { "" }));
+            }
+            if let Some(long) = &opt.long {
+                names.push(format!("--{}{}", long, if takes_value { "=" } else { "" }));
+            }
+            let prefix = match names.len() {
+                _ if opt.repeats => "'*'".to_string(),
+                1 => String::new(),
+                _ => format!("'({})'", opt.names().join(" ")),
+            };
+            let names = match names.len() {
+                1 => names.remove(0),
+                _ => format!("{{{}}}", names.join(",")),
+            };
+            let action = match &opt.takes {
+                Takes::Nothing => String::new(),
+                Takes::Value => format!(":{}: ", zsh_escape(&opt.value_name)),
+                Takes::Path => format!(":{}:_files", zsh_escape(&opt.value_name)),
+                Takes::OneOf(values) => format!(":{}:({})", zsh_escape(&opt.value_name), values.join(" ")),
+            };
+            specs.push(format!("{}{}'[{}]{}'", prefix, names, zsh_escape(&opt.help), action));
+        }
+        for positional in &cmd.positionals {
+            let action = match positional.values.is_empty() {
+                true => "_default".to_string(),
+                false => format!("({})", positional.values.join(" ")),
+            };
+            let many = if positional.many { "*" } else { "" };
+            specs.push(format!("'{}:{}:{}'", many, zsh_escape(&positional.name), action));
+        }
+        specs
+    }
+
+    let join = |specs: Vec<String>, indent: &str| {
+        specs.iter().map(|spec| format!("{}{} \\\n", indent, spec)).collect::<String>()
+    };
+    let described: String = commands
+        .iter()
+        .map(|cmd| format!("                '{}:{}'\n", cmd.name, cmd.help.replace('\'', "'\\''")))
+        .collect();
+    let mut cases = String::new();
+    for cmd in commands {
+        cases.push_str(&format!("                ({})\n                    _arguments -s \\\n", cmd.name));
+        cases.push_str(&join(specs(cmd), "                        "));
+        cases.push_str("                        && ret=0\n                    ;;\n");
+    }
+    format!(
+        r#"#autoload
+# Completions for `cargo armory`, which cargo's own zsh completions call
+# as _cargo-armory. Save this as _cargo-armory in a directory in $fpath.
+_cargo-armory() {{
+    local curcontext="$curcontext" state line ret=1
+    _arguments -C -s \
+{}        ': :->command' \
+        '*:: :->args' \
+        && ret=0
+    case $state in
+        command)
+            local -a commands=(
+{}            )
+            _describe -t commands 'armory command' commands && ret=0
+            ;;
+        args)
+            curcontext="${{curcontext%:*}}-$line[1]:"
+            case $line[1] in
+{}            esac
+            ;;
+    esac
+    return ret
+}}
+if [ "$funcstack[1]" = "_cargo-armory" ]; then
+    _cargo-armory "$@"
+fi
+"#,
+        join(specs(top), "        "),
+        described,
+        cases
+    )
+}
+
+fn fish_escape(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn fish(top: &Cmd, commands: &[Cmd]) -> String {
+    fn complete(condition: &str, cmd: &Cmd) -> String {
+        let mut lines = String::new();
+        for opt in &cmd.opts {
+            let mut line = format!("complete -c cargo -n {}", fish_escape(condition));
+            if let Some(short) = opt.short {
+                line.push_str(&format!(" -s {}", short));
+            }
+            if let Some(long) = &opt.long {
+                line.push_str(&format!(" -l {}", long));
+            }
+            match &opt.takes {
+                Takes::Nothing => {}
+                Takes::Value => line.push_str(" -r"),
+                Takes::Path => line.push_str(" -r -F"),
+                Takes::OneOf(values) => line.push_str(&format!(" -r -a {}", fish_escape(&values.join(" ")))),
+            }
+            lines.push_str(&format!("{} -d {}\n", line, fish_escape(&opt.help)));
+        }
+        let values: Vec<String> = cmd.positionals.iter().flat_map(|positional| positional.values.clone()).collect();
+        if !values.is_empty() {
+            lines.push_str(&format!(
+                "complete -c cargo -n {} -a {}\n",
+                fish_escape(condition),
+                fish_escape(&values.join(" "))
+            ));
+        }
+        lines
+    }
+
+    let names: Vec<&str> = commands.iter().map(|cmd| cmd.name.as_str()).collect();
+    let top_condition = format!(
+        "__fish_seen_subcommand_from armory; and not __fish_seen_subcommand_from {}",
+        names.join(" ")
+    );
+    let mut script = String::from(
+        "# Completions for `cargo armory`. Save this in ~/.config/fish/conf.d, as\n\
+         # completions for cargo are only autoloaded from its own file.\n\
+         complete -c cargo -n '__fish_seen_subcommand_from armory' -f\n",
+    );
+    script.push_str(&complete(&top_condition, top));
+    for cmd in commands {
+        script.push_str(&format!(
+            "complete -c cargo -n {} -a {} -d {}\n",
+            fish_escape(&top_condition),
+            cmd.name,
+            fish_escape(&cmd.help)
+        ));
+    }
+    for cmd in commands {
+        let condition = format!("__fish_seen_subcommand_from armory; and __fish_seen_subcommand_from {}", cmd.name);
+        script.push_str(&complete(&condition, cmd));
+    }
+    script
+}
+
+fn powershell_escape(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn powershell(top: &Cmd, commands: &[Cmd]) -> String {
+    fn result(text: &str, kind: &str, help: &str) -> String {
+        // the tooltip can't be empty
+        let help = if help.is_empty() { text } else { help };
+        format!(
+            "            [CompletionResult]::new({}, {}, [CompletionResultType]::{}, {})\n",
+            powershell_escape(text),
+            powershell_escape(text.trim_start_matches('-')),
+            kind,
+            powershell_escape(help)
+        )
+    }
+
+    fn case(words: &str, cmd: &Cmd, commands: &[Cmd]) -> String {
+        let mut case = format!("        {} {{\n", powershell_escape(words));
+        for opt in &cmd.opts {
+            for name in opt.names() {
+                case.push_str(&result(&name, "ParameterName", &opt.help));
+            }
+        }
+        for command in commands {
+            case.push_str(&result(&command.name, "ParameterValue", &command.help));
+        }
+        for value in cmd.positionals.iter().flat_map(|positional| &positional.values) {
+            case.push_str(&result(value, "ParameterValue", value));
+        }
+        case.push_str("            break\n        }\n");
+        case
+    }
+
+    let mut cases = case("armory", top, commands);
+    for cmd in commands {
+        cases.push_str(&case(&format!("armory {}", cmd.name), cmd, &[]));
+    }
+    format!(
+        r#"# Completions for `cargo armory`. Add this to your $PROFILE. It replaces
+# any other completer registered for cargo.
+using namespace System.Management.Automation
+using namespace System.Management.Automation.Language
+
+Register-ArgumentCompleter -Native -CommandName cargo -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+
+    # the words before the one being completed, up to the first option
+    $command = @(
+        for ($i = 1; $i -lt $commandAst.CommandElements.Count; $i++) {{
+            $element = $commandAst.CommandElements[$i]
+            if ($element -isnot [StringConstantExpressionAst] -or
+                $element.StringConstantType -ne [StringConstantType]::BareWord -or
+                $element.Value.StartsWith('-') -or
+                $element.Value -eq $wordToComplete) {{
+                break
+            }}
+            $element.Value
+        }}) -join ' '
+
+    $completions = @(switch ($command) {{
+{}    }})
+
+    $completions.Where{{ $_.CompletionText -like "$wordToComplete*" }} | Sort-Object -Property ListItemText
+}}
+"#,
+        cases
+    )
+}
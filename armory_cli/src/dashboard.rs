@@ -0,0 +1,334 @@
+//! A full-screen view of a publish for workspaces with too many crates to
+//! follow scrolling by: the dependency graph, level by level with what each
+//! crate waits for, where each crate is at with any retry counting down,
+//! and the latest output under them. The output is printed as usual once
+//! the publish is over, so nothing is lost with the screen.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Stderr},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use armory_lib::Progress;
+use dialoguer::console::{self, Term};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Row, Table},
+    Frame, Terminal,
+};
+use semver::Version;
+
+use crate::progress::describe;
+
+/// Switches to the terminal's alternate screen, and back.
+const ENTER_SCREEN: &str = "\x1b[?1049h";
+const LEAVE_SCREEN: &str = "\x1b[?1049l";
+
+/// The fewest lines of output shown, however many crates there are.
+const MIN_LOG_LINES: u16 = 5;
+
+#[derive(Default)]
+struct Crate {
+    /// Where it's at, or `None` until its turn.
+    progress: Option<Progress>,
+    /// When its turn came.
+    started: Option<Instant>,
+    /// When it moved on to its current step, to count waits down from.
+    changed: Option<Instant>,
+    /// How long it took, once done with.
+    took: Option<Duration>,
+}
+
+struct Dashboard {
+    term: Term,
+    terminal: Terminal<CrosstermBackend<Stderr>>,
+    view: View,
+}
+
+/// What the dashboard shows.
+struct View {
+    /// The crates in publish order, grouped by how deep they are in the
+    /// dependency graph.
+    groups: Vec<Vec<String>>,
+    /// The local dependencies of each crate that are published with it.
+    deps: HashMap<String, Vec<String>>,
+    crates: HashMap<String, Crate>,
+    started: Instant,
+    /// Every line of output, to print once the dashboard is gone.
+    log: Vec<String>,
+}
+
+static DASHBOARD: Mutex<Option<Dashboard>> = Mutex::new(None);
+
+impl Dashboard {
+    fn draw(&mut self) {
+        let view = &self.view;
+        let _ = self.terminal.draw(|frame| view.render(frame));
+    }
+}
+
+impl View {
+    fn count(&self, progress: Progress) -> usize {
+        self.crates.values().filter(|state| state.progress == Some(progress)).count()
+    }
+
+    /// How `name` is styled for where it's at.
+    fn style(&self, name: &str) -> Style {
+        match self.crates[name].progress {
+            None => Style::default().add_modifier(Modifier::DIM),
+            Some(Progress::Done) => Style::default().fg(Color::Green),
+            Some(Progress::Failed) => Style::default().fg(Color::Red),
+            Some(_) => Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        }
+    }
+
+    fn status(&self, name: &str) -> Span<'static> {
+        let state = &self.crates[name];
+        match (state.progress, state.changed) {
+            (Some(Progress::Done), _) => Span::styled("✔ done", Style::default().fg(Color::Green)),
+            (Some(Progress::Failed), _) => Span::styled("✘ failed", Style::default().fg(Color::Red)),
+            (Some(progress), Some(changed)) => {
+                Span::styled(describe(progress, changed), Style::default().fg(Color::Yellow))
+            }
+            _ => Span::styled("pending", Style::default().add_modifier(Modifier::DIM)),
+        }
+    }
+
+    fn time(&self, name: &str) -> String {
+        let state = &self.crates[name];
+        match (state.took, state.started) {
+            (Some(took), _) => format!("{:.1}s", took.as_secs_f64()),
+            (None, Some(started)) => format!("{}s", started.elapsed().as_secs()),
+            _ => String::new(),
+        }
+    }
+
+    /// Whether `name` is still to go or under way.
+    fn unfinished(&self, name: &str) -> bool {
+        !matches!(self.crates[name].progress, Some(Progress::Done | Progress::Failed))
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let area = frame.size();
+        let log_lines = MIN_LOG_LINES.max(area.height / 3) + 2;
+        let [header, body, output] = split(Direction::Vertical, area, [
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(log_lines),
+        ]);
+        let [graph, table] =
+            split(Direction::Horizontal, body, [Constraint::Percentage(55), Constraint::Percentage(45)]);
+
+        let total: usize = self.groups.iter().map(Vec::len).sum();
+        let summary = format!(
+            " publishing {} crates: {} done, {} failed, {}s",
+            total,
+            self.count(Progress::Done),
+            self.count(Progress::Failed),
+            self.started.elapsed().as_secs()
+        );
+        let name = Span::styled("armory", Style::default().add_modifier(Modifier::BOLD));
+        let title = Line::from(vec![name, summary.into()]);
+        frame.render_widget(Paragraph::new(title), header);
+
+        self.render_graph(frame, graph);
+        self.render_table(frame, table);
+
+        let block = Block::default().borders(Borders::ALL).title("Output");
+        let room = block.inner(output).height as usize;
+        let lines: Vec<Line> = self
+            .log
+            .iter()
+            .skip(self.log.len().saturating_sub(room))
+            .map(|line| Line::from(console::strip_ansi_codes(line).into_owned()))
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(block), output);
+    }
+
+    /// The dependency graph a level at a time, each crate with the crates
+    /// it waits for, scrolled to the first level still to go.
+    fn render_graph(&self, frame: &mut Frame, area: Rect) {
+        let mut lines = Vec::new();
+        let mut first = None;
+        for (depth, group) in self.groups.iter().enumerate() {
+            if first.is_none() && group.iter().any(|name| self.unfinished(name)) {
+                first = Some(lines.len());
+            }
+            lines.push(Line::styled(format!("level {}", depth + 1), Style::default().add_modifier(Modifier::BOLD)));
+            for (index, name) in group.iter().enumerate() {
+                let branch = if index + 1 == group.len() { "└─ " } else { "├─ " };
+                let mut spans = vec![Span::raw(branch), Span::styled(name.clone(), self.style(name))];
+                let deps = &self.deps[name];
+                if !deps.is_empty() {
+                    spans.push(Span::styled(" ◀ ", Style::default().add_modifier(Modifier::DIM)));
+                    for (index, dep) in deps.iter().enumerate() {
+                        if index > 0 {
+                            spans.push(Span::raw(", "));
+                        }
+                        spans.push(Span::styled(dep.clone(), self.style(dep)));
+                    }
+                }
+                lines.push(Line::from(spans));
+            }
+        }
+
+        let block = Block::default().borders(Borders::ALL).title("Dependency graph");
+        let room = block.inner(area).height as usize;
+        let scroll = first.unwrap_or(lines.len()).min(lines.len().saturating_sub(room));
+        frame.render_widget(Paragraph::new(lines).block(block).scroll((scroll as u16, 0)), area);
+    }
+
+    /// Each crate's time and status in publish order, scrolled to the
+    /// crates still to go.
+    fn render_table(&self, frame: &mut Frame, area: Rect) {
+        let order: Vec<&String> = self.groups.iter().flatten().collect();
+        let block = Block::default().borders(Borders::ALL).title("Crates");
+        // less the header row
+        let room = (block.inner(area).height as usize).saturating_sub(1).max(1);
+        let first = order
+            .iter()
+            .position(|name| self.unfinished(name))
+            .unwrap_or(order.len())
+            .saturating_sub(2)
+            .min(order.len().saturating_sub(room));
+        let rows: Vec<Row> = order
+            .iter()
+            .skip(first)
+            .take(room)
+            .map(|name| Row::new(vec![Span::raw(name.to_string()), Span::raw(self.time(name)), self.status(name)]))
+            .collect();
+        let width = order.iter().map(|name| name.len()).max().unwrap_or(0).max("CRATE".len());
+        let widths = [Constraint::Length(width as u16), Constraint::Length(8), Constraint::Min(10)];
+        let header = Row::new(["CRATE", "TIME", "STATUS"]).style(Style::default().add_modifier(Modifier::BOLD));
+        frame.render_widget(Table::new(rows, widths).header(header).block(block), area);
+    }
+}
+
+/// Splits `area` into `N` parts along `direction`.
+fn split<const N: usize>(direction: Direction, area: Rect, constraints: [Constraint; N]) -> [Rect; N] {
+    let parts = Layout::default().direction(direction).constraints(constraints).split(area);
+    std::array::from_fn(|index| parts[index])
+}
+
+/// Takes over `term` to show the publish of `versions` in the order of
+/// `graph`, and armory's output. Returns what to pass the publish's
+/// progress to, or `None` if the terminal can't be drawn on.
+pub fn start(
+    term: Term,
+    graph: &HashMap<String, HashSet<String>>,
+    versions: &HashMap<String, Version>,
+) -> Option<fn(&str, Progress)> {
+    let terminal = Terminal::new(CrosstermBackend::new(io::stderr())).ok()?;
+    let groups: Vec<Vec<String>> = armory_lib::publish_groups(graph)
+        .into_iter()
+        .map(|group| group.into_iter().filter(|name| versions.contains_key(name)).collect::<Vec<_>>())
+        .filter(|group| !group.is_empty())
+        .collect();
+    let crates = groups.iter().flatten().map(|name| (name.clone(), Crate::default())).collect();
+    let deps = groups
+        .iter()
+        .flatten()
+        .map(|name| {
+            let mut deps: Vec<String> = graph[name].iter().filter(|dep| versions.contains_key(*dep)).cloned().collect();
+            deps.sort();
+            (name.clone(), deps)
+        })
+        .collect();
+
+    let _ = term.write_str(ENTER_SCREEN);
+    let _ = term.hide_cursor();
+    let view = View { groups, deps, crates, started: Instant::now(), log: Vec::new() };
+    let mut dashboard = Dashboard { term, terminal, view };
+    let _ = dashboard.terminal.clear();
+    dashboard.draw();
+    *DASHBOARD.lock().unwrap() = Some(dashboard);
+    armory_lib::output::set_sink(Some(print_line));
+    // keep the times and countdowns ticking
+    thread::spawn(|| loop {
+        thread::sleep(Duration::from_millis(250));
+        match DASHBOARD.lock().unwrap().as_mut() {
+            Some(dashboard) => dashboard.draw(),
+            None => break,
+        }
+    });
+    Some(update)
+}
+
+fn update(name: &str, progress: Progress) {
+    let mut dashboard = DASHBOARD.lock().unwrap();
+    let Some(dashboard) = dashboard.as_mut() else {
+        return;
+    };
+    // only the crates being published are shown
+    let Some(state) = dashboard.view.crates.get_mut(name) else {
+        return;
+    };
+    let started = *state.started.get_or_insert_with(Instant::now);
+    if matches!(progress, Progress::Done | Progress::Failed) {
+        state.took = Some(started.elapsed());
+    }
+    state.progress = Some(progress);
+    state.changed = Some(Instant::now());
+    dashboard.draw();
+}
+
+fn print_line(line: &str) {
+    match DASHBOARD.lock().unwrap().as_mut() {
+        Some(dashboard) => {
+            dashboard.view.log.push(line.to_string());
+            dashboard.draw();
+        }
+        None => eprintln!("{}", line),
+    }
+}
+
+/// Gives the terminal back and prints the output the dashboard showed.
+pub fn finish() {
+    let Some(dashboard) = DASHBOARD.lock().unwrap().take() else {
+        return;
+    };
+    let _ = dashboard.term.show_cursor();
+    let _ = dashboard.term.write_str(LEAVE_SCREEN);
+    for line in &dashboard.view.log {
+        let _ = dashboard.term.write_line(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+
+    use super::*;
+
+    #[test]
+    fn the_graph_shows_what_each_crate_waits_for() {
+        let groups = vec![vec!["a".to_string()], vec!["b".to_string(), "c".to_string()]];
+        let deps = HashMap::from([
+            ("a".to_string(), vec![]),
+            ("b".to_string(), vec!["a".to_string()]),
+            ("c".to_string(), vec!["a".to_string(), "b".to_string()]),
+        ]);
+        let mut crates: HashMap<String, Crate> =
+            ["a", "b", "c"].into_iter().map(|name| (name.to_string(), Crate::default())).collect();
+        crates.get_mut("a").unwrap().progress = Some(Progress::Done);
+        let view = View { groups, deps, crates, started: Instant::now(), log: Vec::new() };
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        terminal.draw(|frame| view.render(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let screen: Vec<String> = (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer.get(x, y).symbol()).collect())
+            .collect();
+        let shows = |text: &str| screen.iter().any(|line| line.contains(text));
+        assert!(shows("level 2"));
+        assert!(shows("├─ b ◀ a"));
+        assert!(shows("└─ c ◀ a, b"));
+        assert!(shows("✔ done"));
+    }
+}
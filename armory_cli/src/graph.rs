@@ -0,0 +1,96 @@
+//! The publish graph drawn as DOT or Mermaid, for runbooks and for
+//! spotting coupling between crates that shouldn't be there.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz, e.g. for `dot -Tsvg`
+    #[default]
+    Dot,
+    /// A Mermaid flowchart, which GitHub and GitLab render in Markdown
+    Mermaid,
+}
+
+/// Draws `graph`, the local dependencies of each member published, with
+/// each member's `label` and, unless it's empty, their `external`
+/// dependencies as well. Arrows point from a crate to what it depends on,
+/// so the crates published first are at the bottom.
+pub fn render(
+    format: GraphFormat,
+    graph: &HashMap<String, HashSet<String>>,
+    labels: &HashMap<String, String>,
+    external: &HashMap<String, BTreeSet<String>>,
+) -> String {
+    // sorted so the output only changes when the graph does
+    let graph: BTreeMap<&str, BTreeSet<&str>> = graph
+        .iter()
+        .map(|(name, deps)| (name.as_str(), deps.iter().map(String::as_str).collect()))
+        .collect();
+    let external: BTreeMap<&str, &BTreeSet<String>> = external
+        .iter()
+        .filter(|(name, _)| graph.contains_key(name.as_str()))
+        .map(|(name, deps)| (name.as_str(), deps))
+        .collect();
+    let outside: BTreeSet<&str> = external.values().flat_map(|deps| deps.iter().map(String::as_str)).collect();
+    let label = |name: &str| labels.get(name).cloned().unwrap_or_else(|| name.to_string());
+
+    let mut lines = Vec::new();
+    match format {
+        GraphFormat::Dot => {
+            let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+            lines.push("digraph armory {".to_string());
+            lines.push("    node [shape=box];".to_string());
+            for name in graph.keys() {
+                lines.push(format!("    {} [label={}];", quote(name), quote(&label(name))));
+            }
+            for name in &outside {
+                // a member may share a name with a crate it doesn't depend
+                // on locally, so these get their own ids
+                lines.push(format!("    {} [label={}, style=dashed];", quote(&format!("external:{}", name)), quote(name)));
+            }
+            for (name, deps) in &graph {
+                for dep in deps {
+                    lines.push(format!("    {} -> {};", quote(name), quote(dep)));
+                }
+            }
+            for (name, deps) in &external {
+                for dep in deps.iter() {
+                    lines.push(format!("    {} -> {} [style=dashed];", quote(name), quote(&format!("external:{}", dep))));
+                }
+            }
+            lines.push("}".to_string());
+        }
+        GraphFormat::Mermaid => {
+            // crate names can't all be Mermaid ids, so nodes are numbered
+            let ids: HashMap<(bool, &str), String> = graph
+                .keys()
+                .map(|name| (false, *name))
+                .chain(outside.iter().map(|name| (true, *name)))
+                .enumerate()
+                .map(|(index, node)| (node, format!("n{}", index)))
+                .collect();
+            let quote = |text: &str| format!("\"{}\"", text.replace('"', "#quot;"));
+            lines.push("flowchart TD".to_string());
+            for name in graph.keys() {
+                lines.push(format!("    {}[{}]", ids[&(false, *name)], quote(&label(name))));
+            }
+            for name in &outside {
+                lines.push(format!("    {}([{}])", ids[&(true, *name)], quote(name)));
+            }
+            for (name, deps) in &graph {
+                for dep in deps {
+                    lines.push(format!("    {} --> {}", ids[&(false, *name)], ids[&(false, *dep)]));
+                }
+            }
+            for (name, deps) in &external {
+                for dep in deps.iter() {
+                    lines.push(format!("    {} -.-> {}", ids[&(false, *name)], ids[&(true, dep.as_str())]));
+                }
+            }
+        }
+    }
+    lines.join("\n")
+}
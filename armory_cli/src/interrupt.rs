@@ -0,0 +1,99 @@
+//! Ctrl-C during a publish. Killing armory mid-upload would leave no telling
+//! whether the crate made it, so the first Ctrl-C, or a SIGTERM from a
+//! cancelled CI job, lets the crate being published finish and stops the
+//! publish there, with the state saved for `resume`. A second one gives up
+//! on the upload under way, and armory quits once the publish has saved
+//! its state.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use armory_lib::warning;
+use dialoguer::console::{style, Term};
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use crate::ExitCode;
+
+/// Set by the signal handlers once they're in place.
+static CAUGHT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+/// Whether a publish is catching Ctrl-C.
+static CATCHING: AtomicBool = AtomicBool::new(false);
+/// Whether the publish was asked to stop.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+/// Whether the upload under way was given up on as well.
+static FORCED: AtomicBool = AtomicBool::new(false);
+
+/// Catches Ctrl-C until [`release`]. Returns what to pass the publish to
+/// tell it to stop, and [`forced`] tells it to give up on an upload.
+pub fn catch() -> fn() -> bool {
+    STOPPING.store(false, Ordering::Relaxed);
+    FORCED.store(false, Ordering::Relaxed);
+    CATCHING.store(true, Ordering::Relaxed);
+    CAUGHT.get_or_init(|| {
+        let caught = Arc::new(AtomicBool::new(false));
+        for signal in [SIGINT, SIGTERM] {
+            signal_hook::flag::register(signal, caught.clone()).expect("Failed to catch Ctrl-C");
+        }
+        // the handlers can only set a flag, so the telling and quitting
+        // happen here. They stay in place once the publish is over, as
+        // there's no putting the default ones back
+        let watched = caught.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(100));
+            if watched.swap(false, Ordering::Relaxed) {
+                interrupted();
+            }
+        });
+        caught
+    });
+    stopping
+}
+
+fn interrupted() {
+    if !CATCHING.load(Ordering::Relaxed) {
+        std::process::exit(ExitCode::Interrupted as i32);
+    }
+    if STOPPING.swap(true, Ordering::Relaxed) {
+        if !FORCED.swap(true, Ordering::Relaxed) {
+            warning!("{} giving up on the upload, quitting once the state is saved", style("interrupted:").yellow());
+        }
+        return;
+    }
+    warning!(
+        "{} stopping once the crate being published is done, press Ctrl-C again to quit now",
+        style("interrupted:").yellow()
+    );
+}
+
+fn stopping() -> bool {
+    STOPPING.load(Ordering::Relaxed)
+}
+
+/// Whether a second Ctrl-C gave up on the upload under way.
+pub fn forced() -> bool {
+    FORCED.load(Ordering::Relaxed)
+}
+
+/// Quits if the publish that failed with `error` was [`forced`] to stop.
+/// The crate being published may have made it, so the bump and the state
+/// stay for `resume`.
+pub fn exit_if_forced(term: &Term, error: &impl std::fmt::Display) {
+    if forced() {
+        crate::exit_with(term, ExitCode::Interrupted, format!(
+            "{}\nthe crate being published may or may not have made it, `cargo armory resume` checks the registry \
+             before publishing it again",
+            error
+        ));
+    }
+}
+
+/// Lets Ctrl-C quit armory again.
+pub fn release() {
+    CATCHING.store(false, Ordering::Relaxed);
+}
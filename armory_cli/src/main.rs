@@ -1,54 +1,2844 @@
-use dialoguer::{Select, theme::ColorfulTheme, console::{Term, style}};
+use std::{collections::{BTreeMap, BTreeSet, HashMap, HashSet}, io::IsTerminal, path::{Path, PathBuf}, time::Duration};
 
-fn main() -> Result<(), std::io::Error> {
-    let term = Term::stdout();
-    let cwd = std::env::current_dir()?;
-    let mut armory_toml = armory_lib::load_armory_toml(&cwd).unwrap();
-    let theme = ColorfulTheme::default();
-
-    let version = &armory_toml.version;
-
-    let items = vec![
-        ("Patch", {
-            let mut version = version.clone();
-            version.patch += 1;
-            version
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use dialoguer::{Confirm, Input, MultiSelect, Password, Select, theme::{ColorfulTheme, SimpleTheme, Theme}, console::{self, Term, style}};
+use armory_lib::{say, warning, output::Level, ArmoryError, ArmoryTOML, Bump, Change, ColorChoice, Hook, ManifestEdit, Member, PublishOptions, PublishState, ReleaseSnapshot, Scheme};
+use semver::Version;
+use serde_json::json;
+
+mod completions;
+mod dashboard;
+mod graph;
+mod interrupt;
+mod progress;
+mod report;
+mod summary;
+
+use completions::Shell;
+use graph::GraphFormat;
+use report::Output;
+
+#[derive(Parser)]
+#[command(name = "cargo", bin_name = "cargo")]
+enum Cargo {
+    Armory(Armory),
+}
+
+/// Cargo workspaces tool
+#[derive(Args)]
+#[command(version)]
+struct Armory {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Apply the overrides of this `[profile.NAME]` from armory.toml
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    /// How to print the results
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: Output,
+    /// Go ahead without asking to confirm changes, publishes and rollbacks
+    #[arg(long, short, global = true)]
+    yes: bool,
+    /// Print every publish step with timestamps, or with -vv the commands
+    /// run along the way and cargo's verbose output too
+    #[arg(long, short, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Only print warnings and errors, leaving out progress and cargo's
+    /// output
+    #[arg(long, short, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Run as if started in this directory, which can be anywhere in the
+    /// workspace
+    #[arg(short = 'C', global = true, value_name = "DIR")]
+    directory: Option<PathBuf>,
+    /// The Cargo.toml of the workspace or of one of its members
+    #[arg(long, global = true, value_name = "PATH", conflicts_with = "directory")]
+    manifest_path: Option<PathBuf>,
+    /// Print without colors, whatever the `color` setting says
+    #[arg(long, global = true)]
+    no_color: bool,
+    /// Log everything the command does to this file, instead of a new one
+    /// in .armory/logs for the commands that publish
+    #[arg(long, global = true, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Bump the version and publish the workspace (the default)
+    Release(ReleaseArgs),
+    /// Bump the version and rewrite the manifests without publishing
+    Bump(BumpArgs),
+    /// Bump the version on a release branch and open or update a GitHub
+    /// pull request for it, to publish once it's merged
+    ReleasePr(BumpArgs),
+    /// Publish every member at the version in its manifest, without
+    /// editing anything
+    Publish(PublishArgs),
+    /// Package every member into target/package in publish order, without
+    /// uploading anything
+    Package(PackageArgs),
+    /// Package and verify every member in publish order against its local
+    /// dependencies, published or not, and report the ones that would
+    /// fail to publish, e.g. in pull request CI
+    Verify(VerifyArgs),
+    /// Publish the crates an interrupted release or publish didn't get to
+    Resume(ResumeArgs),
+    /// Undo an unfinished release: restore the manifests, drop its commit
+    /// and tags, and optionally yank what was published
+    Rollback(RollbackArgs),
+    /// Show the order crates would be published in, without changing
+    /// anything
+    Plan,
+    /// Run every pre-flight check of a release without changing anything,
+    /// e.g. in pull request CI
+    Check(CheckArgs),
+    /// Print the graph of the crates published and their dependencies, as
+    /// DOT or Mermaid
+    Graph(GraphArgs),
+    /// Explain what a crate has to wait for in the publish order
+    Why(WhyArgs),
+    /// Add a crate to the workspace, set up to be released with the rest
+    NewMember(NewMemberArgs),
+    /// Show each member's version and the state of the last release
+    Status,
+    /// List the members that changed since their last release tag, or
+    /// depend on one that did
+    Changed,
+    /// Print the changelog sections the next release would add, or write
+    /// them, without bumping or publishing anything
+    Changelog(ChangelogArgs),
+    /// Yank a version of some or all members from their registries,
+    /// dependents first
+    Yank(YankArgs),
+    /// List the owners of some or all members on their registries, or
+    /// invite and remove owners on all of them at once
+    Owners(OwnersArgs),
+    /// Ask for a token for each registry armory publishes to, or the ones
+    /// named, and store it in cargo's credential store
+    Login(LoginArgs),
+    /// List the releases recorded in armory.toml
+    History,
+    /// Print the settings in effect, after merging armory.toml with the
+    /// selected profile, your own config and the environment, and where
+    /// each one came from
+    Config,
+    /// Create GitHub releases for the tags of the last recorded release
+    GithubRelease(ForgeReleaseArgs),
+    /// Create GitLab releases for the tags of the last recorded release
+    GitlabRelease(ForgeReleaseArgs),
+    /// Write an armory.toml equivalent to another release tool's config
+    Migrate(MigrateArgs),
+    /// Write an armory.toml for the workspace as it is
+    Init(InitArgs),
+    /// Print a script completing armory's commands and flags in a shell
+    Completions(CompletionsArgs),
+}
+
+#[derive(Args, Default)]
+struct BumpArgs {
+    /// Pick the bump from the conventional commits since the last release
+    /// tag instead of asking
+    #[arg(long, group = "version")]
+    auto: bool,
+    /// Bump the patch version without asking
+    #[arg(long, group = "version")]
+    patch: bool,
+    /// Bump the minor version without asking
+    #[arg(long, group = "version")]
+    minor: bool,
+    /// Bump the major version without asking
+    #[arg(long, group = "version")]
+    major: bool,
+    /// Release at this version without asking
+    #[arg(long, value_name = "VERSION", group = "version")]
+    set_version: Option<Version>,
+    /// Run cargo-semver-checks first and suggest the bump it requires
+    #[arg(long)]
+    semver_checks: bool,
+    /// Build metadata for the released versions; `{sha}` and `{short-sha}`
+    /// expand to the current commit and an empty value clears it
+    #[arg(long, value_name = "TEMPLATE")]
+    build_metadata: Option<String>,
+    /// Bump from the highest versions published on the registry instead of
+    /// the ones in armory.toml
+    #[arg(long)]
+    from_registry: bool,
+    /// Check that the workspace builds with the rust-version from
+    /// armory.toml after bumping
+    #[arg(long)]
+    verify_rust_version: bool,
+    /// Only release crates that changed since the last release tag, or
+    /// depend on one that did
+    #[arg(long)]
+    changed_only: bool,
+    /// Add a section for the release to each crate's CHANGELOG.md
+    #[arg(long)]
+    changelog: bool,
+    /// Commit the manifests and armory.toml once done
+    #[arg(long)]
+    commit: bool,
+    /// Allow major bumps when armory.toml has require-flag-for-major
+    #[arg(long)]
+    allow_major: bool,
+}
+
+#[derive(Args, Default)]
+struct ReleaseArgs {
+    #[command(flatten)]
+    bump: BumpArgs,
+    /// Build each crate from its packaged sources before uploading it
+    #[arg(long)]
+    verify: bool,
+    /// Build every crate's docs before publishing any, failing on rustdoc
+    /// warnings such as broken intra-doc links
+    #[arg(long)]
+    check_docs: bool,
+    /// Publish even with uncommitted changes in the workspace
+    #[arg(long)]
+    allow_dirty: bool,
+    #[command(flatten)]
+    upstream: UpstreamArgs,
+    /// Once everything is published, build a new project depending on
+    /// the released crates from the registry
+    #[arg(long)]
+    smoke_test: bool,
+    /// Tag the commit released from once everything is published
+    #[arg(long)]
+    tag: bool,
+    /// Push the release commit and tags once they're created
+    #[arg(long)]
+    push: bool,
+    /// Create a GitHub release for each tag once it's pushed
+    #[arg(long)]
+    github_release: bool,
+    /// Create a GitLab release for each tag once it's pushed
+    #[arg(long)]
+    gitlab_release: bool,
+    /// Target triple to verify crates for, instead of the ones in
+    /// armory.toml
+    #[arg(long = "target", value_name = "TRIPLE")]
+    targets: Vec<String>,
+    /// Keep publishing crates that don't depend on a failed one, and
+    /// report the failures at the end
+    #[arg(long)]
+    keep_going: bool,
+    /// Stop after each crate is published and ask before publishing the
+    /// next one
+    #[arg(long)]
+    step: bool,
+    /// Show the publish full screen, with the publish order, each crate's
+    /// status and the latest output, instead of a progress bar
+    #[arg(long, conflicts_with = "step")]
+    dashboard: bool,
+    #[command(flatten)]
+    timeouts: TimeoutArgs,
+    #[command(flatten)]
+    token: TokenArgs,
+}
+
+#[derive(Args)]
+struct PublishArgs {
+    /// Package every crate in publish order without uploading anything
+    #[arg(long)]
+    dry_run: bool,
+    /// Only publish crates that changed since the last release tag, or
+    /// depend on one that did
+    #[arg(long)]
+    changed_only: bool,
+    /// Only publish if HEAD is a merged release pull request, and quietly
+    /// do nothing otherwise
+    #[arg(long)]
+    from_merged_pr: bool,
+    /// Only publish these crates, and the local dependencies they need
+    #[arg(long, value_delimiter = ',', value_name = "CRATES")]
+    only: Vec<String>,
+    /// Leave these crates out
+    #[arg(long, value_delimiter = ',', value_name = "CRATES")]
+    exclude: Vec<String>,
+    /// Build each crate from its packaged sources before uploading it
+    #[arg(long)]
+    verify: bool,
+    /// Build every crate's docs before publishing any, failing on rustdoc
+    /// warnings such as broken intra-doc links
+    #[arg(long)]
+    check_docs: bool,
+    /// Publish even with uncommitted changes in the workspace
+    #[arg(long)]
+    allow_dirty: bool,
+    #[command(flatten)]
+    upstream: UpstreamArgs,
+    /// Once everything is published, build a new project depending on
+    /// the released crates from the registry
+    #[arg(long)]
+    smoke_test: bool,
+    /// Tag the commit released from once everything is published
+    #[arg(long)]
+    tag: bool,
+    /// Push the release commit and tags once they're created
+    #[arg(long)]
+    push: bool,
+    /// Create a GitHub release for each tag once it's pushed
+    #[arg(long)]
+    github_release: bool,
+    /// Create a GitLab release for each tag once it's pushed
+    #[arg(long)]
+    gitlab_release: bool,
+    /// Commit the manifests and armory.toml once done
+    #[arg(long)]
+    commit: bool,
+    /// Target triple to verify crates for, instead of the ones in
+    /// armory.toml
+    #[arg(long = "target", value_name = "TRIPLE")]
+    targets: Vec<String>,
+    /// Keep publishing crates that don't depend on a failed one, and
+    /// report the failures at the end
+    #[arg(long)]
+    keep_going: bool,
+    /// Stop after each crate is published and ask before publishing the
+    /// next one
+    #[arg(long)]
+    step: bool,
+    /// Show the publish full screen, with the publish order, each crate's
+    /// status and the latest output, instead of a progress bar
+    #[arg(long, conflicts_with = "step")]
+    dashboard: bool,
+    #[command(flatten)]
+    timeouts: TimeoutArgs,
+    #[command(flatten)]
+    token: TokenArgs,
+}
+
+#[derive(Args)]
+struct PackageArgs {
+    /// Only package these crates, and the local dependencies they need
+    #[arg(long, value_delimiter = ',', value_name = "CRATES")]
+    only: Vec<String>,
+    /// Leave these crates out
+    #[arg(long, value_delimiter = ',', value_name = "CRATES")]
+    exclude: Vec<String>,
+    /// Build each crate from its packaged sources, which needs its local
+    /// dependencies on the registry already
+    #[arg(long)]
+    verify: bool,
+    /// Package even with uncommitted changes in the workspace
+    #[arg(long)]
+    allow_dirty: bool,
+    /// Target triple to verify crates for, instead of the ones in
+    /// armory.toml
+    #[arg(long = "target", value_name = "TRIPLE")]
+    targets: Vec<String>,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    /// Only verify these crates, and the local dependencies they need
+    #[arg(long, value_delimiter = ',', value_name = "CRATES")]
+    only: Vec<String>,
+    /// Leave these crates out
+    #[arg(long, value_delimiter = ',', value_name = "CRATES")]
+    exclude: Vec<String>,
+    /// Target triple to verify crates for, instead of the ones in
+    /// armory.toml
+    #[arg(long = "target", value_name = "TRIPLE")]
+    targets: Vec<String>,
+}
+
+#[derive(Args)]
+struct ResumeArgs {
+    /// Keep publishing crates that don't depend on a failed one, and
+    /// report the failures at the end
+    #[arg(long)]
+    keep_going: bool,
+    /// Stop after each crate is published and ask before publishing the
+    /// next one
+    #[arg(long)]
+    step: bool,
+    /// Show the publish full screen, with the publish order, each crate's
+    /// status and the latest output, instead of a progress bar
+    #[arg(long, conflicts_with = "step")]
+    dashboard: bool,
+    /// Tag the commit released from once everything is published
+    #[arg(long)]
+    tag: bool,
+    /// Push the release commit and tags once they're created
+    #[arg(long)]
+    push: bool,
+    /// Create a GitHub release for each tag once it's pushed
+    #[arg(long)]
+    github_release: bool,
+    /// Create a GitLab release for each tag once it's pushed
+    #[arg(long)]
+    gitlab_release: bool,
+    /// Commit the manifests and armory.toml once done
+    #[arg(long)]
+    commit: bool,
+    #[command(flatten)]
+    timeouts: TimeoutArgs,
+    #[command(flatten)]
+    token: TokenArgs,
+}
+
+#[derive(Args)]
+struct ForgeReleaseArgs {
+    /// Print the releases that would be created without calling GitHub
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct InitArgs {
+    /// Version each crate on its own, even if they're all at the same
+    /// version
+    #[arg(long)]
+    independent: bool,
+    /// Replace the workspace's armory config if it has one
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args)]
+struct ChangelogArgs {
+    /// Head the sections "Unreleased" rather than with the version the
+    /// commits call for
+    #[arg(long)]
+    unreleased: bool,
+    /// Cover the commits since this tag rather than since each crate's
+    /// last release
+    #[arg(long, value_name = "TAG")]
+    since: Option<String>,
+    /// Add the sections to the changelogs instead of printing them
+    #[arg(long)]
+    write: bool,
+}
+
+#[derive(Args)]
+struct CheckArgs {
+    /// Don't fail because of uncommitted changes
+    #[arg(long)]
+    allow_dirty: bool,
+    #[command(flatten)]
+    token: TokenArgs,
+}
+
+#[derive(Args)]
+struct GraphArgs {
+    /// The format to print the graph in
+    #[arg(long, value_enum, default_value_t)]
+    format: GraphFormat,
+    /// Leave out dependencies from outside the workspace
+    #[arg(long)]
+    local_only: bool,
+}
+
+#[derive(Args)]
+struct WhyArgs {
+    /// The crate whose place in the publish order to explain
+    #[arg(value_name = "CRATE")]
+    name: String,
+}
+
+#[derive(Args)]
+struct NewMemberArgs {
+    /// Where to create the crate, relative to the workspace root
+    path: String,
+    /// The crate's name, the last part of its path by default
+    #[arg(long)]
+    name: Option<String>,
+    /// Create a binary rather than a library
+    #[arg(long)]
+    bin: bool,
+    /// A directory to copy the crate from, instead of armory.toml's
+    /// member-template or a bare crate
+    #[arg(long, value_name = "DIR")]
+    template: Option<PathBuf>,
+    /// A member the crate depends on, which can be given more than once
+    #[arg(long = "dep", value_name = "CRATE")]
+    deps: Vec<String>,
+}
+
+#[derive(Args)]
+struct CompletionsArgs {
+    /// The shell to complete in
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+#[derive(Args)]
+struct MigrateArgs {
+    /// The tool to migrate from
+    #[arg(long, value_enum)]
+    from: MigrateFrom,
+    /// Replace the workspace's armory config if it has one
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MigrateFrom {
+    /// release.toml files and `[package.metadata.release]` tables
+    CargoRelease,
+}
+
+#[derive(Args)]
+struct RollbackArgs {
+    /// Yank the crates that were published
+    #[arg(long)]
+    yank: bool,
+    #[command(flatten)]
+    token: TokenArgs,
+}
+
+#[derive(Args)]
+struct YankArgs {
+    /// The version to yank
+    version: Version,
+    /// The crates to yank it from
+    #[arg(required_unless_present = "all")]
+    crates: Vec<String>,
+    /// Yank it from every member
+    #[arg(long, conflicts_with = "crates")]
+    all: bool,
+    /// Un-yank the version instead
+    #[arg(long)]
+    undo: bool,
+    #[command(flatten)]
+    token: TokenArgs,
+}
+
+#[derive(Args)]
+struct LoginArgs {
+    /// Only log in to these registries, crates.io being `crates-io`
+    #[arg(long = "registry", value_delimiter = ',', value_name = "NAMES")]
+    registries: Vec<String>,
+}
+
+#[derive(Args)]
+struct OwnersArgs {
+    /// The crates to list or change the owners of, every member armory
+    /// publishes if none are given
+    crates: Vec<String>,
+    /// Invite a user, or a team like github:org:team, to own the crates
+    #[arg(long, value_name = "LOGIN")]
+    add: Vec<String>,
+    /// Remove a user or team from the crates' owners
+    #[arg(long, value_name = "LOGIN")]
+    remove: Vec<String>,
+    #[command(flatten)]
+    token: TokenArgs,
+}
+
+#[derive(Args, Default)]
+struct UpstreamArgs {
+    /// Publish even with local commits that aren't pushed upstream
+    #[arg(long)]
+    allow_unpushed: bool,
+    /// Publish even when the upstream branch has commits that aren't
+    /// checked out
+    #[arg(long)]
+    allow_behind: bool,
+}
+
+#[derive(Args, Default)]
+struct TimeoutArgs {
+    /// Seconds a single upload may take before it's retried, overriding
+    /// armory.toml
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<u64>,
+    /// Seconds the whole publish may take before stopping, leaving the
+    /// rest for `cargo armory resume`, overriding armory.toml
+    #[arg(long, value_name = "SECS")]
+    deadline: Option<u64>,
+}
+
+#[derive(Args, Default)]
+struct TokenArgs {
+    /// The registry token to publish with, overriding armory.toml and
+    /// `cargo login` [env: ARMORY_TOKEN]
+    #[arg(long)]
+    token: Option<String>,
+}
+
+impl TokenArgs {
+    fn token(&self) -> Option<String> {
+        self.token.clone().or_else(|| std::env::var("ARMORY_TOKEN").ok())
+    }
+}
+
+fn bump_options(version: &Version, scheme: Scheme, channel: Option<&str>) -> Vec<(String, Version)> {
+    scheme.bumps(version, channel)
+        .into_iter()
+        .map(|bump| {
+            let version = bump.apply_on(version, channel).unwrap_or_else(|e| exit_with_error(&report::term(), e));
+            (format!("{} ({})", bump, version), version)
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Asks the user for the next version, either one of the standard bumps
+/// or a custom one, preselecting `suggested` if given. Returns `None` when
+/// `skippable` and the user skips.
+fn select_version(
+    theme: &dyn Theme,
+    prompt: String,
+    current: &Version,
+    scheme: Scheme,
+    channel: Option<&str>,
+    skippable: bool,
+    suggested: Option<Bump>,
+) -> Result<Option<Version>, std::io::Error> {
+    let mut items = Vec::new();
+    if skippable {
+        items.push((format!("Skip ({})", current), None));
+    }
+    items.extend(bump_options(current, scheme, channel).into_iter().map(|(s, v)| (s, Some(v))));
+    items.push(("Custom".to_string(), None));
+
+    let default = suggested
+        .and_then(|bump| scheme.bumps(current, channel).iter().position(|b| *b == bump))
+        .map(|index| index + skippable as usize)
+        .unwrap_or(0);
+
+    let selected = Select::with_theme(theme)
+        .with_prompt(prompt)
+        .items(&items.iter().map(|t| &t.0).collect::<Vec<_>>())
+        .default(default)
+        .interact()?;
+
+    if skippable && selected == 0 {
+        return Ok(None);
+    }
+    if let Some(version) = &items[selected].1 {
+        return Ok(Some(version.clone()));
+    }
+
+    let custom: String = Input::with_theme(theme)
+        .with_prompt("Version")
+        .validate_with(|input: &String| -> Result<(), String> {
+            let version = input.trim().parse::<Version>().map_err(|e| e.to_string())?;
+            if version <= *current {
+                return Err(format!("Version must be greater than {}", current));
+            }
+            Ok(())
+        })
+        .interact_text()?;
+
+    Ok(Some(custom.trim().parse().unwrap()))
+}
+
+/// Asks which of `versions` to release, all of them to begin with, and
+/// drops the rest. The local dependencies of the crates picked can't be
+/// left out, so they're put back if they were.
+fn select_crates_to_release(
+    cwd: &Path,
+    theme: &dyn Theme,
+    versions: &mut HashMap<String, Version>,
+) -> Result<(), std::io::Error> {
+    let graph = loaded(armory_lib::dependency_graph(cwd));
+    let names: Vec<String> = armory_lib::publish_order(&graph)
+        .into_iter()
+        .filter(|name| versions.contains_key(name))
+        .collect();
+    let items: Vec<String> = names.iter().map(|name| format!("{} {}", name, versions[name])).collect();
+    let picked: HashSet<String> = MultiSelect::with_theme(theme)
+        .with_prompt("Select the crates to release")
+        .items(&items)
+        .defaults(&vec![true; items.len()])
+        .interact()?
+        .into_iter()
+        .map(|index| names[index].clone())
+        .collect();
+
+    let needed = armory_lib::with_dependencies(&graph, &picked);
+    for name in names.iter().filter(|name| needed.contains(*name) && !picked.contains(*name)) {
+        let mut dependents: Vec<&str> = picked
+            .iter()
+            .filter(|dependent| {
+                armory_lib::with_dependencies(&graph, &HashSet::from([dependent.to_string()])).contains(name)
+            })
+            .map(|dependent| dependent.as_str())
+            .collect();
+        dependents.sort();
+        say!("{}: released anyway, needed by {}", name, dependents.join(", "));
+    }
+    versions.retain(|name, _| needed.contains(name));
+    Ok(())
+}
+
+/// Picks the next version from the conventional commits since `since`,
+/// touching only `path` if one is given. Returns `None` if no release is
+/// needed.
+fn auto_version(
+    cwd: &Path,
+    name: &str,
+    since: Option<&str>,
+    current: &Version,
+    scheme: Scheme,
+    channel: Option<&str>,
+    path: Option<&Path>,
+) -> Option<Version> {
+    let bump = armory_lib::conventional_bump(cwd, since, current, path)
+        .unwrap_or_else(|e| exit_with_error(&report::term(), format!("{}: {}", name, e)));
+    // calendar versions don't encode the kind of change, any release will do
+    let bump = match scheme {
+        Scheme::Semver => bump,
+        Scheme::Calver => bump.map(|_| Bump::Calendar),
+    };
+    match bump {
+        Some(bump) => {
+            let version = bump.apply_on(current, channel).unwrap_or_else(|e| exit_with_error(&report::term(), e));
+            say!("{}: {} bump from commits ({} -> {})", name, bump, current, version);
+            Some(version)
+        }
+        None => {
+            say!("{}: no releasable commits since the last release", name);
+            None
+        }
+    }
+}
+
+/// The version each of `changed` would be released at for the changelog,
+/// going by the conventional commits since its last release or `--since`,
+/// or `None` with `--unreleased` or when the commits don't call for one.
+fn changelog_versions(
+    cwd: &Path,
+    armory_toml: &ArmoryTOML,
+    changed: &[Member],
+    args: &ChangelogArgs,
+) -> HashMap<String, Option<Version>> {
+    let next = |name: Option<&str>, current: &Version, path: Option<&Path>| {
+        if args.unreleased {
+            return None;
+        }
+        let since = args.since.clone().or_else(|| armory_toml.last_release_tag(cwd, name));
+        let bump = armory_lib::conventional_bump(cwd, since.as_deref(), current, path)
+            .unwrap_or_else(|e| exit_with_error(&report::term(), e))?;
+        let bump = match armory_toml.scheme {
+            Scheme::Semver => bump,
+            Scheme::Calver => Bump::Calendar,
+        };
+        let version = bump.apply_on(current, armory_toml.pre_release());
+        Some(version.unwrap_or_else(|e| exit_with_error(&report::term(), e)))
+    };
+    match armory_toml.independent {
+        true => changed
+            .iter()
+            .map(|member| {
+                let path = Path::new(&member.path);
+                (member.name.clone(), next(Some(&member.name), armory_toml.version_of(&member.name), Some(path)))
+            })
+            .collect(),
+        false => {
+            let version = next(None, &armory_toml.version, None);
+            changed.iter().map(|member| (member.name.clone(), version.clone())).collect()
+        }
+    }
+}
+
+/// Runs cargo-semver-checks on every member, returning the bump each
+/// one requires. Members that couldn't be checked are left out.
+fn semver_check_members(cwd: &Path, armory_toml: &ArmoryTOML, members: &[Member]) -> HashMap<String, Bump> {
+    let mut required = HashMap::new();
+    if !armory_lib::semver_checks::is_installed() {
+        say!("cargo-semver-checks is not installed, skipping semver checks");
+        return required;
+    }
+
+    for member in members {
+        let version = armory_toml.version_of(&member.name);
+        match armory_lib::semver_checks::required_bump(cwd, &member.name, version) {
+            Ok(bump) => {
+                say!("{}: semver checks require a {} bump", member.name, bump);
+                required.insert(member.name.clone(), bump);
+            }
+            Err(e) => say!("{}: {}", member.name, e),
+        }
+    }
+    required
+}
+
+impl BumpArgs {
+    /// Whether the version is picked from the menu rather than by a flag.
+    fn asks(&self) -> bool {
+        !(self.auto || self.patch || self.minor || self.major || self.set_version.is_some())
+    }
+
+    /// The version the flags pick for a crate at `current`, if one was
+    /// given, or why it can't be released at it.
+    fn version_from_flags(&self, current: &Version, scheme: Scheme, channel: Option<&str>) -> Result<Option<Version>, String> {
+        if let Some(version) = &self.set_version {
+            if version <= current {
+                return Err(format!("--set-version {} isn't greater than the current version {}", version, current));
+            }
+            return Ok(Some(version.clone()));
+        }
+        let bump = match (self.patch, self.minor, self.major) {
+            (true, _, _) => Bump::Patch,
+            (_, true, _) => Bump::Minor,
+            (_, _, true) => Bump::Major,
+            _ => return Ok(None),
+        };
+        if scheme == Scheme::Calver {
+            return Err(format!("calendar versions are bumped by date, not with --{}", bump.to_string().to_lowercase()));
+        }
+        bump.apply_on(current, channel).map(Some).map_err(|e| e.to_string())
+    }
+}
+
+/// The version the bump flags of `args` pick for `name`, exiting if it
+/// can't be released at it.
+fn flagged_version(args: &BumpArgs, name: &str, current: &Version, scheme: Scheme, channel: Option<&str>) -> Option<Version> {
+    let version = args
+        .version_from_flags(current, scheme, channel)
+        .unwrap_or_else(|e| exit_with_error(&report::term(), format!("{}: {}", name, e)))?;
+    say!("{}: {} -> {}", name, current, version);
+    Some(version)
+}
+
+/// Exits before anything changes if `selected` is a major bump that
+/// armory.toml doesn't allow without `--allow-major`.
+fn check_major_bump(armory_toml: &ArmoryTOML, name: &str, current: &Version, selected: &Version, args: &BumpArgs) {
+    if let Err(e) = armory_toml.check_major_bump(name, current, selected, args.allow_major) {
+        exit_with(&report::term(), ExitCode::Preflight, e);
+    }
+}
+
+/// Picks the new versions, records them in `armory_toml` and returns the
+/// crates to release. Returns `None` if there's nothing to release.
+fn select_versions(
+    cwd: &Path,
+    armory_toml: &mut ArmoryTOML,
+    args: &BumpArgs,
+) -> Result<Option<HashMap<String, Version>>, std::io::Error> {
+    if args.asks() && !interactive() {
+        exit_with_error(
+            &report::term(),
+            "there's no terminal to pick the version on, pass --patch, --minor, --major, --set-version or --auto",
+        );
+    }
+    let theme = theme();
+
+    let build = args.build_metadata.as_ref()
+        .or(armory_toml.build_metadata.as_ref())
+        .map(|template| {
+            armory_lib::build_metadata(cwd, template).unwrap_or_else(|e| exit_with_error(&report::term(), e))
+        });
+
+    let mut members = loaded(armory_toml.members(cwd));
+    if args.changed_only {
+        let changed = armory_toml.changes(cwd).unwrap_or_else(|e| exit_with_error(&report::term(), e));
+        members.retain(|member| {
+            if !changed.contains_key(&member.name) {
+                say!("{}: unchanged since the last release, skipping", member.name);
+            }
+            changed.contains_key(&member.name)
+        });
+        if members.is_empty() {
+            say!("Nothing changed since the last release");
+            return Ok(None);
+        }
+    }
+    if args.from_registry || armory_toml.version_from_registry {
+        let drifted = armory_toml
+            .sync_from_registry(&members)
+            .unwrap_or_else(|e| exit_with(&report::term(), ExitCode::Preflight, e));
+        for (name, recorded, published) in drifted {
+            warning!(
+                "{} {}: armory.toml has {} but the registry has {}, bumping from {}",
+                style("warning:").yellow(), name, recorded, published, published
+            );
+        }
+    }
+    let pinned = armory_toml.changed_overrides(&members);
+    for (name, version) in &pinned {
+        say!("{}: pinned to {}", name, version);
+    }
+
+    let required = if args.asks() && (args.semver_checks || armory_toml.semver_checks) {
+        semver_check_members(cwd, armory_toml, &members)
+    } else {
+        HashMap::new()
+    };
+
+    if armory_toml.independent {
+        let mut versions = pinned;
+
+        for member in members {
+            if armory_toml.overrides.contains_key(&member.name) {
+                continue;
+            }
+            let version = armory_toml.version_of(&member.name).clone();
+
+            let flagged = flagged_version(args, &member.name, &version, armory_toml.scheme, armory_toml.pre_release());
+            let selected = if flagged.is_some() {
+                flagged
+            } else if args.auto {
+                let since = armory_toml.last_release_tag(cwd, Some(&member.name));
+                auto_version(cwd, &member.name, since.as_deref(), &version, armory_toml.scheme, armory_toml.pre_release(), Some(Path::new(&member.path)))
+            } else {
+                let prompt = format!("Select a release type for {}. Current version: {}", member.name, version);
+                select_version(&*theme, prompt, &version, armory_toml.scheme, armory_toml.pre_release(), true, required.get(&member.name).copied())?
+            };
+
+            if let Some(mut selected) = selected {
+                check_major_bump(armory_toml, &member.name, &version, &selected, args);
+                if let Some(build) = &build {
+                    selected.build = build.clone();
+                }
+                versions.insert(member.name, selected);
+            }
+        }
+
+        if args.asks() && versions.len() > 1 {
+            select_crates_to_release(cwd, &*theme, &mut versions)?;
+        }
+        if versions.is_empty() {
+            say!("No crates selected for release");
+            return Ok(None);
+        }
+
+        for (name, version) in &versions {
+            say!("You selected: {} {}", name, version);
+            if !armory_toml.overrides.contains_key(name) {
+                armory_toml.versions.insert(name.clone(), version.clone());
+            }
+        }
+
+        report::versions(&versions);
+        Ok(Some(versions))
+    } else {
+        let version = &armory_toml.version;
+
+        let flagged = flagged_version(args, "workspace", version, armory_toml.scheme, armory_toml.pre_release());
+        let mut selected = if let Some(flagged) = flagged {
+            flagged
+        } else if args.auto {
+            let since = armory_toml.last_release_tag(cwd, None);
+            match auto_version(cwd, "workspace", since.as_deref(), version, armory_toml.scheme, armory_toml.pre_release(), None) {
+                Some(selected) => selected,
+                None => return Ok(None),
+            }
+        } else {
+            let prompt = format!("Select a release type. Current version: {}", version);
+            let suggested = required.values().copied().reduce(Bump::max);
+            select_version(&*theme, prompt, version, armory_toml.scheme, armory_toml.pre_release(), false, suggested)?.unwrap()
+        };
+        check_major_bump(armory_toml, "workspace", version, &selected, args);
+        if let Some(build) = build {
+            selected.build = build;
+        }
+
+        say!("You selected: {}", selected);
+
+        armory_toml.version = selected.clone();
+
+        let mut versions = pinned;
+        for member in members {
+            if !armory_toml.overrides.contains_key(&member.name) {
+                versions.insert(member.name, selected.clone());
+            }
+        }
+        if args.asks() && versions.len() > 1 {
+            select_crates_to_release(cwd, &*theme, &mut versions)?;
+            if versions.is_empty() {
+                say!("No crates selected for release");
+                return Ok(None);
+            }
+        }
+        report::versions(&versions);
+        Ok(Some(versions))
+    }
+}
+
+/// Shows the manifest edits for `versions`, followed by what's about to be
+/// published when `publishing`, and once confirmed writes them along with
+/// armory.toml. Returns what was written, or `None` if the user backed out.
+fn apply_bump(
+    cwd: &Path,
+    armory_toml: &ArmoryTOML,
+    versions: &HashMap<String, Version>,
+    args: &BumpArgs,
+    publishing: bool,
+) -> Result<Option<Vec<ManifestEdit>>, std::io::Error> {
+    let mut edits = armory_lib::plan_bump(cwd, armory_toml, versions)
+        .unwrap_or_else(|e| exit_with(&report::term(), ExitCode::Config, e));
+    if args.changelog || armory_toml.changelogs {
+        match armory_lib::plan_changelogs(cwd, armory_toml, versions) {
+            Ok(changelogs) => edits.extend(changelogs),
+            Err(e) => exit_with_error(&report::term(), e),
+        }
+    }
+
+    print_edits(cwd, &edits);
+    if publishing {
+        print_publish_summary(cwd, armory_toml, versions);
+    }
+    if armory_toml.user.confirm && (publishing || !edits.is_empty()) {
+        let prompt = if publishing { "Apply these changes and publish?" } else { "Apply these changes?" };
+        if !confirm(&report::term(), prompt, true)? {
+            return Ok(None);
+        }
+    }
+
+    if let Err(e) = armory_toml.run_hook(cwd, Hook::PreBump, versions, None) {
+        exit_with_error(&report::term(), e);
+    }
+    if let Err(e) = armory_toml.add_edit(cwd, &mut edits).and_then(|()| armory_lib::apply_edits(&edits)) {
+        exit_with_error(&report::term(), e);
+    }
+    if let Err(e) = armory_toml.run_hook(cwd, Hook::PostBump, versions, None) {
+        abort_bump(&report::term(), &edits, ExitCode::Failure, e);
+    }
+    Ok(Some(edits))
+}
+
+/// Prints the diff of each of `edits`.
+fn print_edits(cwd: &Path, edits: &[ManifestEdit]) {
+    for edit in edits {
+        for line in edit.diff(cwd).lines() {
+            if line.starts_with("+++") || line.starts_with("---") {
+                say!("{}", style(line).bold());
+            } else if line.starts_with('+') {
+                say!("{}", style(line).green());
+            } else if line.starts_with('-') {
+                say!("{}", style(line).red());
+            } else if line.starts_with("@@") {
+                say!("{}", style(line).cyan());
+            } else {
+                say!("{}", line);
+            }
+        }
+    }
+}
+
+/// Puts back the files `edits` changed, then exits with `code` and
+/// `message`.
+fn abort_bump(term: &Term, edits: &[ManifestEdit], code: ExitCode, message: impl std::fmt::Display) -> ! {
+    match armory_lib::revert_edits(edits) {
+        Ok(()) => exit_with(term, code, format!("{}\nthe manifests were restored", message)),
+        Err(e) => exit_with(term, code, format!("{}\nfailed to restore the manifests: {}", message, e)),
+    }
+}
+
+/// Exits if armory.toml refuses the workspace's path-only
+/// dev-dependencies.
+fn check_dev_dependencies(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML) {
+    if let Err(e) = armory_lib::check_dev_dependencies(cwd, armory_toml) {
+        exit_with(term, ExitCode::Preflight, e);
+    }
+}
+
+/// Exits before anything changes if some of `crates` would go to a
+/// registry that `registries.allowed` doesn't list.
+fn check_registries(term: &Term, armory_toml: &ArmoryTOML, crates: &HashSet<String>) {
+    if let Err(e) = armory_toml.check_registries_allowed(crates) {
+        exit_with(term, ExitCode::Preflight, e);
+    }
+}
+
+/// Exits before anything changes if the token may not publish some of
+/// `crates`. Being unable to check is only a warning.
+fn check_ownership(term: &Term, armory_toml: &ArmoryTOML, crates: &HashSet<String>, token: Option<&str>) -> std::io::Result<()> {
+    match armory_lib::check_ownership(armory_toml, crates, token) {
+        Ok(rejected) if rejected.is_empty() => Ok(()),
+        Ok(rejected) => {
+            let mut message = "the token can't publish these crates:".to_string();
+            for (name, reason) in rejected {
+                message.push_str(&format!("\n    {}: {}", name, reason));
+            }
+            exit_with(term, ExitCode::Preflight, message);
+        }
+        Err(e) => term.write_line(&format!("{} couldn't check crate ownership: {}", style("warning:").yellow(), e)),
+    }
+}
+
+fn verify_rust_version(cwd: &Path, armory_toml: &ArmoryTOML, args: &BumpArgs) -> Result<(), ArmoryError> {
+    if !(args.verify_rust_version || armory_toml.verify_rust_version) {
+        return Ok(());
+    }
+    match &armory_toml.rust_version {
+        Some(rust_version) => armory_lib::verify_rust_version(cwd, rust_version),
+        None => {
+            warning!("{} no rust-version in armory.toml to verify", style("warning:").yellow());
+            Ok(())
+        }
+    }
+}
+
+/// Exits if the workspace has uncommitted changes, unless they're allowed.
+fn check_clean(term: &Term, cwd: &Path, allow_dirty: bool) {
+    if allow_dirty {
+        return;
+    }
+    let dirty = armory_lib::git::dirty_files(cwd).unwrap_or_else(|e| exit_with(term, ExitCode::Preflight, e));
+    if !dirty.is_empty() {
+        exit_with(term, ExitCode::Preflight, format!(
+            "the workspace has uncommitted changes, commit them or pass --allow-dirty:\n{}",
+            dirty.join("\n")
+        ));
+    }
+}
+
+/// Exits if armory.toml doesn't allow releasing from the current branch.
+fn check_branch(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML) {
+    let branch = armory_lib::git::current_branch(cwd);
+    if !armory_toml.allows_branch(branch.as_deref()) {
+        exit_with(term, ExitCode::Preflight, format!(
+            "releases can only be published from {}, not {}",
+            armory_toml.release_branches.join(", "),
+            branch.as_deref().unwrap_or("a detached HEAD")
+        ));
+    }
+}
+
+/// Exits if the current branch has commits that aren't pushed upstream, or
+/// is missing commits from upstream, unless that's allowed. Upstream is
+/// fetched first.
+fn check_upstream(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML, args: &UpstreamArgs) {
+    let allow_unpushed = args.allow_unpushed || armory_toml.allow_unpushed;
+    let allow_behind = args.allow_behind || armory_toml.allow_behind;
+    if allow_unpushed && allow_behind {
+        return;
+    }
+    let warn = |message: String| {
+        let _ = term.write_line(&format!("{} {}", style("warning:").yellow(), message));
+    };
+
+    let Some(upstream) = armory_lib::git::upstream(cwd) else {
+        warn("the current branch has no upstream to compare it with".to_string());
+        return;
+    };
+    if let Some((remote, _)) = upstream.split_once('/') {
+        if let Err(e) = armory_lib::git::fetch(cwd, remote) {
+            warn(format!("couldn't fetch {}, comparing with what's known of it: {}", remote, e));
+        }
+    }
+
+    let (ahead, behind) = armory_lib::git::ahead_behind(cwd, &upstream).unwrap_or_else(|e| exit_with(term, ExitCode::Preflight, e));
+    if ahead > 0 && !allow_unpushed {
+        exit_with(term, ExitCode::Preflight, format!(
+            "{} commit(s) aren't pushed to {}, push them or pass --allow-unpushed", ahead, upstream
+        ));
+    }
+    if behind > 0 && !allow_behind {
+        exit_with(term, ExitCode::Preflight, format!(
+            "{} has {} commit(s) that aren't checked out, pull them or pass --allow-behind", upstream, behind
+        ));
+    }
+}
+
+/// Whether there's someone at a terminal to answer prompts. When stdout
+/// isn't a terminal either, as in CI logs, armory doesn't ask even if it
+/// could, unless stdout is taken by `--output json`.
+fn interactive() -> bool {
+    Term::stderr().is_term() && std::io::stdin().is_terminal() && (Term::stdout().is_term() || report::is_json())
+}
+
+/// The look of prompts, without colors when they're turned off.
+fn theme() -> Box<dyn Theme> {
+    match console::colors_enabled_stderr() {
+        true => Box::new(ColorfulTheme::default()),
+        false => Box::new(SimpleTheme),
+    }
+}
+
+/// Asks `prompt`, exiting if there's no terminal to ask on rather than
+/// failing with dialoguer's bare "not a terminal", or waiting for an answer
+/// that never comes.
+fn confirm(term: &Term, prompt: &str, default: bool) -> std::io::Result<bool> {
+    if !interactive() {
+        exit_with_error(term, "there's no terminal to confirm on, pass --yes to go ahead without asking");
+    }
+    Confirm::with_theme(&*theme())
+        .with_prompt(prompt)
+        .default(default)
+        .interact()
+}
+
+/// Prints what publishing `versions` will do: the version, then each crate
+/// in publish order with the registries it goes to.
+fn print_publish_summary(cwd: &Path, armory_toml: &ArmoryTOML, versions: &HashMap<String, Version>) {
+    let graph = loaded(armory_lib::publish_graph(cwd, armory_toml));
+    let order: Vec<String> = armory_lib::publish_order(&graph)
+        .into_iter()
+        .filter(|name| versions.contains_key(name))
+        .collect();
+
+    match armory_toml.independent {
+        true => say!("{}", style(format!("About to publish {} crate(s)", order.len())).bold()),
+        false => say!("{}", style(format!("About to publish {} crate(s) at {}", order.len(), armory_toml.version)).bold()),
+    }
+    let width = order.iter().map(|name| name.len()).max().unwrap_or(0);
+    let version_width = order.iter().map(|name| versions[name].to_string().len()).max().unwrap_or(0);
+    for (index, name) in order.iter().enumerate() {
+        let registries: Vec<&str> = std::iter::once(armory_toml.registry_for(name))
+            .chain(armory_toml.mirrors_for(name))
+            .map(|registry| registry.unwrap_or("crates-io"))
+            .collect();
+        say!(
+            "{:>4}. {:<width$}  {:<version_width$}  {}",
+            index + 1,
+            name,
+            versions[name].to_string(),
+            style(format!("-> {}", registries.join(", "))).dim(),
+            width = width,
+            version_width = version_width
+        );
+    }
+
+    let mut skipped: Vec<&str> = versions.keys().map(|name| name.as_str()).filter(|name| !graph.contains_key(*name)).collect();
+    if !skipped.is_empty() {
+        skipped.sort();
+        say!("{} {}", style("Not published:").bold(), skipped.join(", "));
+    }
+}
+
+/// Asks whether to go on to `next` now that `published` is out, for
+/// `--step`.
+fn confirm_step(published: &str, next: &str) -> bool {
+    Confirm::with_theme(&*theme())
+        .with_prompt(format!("{} is published, publish {} next?", published, next))
+        .default(true)
+        .interact()
+        .unwrap_or(false)
+}
+
+/// The options `release`, `publish` and `resume` share, showing the
+/// publish's progress with `progress` unless it's stepped through.
+fn publish_options(
+    token: Option<String>,
+    keep_going: bool,
+    timeouts: &TimeoutArgs,
+    step: bool,
+    progress: impl FnOnce() -> Option<fn(&str, armory_lib::Progress)>,
+) -> PublishOptions {
+    let mut options = PublishOptions::new().keep_going(keep_going).interrupted(interrupt::catch()).abort(interrupt::forced);
+    if let Some(token) = token {
+        options = options.token(token);
+    }
+    if let Some(timeout) = timeouts.timeout {
+        options = options.timeout(Duration::from_secs(timeout));
+    }
+    if let Some(deadline) = timeouts.deadline {
+        options = options.deadline(Duration::from_secs(deadline));
+    }
+    if step {
+        options = options.step(confirm_step);
+    } else if let Some(progress) = progress() {
+        options = options.progress(progress);
+    }
+    options
+}
+
+/// Prints the owners of each crate on each of its registries, as
+/// [`armory_lib::crate_owners`] found them.
+fn print_owners(owners: &armory_lib::CrateOwners) {
+    if report::is_json() {
+        report::set("owners", owners);
+        return;
+    }
+    let width = owners.keys().map(|name| name.len()).chain(["CRATE".len()]).max().unwrap_or(0);
+    let registry_width = owners.values().flat_map(|registries| registries.keys()).map(|registry| registry.len())
+        .chain(["REGISTRY".len()])
+        .max()
+        .unwrap_or(0);
+    println!("{}", style(format!("{:<width$}  {:<registry_width$}  OWNERS", "CRATE", "REGISTRY")).dim());
+    for (name, registries) in owners {
+        for (registry, owners) in registries {
+            let owners = match owners {
+                Some(owners) => owners.iter().map(|owner| owner.login.as_str()).collect::<Vec<_>>().join(", "),
+                None => style("never published").dim().to_string(),
+            };
+            println!("{:<width$}  {:<registry_width$}  {}", name, registry, owners);
+        }
+    }
+}
+
+/// Exits before anything happens if `--step` is given without a terminal
+/// to ask on.
+fn check_step(term: &Term, step: bool) {
+    if step && !interactive() {
+        exit_with_error(term, "there's no terminal to step through the publish on, leave out --step");
+    }
+}
+
+/// Exits if a tag the release of `versions` would create exists already,
+/// locally or on the push remote, which usually means the version was
+/// released already. Being unable to reach the remote is only a warning.
+fn check_tags(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML, versions: &HashMap<String, Version>, enabled: bool) {
+    if !(enabled || armory_toml.create_tags) {
+        return;
+    }
+    let tags: Vec<String> = armory_toml.release_tags(versions).into_keys().collect();
+    let existing: Vec<_> = tags.iter().filter(|tag| armory_lib::git::tag_exists(cwd, tag)).cloned().collect();
+    if !existing.is_empty() {
+        exit_with(term, ExitCode::Preflight, format!(
+            "the release would create tags that exist already: {}\n\
+             if they're left from an aborted release, delete them with `git tag --delete`, otherwise bump again",
+            existing.join(", ")
+        ));
+    }
+
+    let remote = armory_toml.push_remote.as_deref().unwrap_or("origin");
+    if armory_lib::git::remote_url(cwd, remote).is_none() {
+        return;
+    }
+    match armory_lib::git::remote_tags(cwd, remote, &tags) {
+        Ok(existing) if existing.is_empty() => {}
+        Ok(existing) => exit_with(term, ExitCode::Preflight, format!(
+            "the release would create tags that exist on {} already: {}\n\
+             someone may have released this version from another machine, pull and bump again",
+            remote,
+            existing.join(", ")
+        )),
+        Err(e) => {
+            let _ = term.write_line(&format!("{} couldn't check the tags on {}: {}", style("warning:").yellow(), remote, e));
+        }
+    }
+}
+
+/// Runs the pre-flight checks of a release on every member armory would
+/// publish, all of them rather than stopping at the first failure, and
+/// exits if any fails. Nothing is changed.
+fn run_checks(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML, args: &CheckArgs) {
+    let graph = loaded(armory_lib::publish_graph(cwd, armory_toml));
+    let crates: HashSet<String> = graph.keys().cloned().collect();
+    let token = args.token.token();
+    let listed = |header: &str, lines: Vec<String>| match lines.is_empty() {
+        true => Ok(()),
+        false => Err(format!("{}\n    {}", header, lines.join("\n    "))),
+    };
+
+    let checks: Vec<(&str, Result<(), String>)> = vec![
+        ("clean tree", match args.allow_dirty || armory_toml.allow_dirty {
+            true => Ok(()),
+            false => armory_lib::git::dirty_files(cwd)
+                .map_err(|e| e.to_string())
+                .and_then(|dirty| listed("uncommitted changes:", dirty)),
         }),
-        ("Minor", {
-            let mut version = version.clone();
-            version.minor += 1;
-            version.patch = 0;
-            version
+        ("leftover state", match PublishState::load(cwd) {
+            Ok(None) => Ok(()),
+            Ok(Some(_)) => Err(format!("a previous publish didn't finish, see {}", PublishState::path(cwd).display())),
+            Err(e) => Err(e.to_string()),
         }),
-        ("Major", {
-            let mut version = version.clone();
-            version.major += 1;
-            version.minor = 0;
-            version.patch = 0;
-            version
+        ("credentials", armory_lib::missing_credentials(armory_toml, &crates, token.as_deref())
+            .map_err(|e| e.to_string())
+            .and_then(|missing| listed("no token to publish to:", missing))),
+        ("ownership", armory_lib::check_ownership(armory_toml, &crates, token.as_deref())
+            .map_err(|e| e.to_string())
+            .and_then(|rejected| {
+                let rejected = rejected.into_iter().map(|(name, reason)| format!("{}: {}", name, reason)).collect();
+                listed("the token can't publish these crates:", rejected)
+            })),
+        ("publish flags", listed(
+            "these crates depend on members that aren't published:",
+            loaded(armory_lib::unpublishable_dependencies(cwd, armory_toml))
+                .into_iter()
+                .map(|(member, dep)| format!("{} -> {}", member, dep))
+                .collect(),
+        )),
+        ("metadata", listed(
+            "these crates lack metadata crates.io requires:",
+            loaded(armory_lib::missing_metadata(cwd, armory_toml))
+                .into_iter()
+                .map(|(name, fields)| format!("{}: {}", name, fields.join(", ")))
+                .collect(),
+        )),
+        ("dependency cycles", match armory_lib::dependency_cycle(&graph) {
+            Some(cycle) => Err(format!("these crates depend on each other: {}", cycle.join(" -> "))),
+            None => Ok(()),
+        }),
+        ("dev-dependencies", armory_lib::check_dev_dependencies(cwd, armory_toml).map_err(|e| e.to_string())),
+        ("allowed registries", armory_toml.check_registries_allowed(&crates).map_err(|e| e.to_string())),
+    ];
+
+    let failed = checks.iter().filter(|(_, result)| result.is_err()).count();
+    if report::is_json() {
+        let checks: Vec<_> = checks
+            .iter()
+            .map(|(name, result)| json!({ "name": name, "passed": result.is_ok(), "error": result.as_ref().err() }))
+            .collect();
+        report::set("checks", checks);
+    } else {
+        for (name, result) in &checks {
+            match result {
+                Ok(()) => println!("{} {}", style("✔").green(), name),
+                Err(e) => println!("{} {}: {}", style("✘").red(), style(name).bold(), e.replace('\n', "\n  ")),
+            }
+        }
+    }
+    if failed > 0 {
+        exit_with(term, ExitCode::Preflight, format!("{} of {} pre-flight checks failed", failed, checks.len()));
+    }
+}
+
+/// Exits if armory.toml requires signed commits and tags but git can't
+/// sign them.
+fn check_signing(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML) {
+    if armory_toml.sign_git {
+        if let Err(e) = armory_lib::git::check_signing(cwd) {
+            exit_with(term, ExitCode::Preflight, e);
+        }
+    }
+}
+
+/// The optional steps of [`finish_release`] asked for on the command line,
+/// on top of the ones armory.toml enables.
+struct FinishSteps {
+    smoke_test: bool,
+    tag: bool,
+    commit: bool,
+    push: bool,
+    github_release: bool,
+    gitlab_release: bool,
+}
+
+/// Wraps up a published release: checksums and signs its packages,
+/// records it in armory.toml's history, smoke tests, commits, tags and
+/// pushes it if asked to, and runs the post-release hook.
+fn finish_release(
+    term: &Term,
+    cwd: &Path,
+    armory_toml: &mut ArmoryTOML,
+    versions: &HashMap<String, Version>,
+    steps: FinishSteps,
+) {
+    seal_release(term, cwd, armory_toml, versions);
+    let tagging = steps.tag || armory_toml.create_tags;
+    armory_toml.record_release(cwd, versions, tagging);
+    let smoke_test = run_smoke_test(term, cwd, armory_toml, versions, steps.smoke_test);
+    armory_lib::save_armory_toml(cwd, armory_toml).unwrap_or_else(|e| exit_with_error(term, e));
+
+    if steps.commit || armory_toml.create_commit {
+        match armory_lib::commit_release(cwd, armory_toml, versions) {
+            Ok(true) => say!("ARMORY: committed the release"),
+            Ok(false) => {}
+            Err(e) => exit_with_error(term, e),
+        }
+    }
+    // notes are written before tagging, while HEAD is the end of the range
+    let github_release = steps.github_release || armory_toml.github_releases;
+    let gitlab_release = steps.gitlab_release || armory_toml.gitlab_releases;
+    let notes = if github_release || gitlab_release {
+        release_notes(term, cwd, armory_toml, versions)
+    } else {
+        Vec::new()
+    };
+    let mut tags = Vec::new();
+    if tagging {
+        for tag in armory_toml.release_tags(versions).into_keys() {
+            if let Err(e) = armory_lib::git::create_tag(cwd, &tag, &format!("Release {}", tag), armory_toml.sign_git) {
+                exit_with_error(term, e);
+            }
+            say!("ARMORY: tagged {}", tag);
+            report::push("tags", &tag);
+            tags.push(tag);
+        }
+    }
+    if steps.push || armory_toml.push {
+        if let Err(e) = armory_lib::push_release(cwd, armory_toml, &tags) {
+            exit_with_error(term, e);
+        }
+    }
+    if github_release {
+        create_github_releases(term, cwd, armory_toml, &notes, false);
+    }
+    if gitlab_release {
+        create_gitlab_releases(term, cwd, armory_toml, &notes, false);
+    }
+    if let Err(e) = smoke_test {
+        exit_with_error(term, e);
+    }
+    if let Err(e) = armory_toml.run_hook(cwd, Hook::PostRelease, versions, None) {
+        exit_with_error(term, e);
+    }
+    ReleaseSnapshot::remove(cwd).unwrap_or_else(|e| exit_with_error(term, e));
+}
+
+/// The release notes for each of the tags of `versions`.
+fn release_notes(
+    term: &Term,
+    cwd: &Path,
+    armory_toml: &ArmoryTOML,
+    versions: &HashMap<String, Version>,
+) -> Vec<(String, String)> {
+    armory_toml.release_tags(versions)
+        .into_iter()
+        .map(|(tag, crate_name)| {
+            let notes = armory_lib::release_notes(cwd, armory_toml, &tag, crate_name.as_deref())
+                .unwrap_or_else(|e| exit_with_error(term, e));
+            (tag, notes)
         })
-    ]
+        .collect()
+}
+
+/// Bumps the version on the release pull request branch and commits it,
+/// then goes back to `base`. Returns the crates released, or `None` if
+/// there's nothing to release.
+fn commit_release_pr(
+    term: &Term,
+    cwd: &Path,
+    armory_toml: &mut ArmoryTOML,
+    args: &BumpArgs,
+    base: &str,
+) -> Result<Option<HashMap<String, Version>>, std::io::Error> {
+    let Some(versions) = select_versions(cwd, armory_toml, args)? else {
+        return Ok(None);
+    };
+    check_tags(term, cwd, armory_toml, &versions, true);
+
+    if let Err(e) = armory_lib::git::switch_new(cwd, armory_toml.release_pr_branch()) {
+        exit_with_error(term, e);
+    }
+    let versions = apply_bump(cwd, armory_toml, &versions, args, false)?.map(|_| versions);
+    if let Some(versions) = &versions {
+        if let Err(e) = armory_lib::commit_release(cwd, armory_toml, versions) {
+            exit_with_error(term, e);
+        }
+    }
+    if let Err(e) = armory_lib::git::switch(cwd, base) {
+        exit_with_error(term, e);
+    }
+    Ok(versions)
+}
+
+/// Opens a pull request from the release branch, already pushed, with the
+/// notes of every tag the release will create, or updates the one that's
+/// open already.
+fn open_release_pr(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML, versions: &HashMap<String, Version>, base: &str) {
+    let repo = armory_toml.github_repo(cwd).unwrap_or_else(|e| exit_with_error(term, e));
+    let Some(token) = armory_lib::github::token() else {
+        exit_with_error(term, "set GITHUB_TOKEN or GH_TOKEN to open release pull requests");
+    };
+    let branch = armory_toml.release_pr_branch();
+    let title = armory_toml.release_title(versions);
+    let body = release_notes(term, cwd, armory_toml, versions)
         .into_iter()
-        .map(|(s, v)| (format!("{} ({})", s, v), v))
-        .collect::<Vec<_>>();
+        .map(|(tag, notes)| format!("## {}\n\n{}", tag, notes))
+        .collect::<Vec<_>>()
+        .join("\n");
 
-    let selected = Select::with_theme(&theme)
-        .with_prompt(format!("Select a release type. Current version: {}", version))
-        .items(&items.iter().map(|t| &t.0).collect::<Vec<_>>())
-        .default(0)
-        .interact()?;
+    let pull = match armory_lib::github::find_pull_request(&repo, &token, branch) {
+        Ok(Some(pull)) => armory_lib::github::update_pull_request(&repo, &token, pull.number, &title, &body),
+        Ok(None) => armory_lib::github::open_pull_request(&repo, &token, branch, base, &title, &body),
+        Err(e) => Err(e),
+    };
+    match pull {
+        Ok(pull) => {
+            say!("ARMORY: release pull request {}", pull.html_url);
+            report::set("pull_request", &pull.html_url);
+        }
+        Err(e) => exit_with_error(term, e),
+    }
+}
 
-    let selected = &items[selected].1;
+/// Whether HEAD is a release pull request that was merged, according to
+/// GitHub.
+fn is_merged_release_pr(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML) -> bool {
+    let repo = armory_toml.github_repo(cwd).unwrap_or_else(|e| exit_with_error(term, e));
+    let Some(token) = armory_lib::github::token() else {
+        exit_with_error(term, "set GITHUB_TOKEN or GH_TOKEN to check for a merged release pull request");
+    };
+    let sha = armory_lib::git::head_sha(cwd).unwrap_or_else(|e| exit_with_error(term, e));
+    let pulls = armory_lib::github::commit_pull_requests(&repo, &token, &sha)
+        .unwrap_or_else(|e| exit_with_error(term, e));
+    match pulls.iter().find(|pull| pull.merged_at.is_some() && pull.head.branch == armory_toml.release_pr_branch()) {
+        Some(pull) => {
+            say!("ARMORY: publishing release pull request #{}", pull.number);
+            true
+        }
+        None => false,
+    }
+}
 
-    println!("You selected: {}", selected);
+/// Creates a GitHub release for each tag with its notes, or prints them in
+/// a dry run. The tags have to be pushed already.
+fn create_github_releases(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML, notes: &[(String, String)], dry_run: bool) {
+    let repo = armory_toml.github_repo(cwd).unwrap_or_else(|e| exit_with_error(term, e));
+    if dry_run {
+        for (tag, body) in notes {
+            say!("{}", style(format!("Release {} in {}", tag, repo)).bold());
+            say!("{}", body);
+            report::push("github_releases", json!({ "tag": tag, "notes": body }));
+        }
+        return;
+    }
 
-    armory_toml.version = selected.clone();
-    armory_lib::save_armory_toml(&cwd, &armory_toml);
+    let Some(token) = armory_lib::github::token() else {
+        exit_with_error(term, "set GITHUB_TOKEN or GH_TOKEN to create GitHub releases");
+    };
+    for (tag, body) in notes {
+        match armory_lib::github::create_release(&repo, &token, tag, tag, body) {
+            Ok(url) => {
+                say!("ARMORY: created the GitHub release {}", url);
+                report::push("github_releases", json!({ "tag": tag, "url": url }));
+            }
+            Err(e) => exit_with_error(term, e),
+        }
+    }
+}
 
-    armory_lib::publish_workspace(&cwd, selected);
+/// Creates a GitLab release for each tag with its notes, or prints them in
+/// a dry run. The tags have to be pushed already.
+fn create_gitlab_releases(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML, notes: &[(String, String)], dry_run: bool) {
+    let url = armory_toml.gitlab_url();
+    let project = armory_toml.gitlab_project(cwd).unwrap_or_else(|e| exit_with_error(term, e));
+    if dry_run {
+        for (tag, body) in notes {
+            say!("{}", style(format!("Release {} in {} on {}", tag, project, url)).bold());
+            say!("{}", body);
+            report::push("gitlab_releases", json!({ "tag": tag, "notes": body }));
+        }
+        return;
+    }
 
-    term.write_line(&format!("{} Done!", style("✔").green()))?;
+    let Some(token) = armory_lib::gitlab::token() else {
+        exit_with_error(term, "set GITLAB_TOKEN to create GitLab releases");
+    };
+    for (tag, body) in notes {
+        match armory_lib::gitlab::create_release(&url, &project, &token, tag, tag, body) {
+            Ok(url) => {
+                say!("ARMORY: created the GitLab release {}", url);
+                report::push("gitlab_releases", json!({ "tag": tag, "url": url }));
+            }
+            Err(e) => exit_with_error(term, e),
+        }
+    }
+}
+
+/// The latest version of each member on the registry it's published to,
+/// `None` for those not published yet. Members whose manifest doesn't
+/// allow publishing them are left out, and so are those whose registry
+/// couldn't be reached, with a warning.
+fn published_versions(armory_toml: &ArmoryTOML, members: &[Member]) -> HashMap<String, Option<Version>> {
+    let mut published = HashMap::new();
+    let mut unreachable = Vec::new();
+    for member in members {
+        let registry = armory_toml.registry_for(&member.name);
+        if !member.can_publish_to(registry) {
+            continue;
+        }
+        match armory_lib::registry::latest_version(&member.name, registry) {
+            Ok(version) => {
+                published.insert(member.name.clone(), version);
+            }
+            Err(e) => unreachable.push((member.name.as_str(), e)),
+        }
+    }
+    if let Some((_, e)) = unreachable.first() {
+        let names: Vec<&str> = unreachable.iter().map(|(name, _)| *name).collect();
+        warning!("{} couldn't look up {} on the registry: {}", style("warning:").yellow(), names.join(", "), e);
+    }
+    published
+}
+
+/// What `status` needs to know about releases: each member's published
+/// version, as [`published_versions`] gives them, and the members changed
+/// since their last release.
+fn release_status(
+    cwd: &Path,
+    armory_toml: &ArmoryTOML,
+    members: &[Member],
+) -> (HashMap<String, Option<Version>>, BTreeMap<String, Change>) {
+    let published = published_versions(armory_toml, members);
+    let changes = armory_toml.changes(cwd).unwrap_or_else(|e| {
+        warning!("{} couldn't tell what changed since the last release: {}", style("warning:").yellow(), e);
+        BTreeMap::new()
+    });
+    (published, changes)
+}
+
+/// Whether `member` has something to release: changes since its last
+/// release, or a version the registry doesn't have yet.
+fn needs_release(
+    armory_toml: &ArmoryTOML,
+    member: &Member,
+    published: &HashMap<String, Option<Version>>,
+    changes: &BTreeMap<String, Change>,
+) -> bool {
+    if armory_toml.is_excluded(member) {
+        return false;
+    }
+    let behind = match published.get(&member.name) {
+        Some(published) => published.as_ref() < Some(armory_toml.version_of(&member.name)),
+        None => false,
+    };
+    behind || changes.contains_key(&member.name)
+}
+
+/// Prints each member with the version armory has for it, then the last
+/// recorded release and anything left unfinished.
+fn print_status(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML) {
+    match armory_toml.independent {
+        true => println!("{}", style("Versioned independently").bold()),
+        false => println!("{} {}", style("Released together at").bold(), armory_toml.version),
+    }
+    let members = loaded(armory_lib::workspace_members(cwd));
+    let (published, changes) = release_status(cwd, armory_toml, &members);
+    let published_of = |member: &Member| match published.get(&member.name) {
+        Some(Some(version)) => version.to_string(),
+        Some(None) => "unpublished".to_string(),
+        None => "-".to_string(),
+    };
+    let width = members.iter().map(|member| member.name.len()).max().unwrap_or(0).max("CRATE".len());
+    let version_width = members
+        .iter()
+        .map(|member| armory_toml.version_of(&member.name).to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max("LOCAL".len());
+    let published_width = members.iter().map(|member| published_of(member).len()).max().unwrap_or(0);
+    let header = format!(
+        "    {:<width$}  {:<version_width$}  {}",
+        "CRATE",
+        "LOCAL",
+        "PUBLISHED",
+        width = width,
+        version_width = version_width
+    );
+    println!("{}", style(header).dim());
+    for member in &members {
+        let version = armory_toml.version_of(&member.name);
+        let mut notes = Vec::new();
+        if armory_toml.is_excluded(member) {
+            notes.push("excluded".to_string());
+        } else if armory_toml.overrides.contains_key(&member.name) {
+            notes.push("pinned".to_string());
+        }
+        if let Some(manifest) = member.version.as_ref().filter(|manifest| *manifest != version) {
+            notes.push(format!("manifest has {}", manifest));
+        }
+        if published.get(&member.name).is_some_and(|published| published.as_ref() > Some(version)) {
+            notes.push("behind the registry".to_string());
+        }
+        match changes.get(&member.name) {
+            Some(Change::Unreleased) => notes.push("never released".to_string()),
+            Some(Change::Files(_)) => notes.push("unreleased changes".to_string()),
+            Some(Change::Dependency(dep)) => notes.push(format!("depends on {}, which changed", dep)),
+            None => {}
+        }
+        let notes = match notes.is_empty() {
+            true => String::new(),
+            false => style(format!("({})", notes.join(", "))).dim().to_string(),
+        };
+        let line = format!(
+            "    {:<width$}  {:<version_width$}  {:<published_width$}  {}",
+            member.name,
+            version.to_string(),
+            published_of(member),
+            notes,
+            width = width,
+            version_width = version_width,
+            published_width = published_width
+        );
+        println!("{}", line.trim_end());
+    }
+    let needed: Vec<&str> = members
+        .iter()
+        .filter(|member| needs_release(armory_toml, member, &published, &changes))
+        .map(|member| member.name.as_str())
+        .collect();
+    match needed.is_empty() {
+        true => println!("{} nothing to release", style("Release:").bold()),
+        false => println!("{} needed for {}", style("Release:").bold(), needed.join(", ")),
+    }
+
+    println!();
+    match armory_toml.releases.last() {
+        Some(release) => {
+            let sha = release.sha.as_deref().map(|sha| &sha[..sha.len().min(7)]).unwrap_or("-");
+            println!("{} {} ({})", style("Last release:").bold(), release.date, sha);
+        }
+        None => println!("{} none recorded", style("Last release:").bold()),
+    }
+    if let Some(state) = PublishState::load(cwd).unwrap_or_else(|e| exit_with_error(term, e)) {
+        let pending: Vec<&str> = state.pending().map(|(name, _)| name.as_str()).collect();
+        println!(
+            "{} an unfinished publish still has {} to go, run `cargo armory resume`",
+            style("warning:").yellow(),
+            pending.join(", ")
+        );
+    }
+    if ReleaseSnapshot::load(cwd).unwrap_or_else(|e| exit_with_error(term, e)).is_some() {
+        println!(
+            "{} a release didn't finish, `cargo armory rollback` undoes it",
+            style("warning:").yellow()
+        );
+    }
+}
+
+/// Records what [`print_status`] prints in the JSON report.
+fn report_status(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML) {
+    let members = loaded(armory_lib::workspace_members(cwd));
+    let (published, changes) = release_status(cwd, armory_toml, &members);
+    let statuses: Vec<_> = members
+        .iter()
+        .map(|member| {
+            json!({
+                "name": member.name,
+                "version": armory_toml.version_of(&member.name).to_string(),
+                "manifest_version": member.version.as_ref().map(|version| version.to_string()),
+                "published_version": published.get(&member.name).cloned().flatten().map(|version| version.to_string()),
+                "unreleased_changes": changes.contains_key(&member.name),
+                "needs_release": needs_release(armory_toml, member, &published, &changes),
+                "excluded": armory_toml.is_excluded(member),
+                "pinned": armory_toml.overrides.contains_key(&member.name),
+            })
+        })
+        .collect();
+    report::set("independent", armory_toml.independent);
+    report::set("members", statuses);
+    report::set("last_release", armory_toml.releases.last());
+    let state = PublishState::load(cwd).unwrap_or_else(|e| exit_with_error(term, e));
+    report::set("unfinished_publish", state.map(|state| state.pending().map(|(name, _)| name.clone()).collect::<Vec<_>>()));
+    let snapshot = ReleaseSnapshot::load(cwd).unwrap_or_else(|e| exit_with_error(term, e));
+    report::set("unfinished_release", snapshot.is_some());
+}
+
+/// Prints `settings` as `key = value` lines, with where each one came from
+/// lined up after them.
+fn print_settings(settings: &[armory_lib::config::Setting]) {
+    let lines: Vec<String> = settings.iter().map(|setting| format!("{} = {}", setting.key, setting.value)).collect();
+    let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0).min(60);
+    for (line, setting) in lines.iter().zip(settings) {
+        let source = match &setting.via {
+            Some(via) => format!("# {}, {}", via, setting.source),
+            None => format!("# {}", setting.source),
+        };
+        println!("{:<width$}  {}", line, style(source).dim(), width = width);
+    }
+}
+
+/// `settings` as JSON objects, with the values as TOML.
+fn settings_json(settings: &[armory_lib::config::Setting]) -> Vec<serde_json::Value> {
+    settings
+        .iter()
+        .map(|setting| {
+            json!({
+                "key": setting.key,
+                "value": setting.value,
+                "source": setting.source.to_string(),
+                "via": setting.via,
+            })
+        })
+        .collect()
+}
+
+/// The notes for each tag of the last recorded release, which has to be
+/// tagged already.
+fn last_release_notes(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML) -> Vec<(String, String)> {
+    let Some(release) = armory_toml.releases.last() else {
+        exit_with_error(term, "no releases recorded yet");
+    };
+    let versions: HashMap<_, _> = release.crates.clone().into_iter().collect();
+    let missing: Vec<_> = armory_toml.release_tags(&versions)
+        .into_keys()
+        .filter(|tag| !armory_lib::git::tag_exists(cwd, tag))
+        .collect();
+    if !missing.is_empty() {
+        exit_with_error(term, format!("the release isn't tagged yet, missing {}", missing.join(", ")));
+    }
+    release_notes(term, cwd, armory_toml, &versions)
+}
+
+/// Smoke tests the release just recorded in armory.toml if asked to,
+/// noting the result in its history.
+fn run_smoke_test(
+    term: &Term,
+    cwd: &Path,
+    armory_toml: &mut ArmoryTOML,
+    versions: &HashMap<String, Version>,
+    enabled: bool,
+) -> Result<(), String> {
+    if !(enabled || armory_toml.smoke_test) {
+        return Ok(());
+    }
+    let _ = term.write_line(&format!("{}", style("Smoke testing the release").bold()));
+    let result = armory_lib::smoke_test(cwd, armory_toml, versions)
+        .map_err(|e| format!("the smoke test failed: {}", e));
+    if let Some(release) = armory_toml.releases.last_mut() {
+        release.smoke_test = Some(match &result {
+            Ok(()) => "passed".to_string(),
+            Err(e) => e.clone(),
+        });
+    }
+    result
+}
+
+/// Checksums and signs the packages cargo left behind while publishing
+/// `versions`. The release is out by then, so failing to is only a warning.
+fn seal_release(term: &Term, cwd: &Path, armory_toml: &ArmoryTOML, versions: &HashMap<String, Version>) {
+    let packages: Vec<_> = versions
+        .iter()
+        .map(|(name, version)| armory_lib::package_path(cwd, name, version))
+        .filter(|path| path.exists())
+        .collect();
+    if let Err(e) = armory_lib::seal_packages(cwd, armory_toml, &packages) {
+        let _ = term.write_line(&format!("{} failed to checksum or sign the packages: {}", style("warning:").yellow(), e));
+    }
+}
+
+/// The members `--only` and `--exclude` pick, with their versions. Crates
+/// picked with `--only` bring the local dependencies they need along.
+fn select_crates(
+    term: &Term,
+    cwd: &Path,
+    armory_toml: &ArmoryTOML,
+    only: &[String],
+    exclude: &[String],
+) -> HashMap<String, Version> {
+    armory_lib::select_members(cwd, armory_toml, only, exclude).unwrap_or_else(|e| exit_with_error(term, e))
+}
+
+/// Prints `message` as an error and exits with a failure status.
+fn exit_with_error(term: &Term, message: impl std::fmt::Display) -> ! {
+    exit_with(term, ExitCode::Failure, message)
+}
+
+/// What armory exits with when it fails, so CI can tell failures apart,
+/// e.g. to only retry partial publishes. The codes don't change between
+/// releases. Usage errors exit with 2, as with any clap program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitCode {
+    /// Anything not covered below.
+    Failure = 1,
+    /// The config or the workspace couldn't be loaded, or the config is
+    /// invalid.
+    Config = 3,
+    /// A check before publishing failed, and nothing was published.
+    Preflight = 4,
+    /// Some crates were published but not all, `resume` publishes the
+    /// rest.
+    PartialPublish = 5,
+    /// Publishing failed before any crate was published.
+    PublishFailed = 6,
+    /// Ctrl-C was pressed twice during a publish, or once outside of one.
+    Interrupted = 130,
+}
+
+/// What was read from the workspace, or exits with [`ExitCode::Config`]
+/// if it couldn't be, as when a member's manifest doesn't parse.
+fn loaded<T>(result: Result<T, ArmoryError>) -> T {
+    result.unwrap_or_else(|e| exit_with(&report::term(), ExitCode::Config, e))
+}
+
+/// [`exit_with_error`] with a particular exit `code`.
+fn exit_with(term: &Term, code: ExitCode, message: impl std::fmt::Display) -> ! {
+    let _ = term.write_line(&format!("{} {}", style("error:").red(), message));
+    armory_lib::output::log(&format!("error: {}", message));
+    if let Some(log_file) = armory_lib::output::log_file() {
+        let _ = term.write_line(&format!("{} {}", style("the log is in").dim(), log_file.display()));
+    }
+    report::set("exit_code", code as i32);
+    report::finish(Some(message.to_string()));
+    std::process::exit(code as i32);
+}
+
+/// How a failed publish exits: partially if the state it left behind has
+/// anything published.
+fn publish_failure(cwd: &Path) -> ExitCode {
+    match PublishState::load(cwd).ok().flatten() {
+        Some(state) if !state.published.is_empty() || !state.mirrored.is_empty() => ExitCode::PartialPublish,
+        _ => ExitCode::PublishFailed,
+    }
+}
+
+/// Exits if the workspace already has an armory config, unless it's to be
+/// replaced.
+fn check_no_config(term: &Term, cwd: &Path, force: bool) {
+    let path = armory_lib::config_path(cwd);
+    if path.exists() && !force {
+        exit_with_error(term, format!("{} already has an armory config, pass --force to replace it", path.display()));
+    }
+}
+
+/// Writes an armory config for the workspace, taking the versions from its
+/// manifests.
+fn init(term: &Term, cwd: &Path, args: &InitArgs) {
+    check_no_config(term, cwd, args.force);
+    let armory_toml = loaded(armory_lib::initial_config(cwd, args.independent));
+    armory_lib::save_armory_toml(cwd, &armory_toml).unwrap_or_else(|e| exit_with_error(term, e));
+    say!("ARMORY: wrote {}", armory_lib::config_path(cwd).display());
+    report::set("path", armory_lib::config_path(cwd));
+    if armory_toml.independent {
+        say!("Each of the {} crates is versioned on its own", armory_toml.versions.len());
+    } else {
+        say!("Every crate is released together at {}", armory_toml.version);
+    }
+}
+
+/// Writes the workspace's armory config from another tool's, warning
+/// about what couldn't be carried over.
+fn migrate(term: &Term, cwd: &Path, args: &MigrateArgs) {
+    check_no_config(term, cwd, args.force);
+    let migration = match args.from {
+        MigrateFrom::CargoRelease => armory_lib::migrate::from_cargo_release(cwd),
+    }
+    .unwrap_or_else(|e| exit_with_error(term, e));
+
+    armory_lib::save_armory_toml(cwd, &migration.armory_toml).unwrap_or_else(|e| exit_with_error(term, e));
+    say!("ARMORY: wrote {}", armory_lib::config_path(cwd).display());
+    report::set("path", armory_lib::config_path(cwd));
+    report::set("notes", &migration.notes);
+    for note in &migration.notes {
+        warning!("{} {}", style("warning:").yellow(), note);
+    }
+}
+
+/// The root of the workspace to work on, found from `-C`, `--manifest-path`
+/// or the current directory.
+fn workspace_dir(term: &Term, cli: &Armory) -> std::io::Result<PathBuf> {
+    let current_dir = std::env::current_dir()?;
+    let start = match (&cli.manifest_path, &cli.directory) {
+        (Some(manifest_path), _) => {
+            if manifest_path.file_name().is_none_or(|name| name != "Cargo.toml") {
+                exit_with(term, ExitCode::Config, "--manifest-path has to point to a Cargo.toml");
+            }
+            let manifest_path = current_dir.join(manifest_path);
+            if !manifest_path.is_file() {
+                exit_with(term, ExitCode::Config, format!("{} doesn't exist", manifest_path.display()));
+            }
+            manifest_path.parent().map(Path::to_path_buf).unwrap_or(current_dir)
+        }
+        (None, Some(directory)) => current_dir.join(directory),
+        (None, None) => current_dir,
+    };
+    Ok(armory_lib::workspace_root(&start).unwrap_or_else(|e| exit_with(term, ExitCode::Config, e)))
+}
+
+fn main() -> Result<(), std::io::Error> {
+    let matches = Cargo::command().get_matches();
+    let Cargo::Armory(cli) = Cargo::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let command = matches.subcommand().and_then(|(_, armory)| armory.subcommand_name()).unwrap_or("release");
+    armory_lib::output::set_level(match (cli.quiet, cli.verbose) {
+        (true, _) => Level::Quiet,
+        (false, 0) => Level::Normal,
+        (false, 1) => Level::Verbose,
+        (false, _) => Level::Trace,
+    });
+    report::start(command, cli.output);
+    let result = run(cli);
+    report::set("exit_code", if result.is_ok() { 0 } else { ExitCode::Failure as i32 });
+    report::finish(result.as_ref().err().map(|e| e.to_string()));
+    result
+}
+
+fn run(cli: Armory) -> Result<(), std::io::Error> {
+    let term = report::term();
+    // completions don't need a workspace
+    if let Some(Command::Completions(args)) = &cli.command {
+        completions::print(args.shell, Cargo::command());
+        return Ok(());
+    }
+    let cwd = workspace_dir(&term, &cli)?;
+
+    // these write the config, so there's none to load yet
+    match &cli.command {
+        Some(Command::Init(args)) => {
+            init(&term, &cwd, args);
+            return Ok(());
+        }
+        Some(Command::Migrate(args)) => {
+            migrate(&term, &cwd, args);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut armory_toml = armory_lib::load_armory_toml(&cwd).unwrap_or_else(|e| exit_with(&term, ExitCode::Config, e));
+    match armory_toml.user.color {
+        // https://no-color.org
+        ColorChoice::Auto if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) => {
+            console::set_colors_enabled(false);
+            console::set_colors_enabled_stderr(false);
+        }
+        ColorChoice::Auto => {}
+        choice => {
+            console::set_colors_enabled(choice == ColorChoice::Always);
+            console::set_colors_enabled_stderr(choice == ColorChoice::Always);
+        }
+    }
+    if cli.no_color {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    if let Some(profile) = &cli.profile {
+        if let Err(e) = armory_toml.use_profile(profile) {
+            exit_with(&term, ExitCode::Config, e);
+        }
+    }
+    if cli.yes {
+        armory_toml.user.confirm = false;
+    }
+    if let Some(channel) = armory_toml.pre_release() {
+        if channel.contains('.') || channel.parse::<semver::Prerelease>().is_err() {
+            exit_with(&term, ExitCode::Config, format!("pre-release channel {:?} isn't a single pre-release identifier", channel));
+        }
+    }
+    let command = cli.command.unwrap_or(Command::Release(ReleaseArgs::default()));
+
+    // a record of each publish to look back on when one goes wrong
+    let logged = matches!(command, Command::Release(_) | Command::Publish(_) | Command::Resume(_));
+    let default_log = || armory_lib::output::new_log(&cwd).unwrap_or_else(|e| exit_with_error(&term, e));
+    if let Some(log_file) = cli.log_file.or_else(|| logged.then(default_log)) {
+        let header = std::env::args().collect::<Vec<_>>().join(" ");
+        if let Err(e) = armory_lib::output::log_to(&log_file, &header) {
+            exit_with_error(&term, e);
+        }
+    }
+
+    let publishes = matches!(command, Command::Release(_) | Command::Publish(_));
+    if publishes && PublishState::load(&cwd).unwrap_or_else(|e| exit_with(&term, ExitCode::Preflight, e)).is_some() {
+        exit_with(&term, ExitCode::Preflight, format!(
+            "a previous publish didn't finish, run `cargo armory resume` or delete {}",
+            PublishState::path(&cwd).display()
+        ));
+    }
+
+    match command {
+        Command::Release(ReleaseArgs { bump: args, verify, check_docs, smoke_test, tag, push, github_release, gitlab_release, allow_dirty, upstream, targets, keep_going, step, dashboard, timeouts, token }) => {
+            check_step(&term, step);
+            check_branch(&term, &cwd, &armory_toml);
+            check_clean(&term, &cwd, allow_dirty || armory_toml.allow_dirty);
+            check_dev_dependencies(&term, &cwd, &armory_toml);
+            check_upstream(&term, &cwd, &armory_toml, &upstream);
+            let previous: HashMap<String, Version> = loaded(armory_toml.members(&cwd))
+                .into_iter()
+                .map(|member| (member.name.clone(), armory_toml.version_of(&member.name).clone()))
+                .collect();
+            if let Some(versions) = select_versions(&cwd, &mut armory_toml, &args)? {
+                let token = token.token();
+                check_registries(&term, &armory_toml, &versions.keys().cloned().collect());
+                check_ownership(&term, &armory_toml, &versions.keys().cloned().collect(), token.as_deref())?;
+                // a bump to a version that's tagged already is a mistake
+                // even when this release won't tag it
+                check_tags(&term, &cwd, &armory_toml, &versions, true);
+                check_signing(&term, &cwd, &armory_toml);
+                if check_docs || armory_toml.check_docs {
+                    if let Err(e) = armory_lib::check_docs(&cwd, &versions.keys().cloned().collect()) {
+                        exit_with(&term, ExitCode::Preflight, e);
+                    }
+                }
+                let Some(edits) = apply_bump(&cwd, &armory_toml, &versions, &args, true)? else {
+                    return Ok(());
+                };
+                if let Err(e) = verify_rust_version(&cwd, &armory_toml, &args) {
+                    abort_bump(&term, &edits, ExitCode::Preflight, e);
+                }
+                if let Err(e) = armory_lib::snapshot_release(&cwd, &armory_toml, &versions, &edits) {
+                    abort_bump(&term, &edits, ExitCode::Failure, e);
+                }
+                let options = publish_options(token, keep_going, &timeouts, step, || {
+                    progress::start(&cwd, &armory_toml, &versions, dashboard)
+                })
+                .verify(verify)
+                .targets(targets);
+                let published = armory_lib::publish_members(&cwd, &armory_toml, &versions.keys().cloned().collect(), &options);
+                progress::finish();
+                interrupt::release();
+                report::publish(&cwd, &armory_toml, &versions, published.as_ref().ok(), false);
+                summary::print(&cwd, &armory_toml, &previous, &versions, published.as_ref().ok(), false);
+                if let Err(e) = published {
+                    interrupt::exit_if_forced(&term, &e);
+                    // once something is out, the bump stays so the rest can
+                    // be published with resume
+                    // without the state there's no telling what was published,
+                    // so the bump stays
+                    let state = PublishState::load(&cwd).unwrap_or_else(|load_error| exit_with(&term, ExitCode::Config, format!(
+                        "{}\n{}\nthe manifests keep the bump in case some crates were published", e, load_error
+                    )));
+                    if state.is_none_or(|state| state.published.is_empty() && state.mirrored.is_empty()) {
+                        for removed in [PublishState::remove(&cwd), ReleaseSnapshot::remove(&cwd)] {
+                            if let Err(e) = removed {
+                                warning!("{} {}", style("warning:").yellow(), e);
+                            }
+                        }
+                        abort_bump(&term, &edits, ExitCode::PublishFailed, format!("{}\nnothing was published", e));
+                    }
+                    exit_with(&term, ExitCode::PartialPublish, format!(
+                        "{}\nrun `cargo armory resume` to publish the rest, or `cargo armory rollback` to undo the release",
+                        e
+                    ));
+                }
+                finish_release(&term, &cwd, &mut armory_toml, &versions, FinishSteps {
+                    smoke_test,
+                    tag,
+                    commit: args.commit,
+                    push,
+                    github_release,
+                    gitlab_release,
+                });
+            }
+        }
+        Command::Bump(args) => {
+            check_dev_dependencies(&term, &cwd, &armory_toml);
+            if let Some(versions) = select_versions(&cwd, &mut armory_toml, &args)? {
+                check_tags(&term, &cwd, &armory_toml, &versions, true);
+                let Some(edits) = apply_bump(&cwd, &armory_toml, &versions, &args, false)? else {
+                    return Ok(());
+                };
+                if let Err(e) = verify_rust_version(&cwd, &armory_toml, &args) {
+                    abort_bump(&term, &edits, ExitCode::Preflight, e);
+                }
+                if args.commit || armory_toml.create_commit {
+                    if let Err(e) = armory_lib::commit_release(&cwd, &armory_toml, &versions) {
+                        exit_with_error(&term, e);
+                    }
+                }
+            }
+        }
+        Command::ReleasePr(args) => {
+            check_clean(&term, &cwd, false);
+            check_branch(&term, &cwd, &armory_toml);
+            check_dev_dependencies(&term, &cwd, &armory_toml);
+            let Some(base) = armory_lib::git::current_branch(&cwd) else {
+                exit_with_error(&term, "release pull requests can't be opened from a detached HEAD");
+            };
+            let Some(versions) = commit_release_pr(&term, &cwd, &mut armory_toml, &args, &base)? else {
+                return Ok(());
+            };
+            let remote = armory_toml.push_remote.as_deref().unwrap_or("origin");
+            if let Err(e) = armory_lib::git::force_push(&cwd, remote, armory_toml.release_pr_branch()) {
+                exit_with_error(&term, e);
+            }
+            open_release_pr(&term, &cwd, &armory_toml, &versions, &base);
+        }
+        Command::Publish(args) => {
+            if args.from_merged_pr && !is_merged_release_pr(&term, &cwd, &armory_toml) {
+                say!("HEAD isn't a merged release pull request, nothing to publish");
+                return Ok(());
+            }
+            check_step(&term, args.step);
+            check_clean(&term, &cwd, args.allow_dirty || armory_toml.allow_dirty);
+            check_dev_dependencies(&term, &cwd, &armory_toml);
+            if !args.dry_run {
+                check_branch(&term, &cwd, &armory_toml);
+                check_upstream(&term, &cwd, &armory_toml, &args.upstream);
+            }
+            let mut versions = select_crates(&term, &cwd, &armory_toml, &args.only, &args.exclude);
+            if args.changed_only {
+                let changed = armory_toml.changes(&cwd).unwrap_or_else(|e| exit_with_error(&term, e));
+                versions.retain(|name, _| changed.contains_key(name));
+                if versions.is_empty() {
+                    say!("Nothing changed since the last release");
+                    return Ok(());
+                }
+            }
+            report::versions(&versions);
+            let token = args.token.token();
+            check_registries(&term, &armory_toml, &versions.keys().cloned().collect());
+            if !args.dry_run {
+                check_ownership(&term, &armory_toml, &versions.keys().cloned().collect(), token.as_deref())?;
+                check_tags(&term, &cwd, &armory_toml, &versions, args.tag);
+                check_signing(&term, &cwd, &armory_toml);
+            }
+            if args.check_docs || armory_toml.check_docs {
+                if let Err(e) = armory_lib::check_docs(&cwd, &versions.keys().cloned().collect()) {
+                    exit_with(&term, ExitCode::Preflight, e);
+                }
+            }
+            // with --from-merged-pr, merging the pull request was the
+            // confirmation
+            if !args.dry_run && !args.from_merged_pr && armory_toml.user.confirm {
+                print_publish_summary(&cwd, &armory_toml, &versions);
+                if !confirm(&term, "Publish these crates?", true)? {
+                    return Ok(());
+                }
+            }
+            let options = publish_options(token, args.keep_going, &args.timeouts, args.step, || {
+                progress::start(&cwd, &armory_toml, &versions, args.dashboard)
+            })
+            .dry_run(args.dry_run)
+            .verify(args.verify)
+            .targets(args.targets);
+            if !args.dry_run {
+                if let Err(e) = armory_lib::snapshot_release(&cwd, &armory_toml, &versions, &[]) {
+                    exit_with_error(&term, e);
+                }
+            }
+            let published = armory_lib::publish_members(&cwd, &armory_toml, &versions.keys().cloned().collect(), &options);
+            progress::finish();
+            interrupt::release();
+            report::publish(&cwd, &armory_toml, &versions, published.as_ref().ok(), args.dry_run);
+            let previous = summary::released_before(&armory_toml, &versions);
+            summary::print(&cwd, &armory_toml, &previous, &versions, published.as_ref().ok(), args.dry_run);
+            if let Err(e) = published {
+                interrupt::exit_if_forced(&term, &e);
+                let code = match args.dry_run {
+                    true => ExitCode::PublishFailed,
+                    false => publish_failure(&cwd),
+                };
+                exit_with(&term, code, e);
+            }
+            if !args.dry_run {
+                finish_release(&term, &cwd, &mut armory_toml, &versions, FinishSteps {
+                    smoke_test: args.smoke_test,
+                    tag: args.tag,
+                    commit: args.commit,
+                    push: args.push,
+                    github_release: args.github_release,
+                    gitlab_release: args.gitlab_release,
+                });
+            }
+        }
+        Command::Package(args) => {
+            check_clean(&term, &cwd, args.allow_dirty || armory_toml.allow_dirty);
+            let versions = select_crates(&term, &cwd, &armory_toml, &args.only, &args.exclude);
+            let options = PublishOptions::new().verify(args.verify).targets(args.targets);
+            let packaged = armory_lib::package_members(&cwd, &armory_toml, &versions.keys().cloned().collect(), &options)
+                .unwrap_or_else(|e| exit_with_error(&term, e));
+            let sealed = armory_lib::seal_packages(&cwd, &armory_toml, &packaged)
+                .unwrap_or_else(|e| exit_with_error(&term, e));
+            if report::is_json() {
+                report::set("packages", packaged.iter().chain(&sealed).collect::<Vec<_>>());
+                return Ok(());
+            }
+            println!("{}", style("Packaged").bold());
+            for path in packaged.iter().chain(&sealed) {
+                println!("  {}", path.display());
+            }
+        }
+        Command::Verify(args) => {
+            let versions = select_crates(&term, &cwd, &armory_toml, &args.only, &args.exclude);
+            let options = PublishOptions::new().targets(args.targets);
+            let crates = versions.keys().cloned().collect();
+            // each line already names the crate
+            let results: Vec<(String, Result<(), String>)> =
+                loaded(armory_lib::verify_members(&cwd, &armory_toml, &crates, &options))
+                    .into_iter()
+                    .map(|(name, result)| {
+                        let result = result.map_err(|e| match e {
+                            ArmoryError::Crate { message, .. } => message,
+                            e => e.to_string(),
+                        });
+                        (name, result)
+                    })
+                    .collect();
+            let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+            if report::is_json() {
+                let crates: Vec<_> = results
+                    .iter()
+                    .map(|(name, result)| json!({ "name": name, "passed": result.is_ok(), "error": result.as_ref().err() }))
+                    .collect();
+                report::set("crates", crates);
+            } else {
+                println!();
+                for (name, result) in &results {
+                    match result {
+                        Ok(()) => println!("{} {}", style("✔").green(), name),
+                        Err(e) => println!("{} {}: {}", style("✘").red(), style(name).bold(), e.replace('\n', "\n  ")),
+                    }
+                }
+            }
+            if failed > 0 {
+                exit_with_error(&term, format!("{} of {} crates would fail to publish", failed, results.len()));
+            }
+        }
+        Command::Resume(args) => {
+            check_step(&term, args.step);
+            let state = PublishState::load(&cwd).unwrap_or_else(|e| exit_with_error(&term, e));
+            let versions: HashMap<String, Version> = state.iter().flat_map(|state| state.crates.clone()).collect();
+            let pending: HashMap<String, Version> = state
+                .iter()
+                .flat_map(|state| state.pending().map(|(name, version)| (name.clone(), version.clone())))
+                .collect();
+            report::versions(&versions);
+            if armory_toml.user.confirm && !pending.is_empty() {
+                print_publish_summary(&cwd, &armory_toml, &pending);
+                if !confirm(&term, "Publish the rest?", true)? {
+                    return Ok(());
+                }
+            }
+            let options = publish_options(args.token.token(), args.keep_going, &args.timeouts, args.step, || {
+                progress::start(&cwd, &armory_toml, &pending, args.dashboard)
+            });
+            let resumed = armory_lib::resume(&cwd, &armory_toml, &options);
+            progress::finish();
+            interrupt::release();
+            report::publish(&cwd, &armory_toml, &versions, resumed.as_ref().ok(), false);
+            let previous = summary::released_before(&armory_toml, &versions);
+            summary::print(&cwd, &armory_toml, &previous, &versions, resumed.as_ref().ok(), false);
+            if let Err(e) = resumed {
+                interrupt::exit_if_forced(&term, &e);
+                exit_with(&term, publish_failure(&cwd), e);
+            }
+            finish_release(&term, &cwd, &mut armory_toml, &versions, FinishSteps {
+                smoke_test: false,
+                tag: args.tag,
+                commit: args.commit,
+                push: args.push,
+                github_release: args.github_release,
+                gitlab_release: args.gitlab_release,
+            });
+        }
+        Command::Plan => {
+            let graph = loaded(armory_lib::publish_graph(&cwd, &armory_toml));
+            let members = loaded(armory_lib::workspace_members(&cwd));
+            let version_of = |name: &str| {
+                members.iter()
+                    .find(|member| member.name == name)
+                    .and_then(|member| member.version.as_ref())
+                    .map(|version| version.to_string())
+                    .unwrap_or_default()
+            };
+
+            let order = armory_lib::publish_order(&graph);
+            let groups = armory_lib::publish_groups(&graph);
+            let mut skipped: Vec<&str> = members.iter()
+                .map(|member| member.name.as_str())
+                .filter(|name| !graph.contains_key(*name))
+                .collect();
+            skipped.sort();
+
+            if report::is_json() {
+                report::versions(&members.iter()
+                    .filter(|member| graph.contains_key(&member.name))
+                    .filter_map(|member| Some((member.name.clone(), member.version.clone()?)))
+                    .collect());
+                report::order(order);
+                report::set("groups", groups);
+                report::set("not_published", skipped);
+                return Ok(());
+            }
+
+            println!("{}", style("Publish order").bold());
+            for (index, name) in order.iter().enumerate() {
+                println!("{:>4}. {} {}", index + 1, name, version_of(name));
+            }
+
+            println!();
+            println!("{}", style("Groups that can be published in parallel").bold());
+            for (index, group) in groups.iter().enumerate() {
+                println!("{:>4}. {}", index + 1, group.join(", "));
+            }
+
+            if !skipped.is_empty() {
+                println!();
+                println!("{} {}", style("Not published:").bold(), skipped.join(", "));
+            }
+            return Ok(());
+        }
+        Command::Rollback(args) => {
+            let Some(snapshot) = ReleaseSnapshot::load(&cwd).unwrap_or_else(|e| exit_with_error(&term, e)) else {
+                exit_with_error(&term, "there's no unfinished release to roll back");
+            };
+            // without a publish state left, everything made it out
+            let published: Vec<(String, Version)> = match PublishState::load(&cwd).unwrap_or_else(|e| exit_with_error(&term, e)) {
+                Some(state) => state.crates.into_iter().filter(|(name, _)| state.published.contains(name)).collect(),
+                None => snapshot.crates.clone().into_iter().collect(),
+            };
+
+            say!("{}", style("Rolling back the release of").bold());
+            for (name, version) in &snapshot.crates {
+                say!("  {} {}", name, version);
+            }
+            if !published.is_empty() && !args.yank {
+                let names: Vec<_> = published.iter().map(|(name, version)| format!("{} {}", name, version)).collect();
+                warning!("{} {} stay published, pass --yank to yank them", style("warning:").yellow(), names.join(", "));
+            }
+            if armory_toml.user.confirm && !confirm(&term, "Roll back?", false)? {
+                return Ok(());
+            }
+
+            if args.yank {
+                let token = args.token.token();
+                for (name, version) in &published {
+                    if let Err(e) = armory_lib::yank_members(&cwd, &armory_toml, &HashSet::from([name.clone()]), version, false, token.as_deref()) {
+                        exit_with_error(&term, e);
+                    }
+                }
+            }
+            if let Err(e) = armory_lib::rollback(&cwd, &snapshot) {
+                exit_with_error(&term, e);
+            }
+        }
+        Command::Yank(args) => {
+            let crates = if args.all {
+                loaded(armory_toml.members(&cwd)).into_iter().map(|member| member.name).collect()
+            } else {
+                args.crates.into_iter().collect()
+            };
+            let token = args.token.token();
+            if let Err(e) = armory_lib::yank_members(&cwd, &armory_toml, &crates, &args.version, args.undo, token.as_deref()) {
+                exit_with_error(&term, e);
+            }
+        }
+        Command::Owners(args) => {
+            let members: HashSet<String> = loaded(armory_lib::publish_graph(&cwd, &armory_toml)).into_keys().collect();
+            if let Some(name) = args.crates.iter().find(|name| !members.contains(*name)) {
+                exit_with_error(&term, format!("{} isn't a member armory publishes", name));
+            }
+            let crates = match args.crates.is_empty() {
+                true => members,
+                false => args.crates.into_iter().collect(),
+            };
+            let token = args.token.token();
+            if args.add.is_empty() && args.remove.is_empty() {
+                let owners = armory_lib::crate_owners(&armory_toml, &crates, token.as_deref())
+                    .unwrap_or_else(|e| exit_with_error(&term, e));
+                print_owners(&owners);
+                return Ok(());
+            }
+            if armory_toml.user.confirm {
+                let mut changes = Vec::new();
+                if !args.add.is_empty() {
+                    changes.push(format!("inviting {}", args.add.join(", ")));
+                }
+                if !args.remove.is_empty() {
+                    changes.push(format!("removing {}", args.remove.join(", ")));
+                }
+                let prompt = format!("Change the owners of {} crate(s), {}?", crates.len(), changes.join(" and "));
+                if !confirm(&term, &prompt, true)? {
+                    return Ok(());
+                }
+            }
+            armory_lib::modify_owners(&armory_toml, &crates, &args.add, &args.remove, token.as_deref())
+                .unwrap_or_else(|e| exit_with_error(&term, e));
+        }
+        Command::Login(args) => {
+            let members: HashSet<String> = loaded(armory_lib::publish_graph(&cwd, &armory_toml)).into_keys().collect();
+            let configured = armory_toml.registries_for(&members);
+            let names = |registry: Option<&str>| registry.unwrap_or("crates-io").to_string();
+            let registries: Vec<Option<&str>> = match args.registries.is_empty() {
+                true => configured.iter().copied().collect(),
+                false => args.registries.iter().map(|name| Some(name.as_str()).filter(|name| *name != "crates-io")).collect(),
+            };
+            if let Some(registry) = registries.iter().find(|registry| !configured.contains(*registry)) {
+                exit_with_error(&term, format!("armory doesn't publish to {}", names(*registry)));
+            }
+            if !interactive() {
+                exit_with_error(&term, "there's no terminal to paste tokens on, run cargo login with the token on stdin instead");
+            }
+            let mut logged_in = Vec::new();
+            for registry in registries {
+                if let Ok(api) = armory_lib::registry::api_url(registry) {
+                    say!("Tokens for {} are made at {}/me", names(registry), api);
+                }
+                let token = Password::with_theme(&*theme())
+                    .with_prompt(format!("Token for {} (empty to skip)", names(registry)))
+                    .allow_empty_password(true)
+                    .interact()?;
+                if token.is_empty() {
+                    continue;
+                }
+                armory_lib::login(registry, &token).unwrap_or_else(|e| exit_with_error(&term, e));
+                logged_in.push(names(registry));
+            }
+            report::set("registries", &logged_in);
+        }
+        Command::Check(args) => {
+            run_checks(&term, &cwd, &armory_toml, &args);
+            return Ok(());
+        }
+        Command::Graph(args) => {
+            let graph = loaded(armory_lib::publish_graph(&cwd, &armory_toml));
+            let external = match args.local_only {
+                true => HashMap::new(),
+                false => loaded(armory_lib::external_dependencies(&cwd)),
+            };
+            if report::is_json() {
+                let sorted = |deps: &HashSet<String>| deps.iter().cloned().collect::<BTreeSet<_>>();
+                report::set("dependencies", graph.iter().map(|(name, deps)| (name, sorted(deps))).collect::<BTreeMap<_, _>>());
+                if !args.local_only {
+                    let external: BTreeMap<_, _> = external.iter().filter(|(name, _)| graph.contains_key(*name)).collect();
+                    report::set("external_dependencies", external);
+                }
+                return Ok(());
+            }
+            let labels = loaded(armory_lib::workspace_members(&cwd))
+                .into_iter()
+                .map(|member| {
+                    let label = match &member.version {
+                        Some(version) => format!("{} {}", member.name, version),
+                        None => member.name.clone(),
+                    };
+                    (member.name, label)
+                })
+                .collect();
+            println!("{}", graph::render(args.format, &graph, &labels, &external));
+            return Ok(());
+        }
+        Command::NewMember(args) => {
+            let path = args.path.trim().trim_end_matches('/');
+            let name = args.name.clone().unwrap_or_else(|| path.rsplit('/').next().unwrap_or(path).to_string());
+            let member = armory_lib::scaffold::NewMember {
+                name: name.clone(),
+                path: path.to_string(),
+                bin: args.bin,
+                template: args.template.clone().or_else(|| armory_toml.member_template.clone()),
+                dependencies: args.deps.clone(),
+            };
+            let edits =
+                armory_lib::scaffold::plan(&cwd, &armory_toml, &member).unwrap_or_else(|e| exit_with_error(&term, e));
+            print_edits(&cwd, &edits);
+            if armory_toml.user.confirm && !confirm(&term, &format!("Create {}?", name), true)? {
+                return Ok(());
+            }
+            if let Err(e) = armory_lib::apply_edits(&edits) {
+                exit_with_error(&term, e);
+            }
+            say!("ARMORY: created {} in {}", name, path);
+            report::set("crate", &name);
+            report::set("path", cwd.join(path));
+        }
+        Command::Why(args) => {
+            let graph = loaded(armory_lib::publish_graph(&cwd, &armory_toml));
+            if !graph.contains_key(&args.name) {
+                exit_with_error(&term, format!("{} isn't a member armory publishes", args.name));
+            }
+            if let Some(cycle) = armory_lib::dependency_cycle(&graph) {
+                exit_with_error(&term, format!("these crates depend on each other, so there's no order to explain: {}", cycle.join(" -> ")));
+            }
+            let order = armory_lib::publish_order(&graph);
+            let groups = armory_lib::publish_groups(&graph);
+            let position = order.iter().position(|name| *name == args.name).unwrap_or_default();
+            let group = groups.iter().position(|group| group.contains(&args.name)).unwrap_or_default();
+            let waits_for: BTreeSet<String> = armory_lib::with_dependencies(&graph, &HashSet::from([args.name.clone()]))
+                .into_iter()
+                .filter(|name| *name != args.name)
+                .collect();
+            let chains = armory_lib::publish_chains(&graph, &args.name);
+            // the links a publish-after makes rather than a dependency
+            let dependencies = loaded(armory_lib::dependency_graph(&cwd));
+            let after = |from: &str, to: &str| !dependencies.get(from).is_some_and(|deps| deps.contains(to));
+
+            if report::is_json() {
+                let chains: Vec<Vec<_>> = chains
+                    .iter()
+                    .map(|chain| {
+                        chain.windows(2)
+                            .map(|link| {
+                                json!({ "crate": link[0], "waits_for": link[1], "publish_after": after(&link[0], &link[1]) })
+                            })
+                            .collect()
+                    })
+                    .collect();
+                report::set("crate", &args.name);
+                report::set("position", position + 1);
+                report::set("group", group + 1);
+                report::set("waits_for", waits_for);
+                report::set("chains", chains);
+                return Ok(());
+            }
+
+            println!(
+                "{} is {} of {} in the publish order, in group {} of {}.",
+                style(&args.name).bold(),
+                position + 1,
+                order.len(),
+                group + 1,
+                groups.len()
+            );
+            if waits_for.is_empty() {
+                println!("It has no local dependencies to wait for; the crates before it only come first by name.");
+                return Ok(());
+            }
+            let count = match waits_for.len() {
+                1 => "1 crate".to_string(),
+                count => format!("{} crates", count),
+            };
+            println!("It waits for {}: {}", count, waits_for.iter().cloned().collect::<Vec<_>>().join(", "));
+            println!();
+            println!("{}", style("Dependencies putting it in its group").bold());
+            let (arrow, after_arrow) = match console::colors_enabled() {
+                true => ("→", "⇢"),
+                false => ("->", "~>"),
+            };
+            for chain in &chains {
+                let mut line = chain[0].clone();
+                for link in chain.windows(2) {
+                    let arrow = if after(&link[0], &link[1]) { after_arrow } else { arrow };
+                    line.push_str(&format!(" {} {}", arrow, link[1]));
+                }
+                println!("  {}", line);
+            }
+            let publish_after = chains.iter().any(|chain| chain.windows(2).any(|link| after(&link[0], &link[1])));
+            if publish_after {
+                println!();
+                println!("{} is a publish-after in armory.toml rather than a dependency.", after_arrow);
+            }
+            return Ok(());
+        }
+        Command::Changed => {
+            let changes = armory_toml.changes(&cwd).unwrap_or_else(|e| exit_with_error(&term, e));
+            let members = loaded(armory_toml.members(&cwd));
+            let changes: Vec<_> = changes
+                .into_iter()
+                .filter(|(name, _)| members.iter().any(|member| member.name == *name))
+                .collect();
+            if report::is_json() {
+                let changes: serde_json::Map<_, _> = changes
+                    .into_iter()
+                    .map(|(name, change)| {
+                        let change = match change {
+                            Change::Unreleased => json!({ "reason": "unreleased" }),
+                            Change::Files(files) => json!({ "reason": "files", "files": files }),
+                            Change::Dependency(dep) => json!({ "reason": "dependency", "dependency": dep }),
+                        };
+                        (name, change)
+                    })
+                    .collect();
+                report::set("changed", changes);
+                return Ok(());
+            }
+            if changes.is_empty() {
+                println!("Nothing changed since the last release");
+            }
+            for (name, change) in changes {
+                let reason = match change {
+                    Change::Unreleased => "never released".to_string(),
+                    Change::Files(files) if files.len() == 1 => "1 file changed".to_string(),
+                    Change::Files(files) => format!("{} files changed", files.len()),
+                    Change::Dependency(dep) => format!("depends on {}", dep),
+                };
+                println!("{} {}", style(name).bold(), reason);
+            }
+            return Ok(());
+        }
+        Command::Changelog(args) => {
+            let changes = match &args.since {
+                Some(since) => armory_lib::member_changes(&cwd, |_| Some(since.clone())),
+                None => armory_toml.changes(&cwd),
+            };
+            let changes = changes.unwrap_or_else(|e| exit_with_error(&term, e));
+            let changed: Vec<Member> = loaded(armory_toml.members(&cwd))
+                .into_iter()
+                .filter(|member| changes.contains_key(&member.name))
+                .collect();
+            let versions = changelog_versions(&cwd, &armory_toml, &changed, &args);
+            let sections = armory_lib::preview_changelogs(&cwd, &armory_toml, &versions, args.since.as_deref())
+                .unwrap_or_else(|e| exit_with_error(&term, e));
+            let paths = |section: &armory_lib::ChangelogSection| {
+                section.paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>()
+            };
+
+            if args.write {
+                let edits = armory_lib::changelog_edits(&cwd, &sections);
+                armory_lib::apply_edits(&edits).unwrap_or_else(|e| exit_with_error(&term, e));
+                for edit in &edits {
+                    say!("ARMORY: wrote {}", edit.path.strip_prefix(&cwd).unwrap_or(&edit.path).display());
+                }
+            }
+            if report::is_json() {
+                for section in &sections {
+                    report::push("sections", json!({
+                        "crate": section.crate_name,
+                        "paths": paths(section),
+                        "text": section.text,
+                    }));
+                }
+                return Ok(());
+            }
+            if args.write {
+                return Ok(());
+            }
+            if sections.is_empty() {
+                println!("Nothing changed since the last release");
+            }
+            for (index, section) in sections.iter().enumerate() {
+                if sections.len() > 1 {
+                    if index > 0 {
+                        println!();
+                    }
+                    println!("{}", style(format!("==> {}", paths(section).join(", "))).bold());
+                }
+                print!("{}", section.text);
+            }
+            return Ok(());
+        }
+        Command::History => {
+            if report::is_json() {
+                report::set("releases", &armory_toml.releases);
+                return Ok(());
+            }
+            if armory_toml.releases.is_empty() {
+                println!("No releases recorded yet");
+            }
+            for release in armory_toml.releases.iter().rev() {
+                let sha = release.sha.as_deref().map(|sha| &sha[..sha.len().min(7)]).unwrap_or("-");
+                match &release.version {
+                    Some(version) => println!("{} {} {}", style(version).bold(), release.date, sha),
+                    None => println!("{} {}", release.date, sha),
+                }
+                for (name, version) in &release.crates {
+                    match release.tags.get(name) {
+                        Some(tag) => println!("    {} {} ({})", name, version, tag),
+                        None => println!("    {} {}", name, version),
+                    }
+                }
+            }
+            return Ok(());
+        }
+        Command::GithubRelease(args) => {
+            let notes = last_release_notes(&term, &cwd, &armory_toml);
+            create_github_releases(&term, &cwd, &armory_toml, &notes, args.dry_run);
+        }
+        Command::GitlabRelease(args) => {
+            let notes = last_release_notes(&term, &cwd, &armory_toml);
+            create_gitlab_releases(&term, &cwd, &armory_toml, &notes, args.dry_run);
+        }
+        Command::Config => {
+            let settings = armory_lib::config::effective(&cwd, &armory_toml, cli.profile.as_deref());
+            let registries = loaded(armory_lib::config::crate_registries(&cwd, &armory_toml, cli.profile.as_deref()));
+            if report::is_json() {
+                report::set("settings", settings_json(&settings));
+                report::set("crate_registries", settings_json(&registries));
+                return Ok(());
+            }
+            print_settings(&settings);
+            println!("\n{}", style("# the registry each crate is published to").dim());
+            print_settings(&registries);
+            return Ok(());
+        }
+        Command::Status => {
+            match report::is_json() {
+                true => report_status(&term, &cwd, &armory_toml),
+                false => print_status(&term, &cwd, &armory_toml),
+            }
+            return Ok(());
+        }
+        Command::Init(_) | Command::Migrate(_) | Command::Completions(_) => {
+            unreachable!("these run before the config is loaded")
+        }
+    }
+
+    if !report::is_json() && armory_lib::output::level() > Level::Quiet {
+        term.write_line(&format!("{} Done!", style("✔").green()))?;
+    }
 
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_stay_the_same() {
+        let codes = [
+            ExitCode::Failure,
+            ExitCode::Config,
+            ExitCode::Preflight,
+            ExitCode::PartialPublish,
+            ExitCode::PublishFailed,
+            ExitCode::Interrupted,
+        ];
+        assert_eq!(codes.map(|code| code as i32), [1, 3, 4, 5, 6, 130]);
+    }
+
+    #[test]
+    fn publish_failures_exit_by_whether_anything_got_out() {
+        let dir = std::env::temp_dir().join(format!("armory-publish-failure-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(publish_failure(&dir), ExitCode::PublishFailed);
+
+        let mut state = PublishState::default();
+        state.save(&dir).unwrap();
+        assert_eq!(publish_failure(&dir), ExitCode::PublishFailed);
+
+        state.published.insert("a".to_string());
+        state.save(&dir).unwrap();
+        assert_eq!(publish_failure(&dir), ExitCode::PartialPublish);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+//! A live progress bar for publishes, kept on the last line of the
+//! terminal while armory's and cargo's output scrolls above it, so long
+//! releases don't look frozen between cargo's bursts of output.
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use armory_lib::{output::{self, Level}, ArmoryTOML, Progress};
+use dialoguer::console::{self, style, Term};
+use semver::Version;
+
+const WIDTH: usize = 24;
+
+struct Bar {
+    term: Term,
+    total: usize,
+    /// The crates done with, published or not.
+    finished: usize,
+    /// The crate being published, with what's happening to it and since
+    /// when.
+    current: Option<(String, Progress, Instant)>,
+    /// When the current crate moved on to its step, to count waits down
+    /// from.
+    changed: Instant,
+}
+
+static BAR: Mutex<Option<Bar>> = Mutex::new(None);
+
+impl Bar {
+    fn draw(&self) {
+        let filled = WIDTH * self.finished / self.total.max(1);
+        let mut line = format!(
+            "[{}{}] {}/{}",
+            "=".repeat(filled),
+            " ".repeat(WIDTH - filled),
+            self.finished,
+            self.total
+        );
+        if let Some((name, progress, since)) = &self.current {
+            let doing = describe(*progress, self.changed);
+            line.push_str(&format!(" {} {} ({}s)", style(name).bold(), doing, since.elapsed().as_secs()));
+        }
+        let (_, columns) = self.term.size();
+        let _ = self.term.clear_line();
+        let _ = self.term.write_str(&console::truncate_str(&line, columns as usize, "…"));
+    }
+
+    /// Prints `line` above the bar.
+    fn print(&self, line: &str) {
+        let _ = self.term.clear_line();
+        let _ = self.term.write_line(line);
+        self.draw();
+    }
+}
+
+/// What's happening to a crate at `progress`, which it got to at
+/// `changed`, with the time left on any wait.
+pub fn describe(progress: Progress, changed: Instant) -> String {
+    let left = |wait: Duration| wait.saturating_sub(changed.elapsed()).as_secs_f64().ceil() as u64;
+    match progress {
+        Progress::Packaging => "packaging".to_string(),
+        Progress::Uploading => "packaging and uploading".to_string(),
+        Progress::WaitingForIndex => "waiting for the index".to_string(),
+        Progress::RateLimited(wait) => format!("rate limited, retrying in {}s", left(wait)),
+        Progress::Retrying(wait) => format!("failed, retrying in {}s", left(wait)),
+        Progress::Done => "done".to_string(),
+        Progress::Failed => "failed".to_string(),
+    }
+}
+
+/// Starts drawing the progress of publishing `versions`, if there's a
+/// terminal to draw it on, as a bar or with `dashboard` as the whole
+/// screen. Returns what to pass the publish's progress to. Armory's output
+/// goes above the bar, or into the dashboard, until [`finish`].
+pub fn start(
+    cwd: &Path,
+    armory_toml: &ArmoryTOML,
+    versions: &HashMap<String, Version>,
+    dashboard: bool,
+) -> Option<fn(&str, Progress)> {
+    // output redirected to a file goes to stderr while the bar is up, so
+    // only draw it when both go to the terminal, or with --output json
+    // where they both go to stderr. Without colors, output stays plain
+    // lines
+    let term = Term::stderr();
+    let drawable = term.is_term() && (Term::stdout().is_term() || crate::report::is_json()) && console::colors_enabled_stderr();
+    if !drawable || output::level() == Level::Quiet {
+        return None;
+    }
+    let graph = crate::loaded(armory_lib::publish_graph(cwd, armory_toml));
+    let total = versions.keys().filter(|name| graph.contains_key(*name)).count();
+    if total == 0 {
+        return None;
+    }
+    // the bar stands in for a dashboard the terminal can't show
+    if let Some(update) = dashboard.then(|| crate::dashboard::start(term.clone(), &graph, versions)).flatten() {
+        return Some(update);
+    }
+
+    *BAR.lock().unwrap() = Some(Bar { term, total, finished: 0, current: None, changed: Instant::now() });
+    output::set_sink(Some(print_line));
+    // keep the time spent on the current crate ticking
+    thread::spawn(|| loop {
+        thread::sleep(Duration::from_millis(250));
+        match BAR.lock().unwrap().as_ref() {
+            Some(bar) => bar.draw(),
+            None => break,
+        }
+    });
+    Some(update)
+}
+
+fn update(name: &str, progress: Progress) {
+    let mut bar = BAR.lock().unwrap();
+    let Some(bar) = bar.as_mut() else {
+        return;
+    };
+    // the time shown is for the whole crate, not each step
+    let since = match &bar.current {
+        Some((current, _, since)) if current == name => Some(*since),
+        _ => None,
+    };
+    match progress {
+        Progress::Done | Progress::Failed => {
+            bar.finished += 1;
+            bar.current = None;
+            let mut line = match progress {
+                Progress::Done => format!("{} {}", style("✔").green(), name),
+                _ => format!("{} {}", style("✘").red(), name),
+            };
+            if let Some(since) = since {
+                line.push_str(&format!(" {}", style(format!("{:.1}s", since.elapsed().as_secs_f64())).dim()));
+            }
+            bar.print(&line);
+        }
+        progress => {
+            bar.current = Some((name.to_string(), progress, since.unwrap_or_else(Instant::now)));
+            bar.changed = Instant::now();
+            bar.draw();
+        }
+    }
+}
+
+fn print_line(line: &str) {
+    match BAR.lock().unwrap().as_ref() {
+        Some(bar) => bar.print(line),
+        None => eprintln!("{}", line),
+    }
+}
+
+/// Takes the bar or dashboard down and goes back to printing armory's
+/// output as usual.
+pub fn finish() {
+    if let Some(bar) = BAR.lock().unwrap().take() {
+        let _ = bar.term.clear_line();
+    }
+    crate::dashboard::finish();
+    output::set_sink(None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_count_down() {
+        let changed = Instant::now() - Duration::from_secs(3);
+        assert_eq!(describe(Progress::Retrying(Duration::from_secs(8)), changed), "failed, retrying in 5s");
+        assert_eq!(describe(Progress::RateLimited(Duration::from_secs(2)), changed), "rate limited, retrying in 0s");
+    }
+}
@@ -0,0 +1,227 @@
+//! The machine-readable results `--output json` prints instead of human
+//! text, collected as the command runs and printed to stdout once it's
+//! done. Progress goes to stderr meanwhile.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Mutex,
+    time::Instant,
+};
+
+use armory_lib::{ArmoryTOML, PublishState};
+use clap::ValueEnum;
+use dialoguer::console::{self, Term};
+use semver::Version;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    /// Text for people
+    #[default]
+    Human,
+    /// A JSON object on stdout, with progress on stderr
+    Json,
+}
+
+#[derive(Serialize)]
+struct Report {
+    command: String,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    duration_secs: f64,
+    /// The version of each crate released or published.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    versions: BTreeMap<String, String>,
+    /// The order crates were, or would be, published in.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    order: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    crates: BTreeMap<String, CrateResult>,
+    /// Whatever else the command has to tell.
+    #[serde(flatten)]
+    extra: serde_json::Map<String, Value>,
+}
+
+/// How publishing a crate went.
+#[derive(Serialize)]
+pub struct CrateResult {
+    pub version: String,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<f64>,
+    /// The attempts at uploading it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attempts: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Published,
+    /// Packaged by a dry run, without uploading.
+    Packaged,
+    Failed,
+    /// Not got to, or held back by a failed dependency.
+    Pending,
+    /// Left out, as its manifest doesn't allow publishing it, or left
+    /// alone as its version was on the registry already.
+    Skipped,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Status::Published => "published",
+            Status::Packaged => "packaged",
+            Status::Failed => "failed",
+            Status::Pending => "pending",
+            Status::Skipped => "skipped",
+        })
+    }
+}
+
+/// The report being collected, with when the command started. `None`
+/// without `--output json`.
+static REPORT: Mutex<Option<(Instant, Report)>> = Mutex::new(None);
+
+fn update(f: impl FnOnce(&mut Report)) {
+    if let Some((_, report)) = REPORT.lock().unwrap().as_mut() {
+        f(report);
+    }
+}
+
+/// Starts collecting the results of `command`, if they're to be printed
+/// as JSON, and sends progress to stderr.
+pub fn start(command: &str, output: Output) {
+    if output != Output::Json {
+        return;
+    }
+    armory_lib::output::progress_to_stderr(true);
+    let report = Report {
+        command: command.to_string(),
+        success: false,
+        error: None,
+        duration_secs: 0.0,
+        versions: BTreeMap::new(),
+        order: Vec::new(),
+        crates: BTreeMap::new(),
+        extra: serde_json::Map::new(),
+    };
+    *REPORT.lock().unwrap() = Some((Instant::now(), report));
+}
+
+pub fn is_json() -> bool {
+    REPORT.lock().unwrap().is_some()
+}
+
+/// Where errors and warnings go, which is stderr in JSON mode so stdout
+/// only has the report.
+pub fn term() -> Term {
+    match is_json() {
+        true => Term::stderr(),
+        false => Term::stdout(),
+    }
+}
+
+/// Records the versions chosen for a release.
+pub fn versions(versions: &HashMap<String, Version>) {
+    update(|report| {
+        report.versions = versions.iter().map(|(name, version)| (name.clone(), version.to_string())).collect();
+    });
+}
+
+/// Records the order crates are published in.
+pub fn order(order: Vec<String>) {
+    update(|report| report.order = order);
+}
+
+/// How publishing each of `versions` went, from the publish's `state`, or
+/// the one it left behind when it failed.
+pub fn crate_results(
+    cwd: &Path,
+    armory_toml: &ArmoryTOML,
+    versions: &HashMap<String, Version>,
+    state: Option<&PublishState>,
+    dry_run: bool,
+) -> BTreeMap<String, CrateResult> {
+    let finished = state.is_some();
+    let left = match state {
+        Some(_) => None,
+        None => PublishState::load(cwd).ok().flatten(),
+    };
+    let state = state.or(left.as_ref());
+
+    let graph = crate::loaded(armory_lib::publish_graph(cwd, armory_toml));
+    let mut results = BTreeMap::new();
+    for (name, version) in versions {
+        let error = state.and_then(|state| state.failed.get(name)).cloned();
+        let skipped = state.is_some_and(|state| state.skipped.contains(name));
+        let status = match (&error, state.is_some_and(|state| state.published.contains(name))) {
+            (Some(_), _) => Status::Failed,
+            (None, _) if skipped => Status::Skipped,
+            (None, true) => Status::Published,
+            (None, false) if !graph.contains_key(name) => Status::Skipped,
+            (None, false) if finished && dry_run => Status::Packaged,
+            (None, false) if finished => Status::Published,
+            (None, false) => Status::Pending,
+        };
+        results.insert(name.clone(), CrateResult {
+            version: version.to_string(),
+            status,
+            error,
+            duration_secs: state.and_then(|state| state.durations.get(name)).copied(),
+            attempts: state.and_then(|state| state.attempts.get(name)).copied(),
+        });
+    }
+    results
+}
+
+/// Records how publishing `versions` went, as [`crate_results`] tells.
+pub fn publish(
+    cwd: &Path,
+    armory_toml: &ArmoryTOML,
+    versions: &HashMap<String, Version>,
+    state: Option<&PublishState>,
+    dry_run: bool,
+) {
+    if !is_json() {
+        return;
+    }
+    let graph = crate::loaded(armory_lib::publish_graph(cwd, armory_toml));
+    order(armory_lib::publish_order(&graph).into_iter().filter(|name| versions.contains_key(name)).collect());
+    let results = crate_results(cwd, armory_toml, versions, state, dry_run);
+    update(|report| report.crates = results);
+}
+
+/// Adds `value` to the report under `key`.
+pub fn set(key: &str, value: impl Serialize) {
+    update(|report| {
+        report.extra.insert(key.to_string(), serde_json::to_value(value).expect("Failed to serialize the report"));
+    });
+}
+
+/// Adds `value` to the list under `key`.
+pub fn push(key: &str, value: impl Serialize) {
+    update(|report| {
+        let list = report.extra.entry(key).or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(list) = list {
+            list.push(serde_json::to_value(value).expect("Failed to serialize the report"));
+        }
+    });
+}
+
+/// Prints the report, successful unless there's an `error`.
+pub fn finish(error: Option<String>) {
+    let Some((started, mut report)) = REPORT.lock().unwrap().take() else {
+        return;
+    };
+    report.success = error.is_none();
+    report.error = error.map(|error| console::strip_ansi_codes(&error).into_owned());
+    report.duration_secs = started.elapsed().as_secs_f64();
+    println!("{}", serde_json::to_string_pretty(&report).expect("Failed to serialize the report"));
+}
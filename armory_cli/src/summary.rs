@@ -0,0 +1,99 @@
+//! The table printed once a publish is over, saying what happened to each
+//! crate in one place rather than scattered through the retries above it.
+
+use std::{collections::HashMap, path::Path};
+
+use armory_lib::{say, ArmoryTOML, PublishState};
+use dialoguer::console::{self, style};
+use semver::Version;
+
+use crate::report::{self, Status};
+
+/// The version each of `versions` was at before, going by the releases
+/// recorded in armory.toml, for publishes that didn't bump anything.
+pub fn released_before(armory_toml: &ArmoryTOML, versions: &HashMap<String, Version>) -> HashMap<String, Version> {
+    let mut previous = HashMap::new();
+    for release in &armory_toml.releases {
+        for (name, version) in &release.crates {
+            if versions.get(name).is_some_and(|new| new != version) {
+                previous.insert(name.clone(), version.clone());
+            }
+        }
+    }
+    previous
+}
+
+/// Prints how publishing `versions` went, up from their `previous` ones,
+/// from the publish's `state`, or the one it left behind when it failed.
+pub fn print(
+    cwd: &Path,
+    armory_toml: &ArmoryTOML,
+    previous: &HashMap<String, Version>,
+    versions: &HashMap<String, Version>,
+    state: Option<&PublishState>,
+    dry_run: bool,
+) {
+    if report::is_json() || versions.is_empty() {
+        return;
+    }
+    let results = report::crate_results(cwd, armory_toml, versions, state, dry_run);
+    let graph = crate::loaded(armory_lib::publish_graph(cwd, armory_toml));
+    // crates left out of the graph go last, as they never got a turn
+    let mut order: Vec<String> =
+        armory_lib::publish_order(&graph).into_iter().filter(|name| versions.contains_key(name)).collect();
+    let mut rest: Vec<String> = versions.keys().filter(|name| !order.contains(name)).cloned().collect();
+    rest.sort();
+    order.extend(rest);
+
+    let arrow = if console::colors_enabled() { "→" } else { "->" };
+    let header = ["CRATE", "VERSION", "REGISTRY", "ATTEMPTS", "TIME", "RESULT"].map(String::from);
+    let rows: Vec<([String; 6], Status)> = order
+        .iter()
+        .map(|name| {
+            let result = &results[name];
+            let version = match previous.get(name) {
+                Some(old) if old.to_string() != result.version => format!("{} {} {}", old, arrow, result.version),
+                _ => result.version.clone(),
+            };
+            let registries: Vec<&str> = std::iter::once(armory_toml.registry_for(name))
+                .chain(armory_toml.mirrors_for(name))
+                .map(|registry| registry.unwrap_or("crates-io"))
+                .collect();
+            let attempts = result.attempts.map(|attempts| attempts.to_string()).unwrap_or_else(|| "-".to_string());
+            let time = match result.duration_secs {
+                Some(secs) => format!("{:.1}s", secs),
+                None => "-".to_string(),
+            };
+            ([name.clone(), version, registries.join(", "), attempts, time, result.status.to_string()], result.status)
+        })
+        .collect();
+
+    // padded before styling, as the escape codes would throw the widths off
+    let mut widths = header.clone().map(|cell| console::measure_text_width(&cell));
+    for (row, _) in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(console::measure_text_width(cell));
+        }
+    }
+    let line = |row: &[String; 6]| -> Vec<String> {
+        row.iter()
+            .zip(widths)
+            .map(|(cell, width)| console::pad_str(cell, width, console::Alignment::Left, None).into_owned())
+            .collect()
+    };
+
+    say!();
+    say!("{}", style(line(&header).join("  ").trim_end()).bold());
+    for (row, status) in &rows {
+        let mut cells = line(row);
+        let result = cells.pop().unwrap_or_default().trim_end().to_string();
+        let result = match status {
+            Status::Published => style(result).green(),
+            Status::Packaged => style(result).cyan(),
+            Status::Failed => style(result).red(),
+            Status::Pending => style(result).yellow(),
+            Status::Skipped => style(result).dim(),
+        };
+        say!("{}  {}", cells.join("  "), result);
+    }
+}
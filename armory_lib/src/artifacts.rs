@@ -0,0 +1,97 @@
+//! Checksums and signatures for packaged `.crate` files.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use cargo_util::Sha256;
+use serde::{Deserialize, Serialize};
+
+use crate::ArmoryError;
+
+/// The file checksums are written to, next to the packages.
+pub const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// The hex SHA-256 of the file at `path`.
+pub fn sha256(path: &Path) -> Result<String, ArmoryError> {
+    Ok(Sha256::new()
+        .update_path(path)
+        .map_err(|e| format!("Failed to hash {}: {:#}", path.display(), e))?
+        .finish_hex())
+}
+
+/// Writes the checksums of `files` to a `SHA256SUMS` file in `dir`, in the
+/// format `sha256sum --check` reads.
+pub fn write_checksums(dir: &Path, files: &[PathBuf]) -> Result<PathBuf, ArmoryError> {
+    let mut checksums = String::new();
+    for file in files {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        checksums.push_str(&format!("{}  {}\n", sha256(file)?, name));
+    }
+
+    let path = dir.join(CHECKSUMS_FILE);
+    fs::write(&path, checksums).map_err(|e| ArmoryError::io("write", &path, e))?;
+    Ok(path)
+}
+
+/// The tool packages are signed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignTool {
+    Gpg,
+    Minisign,
+}
+
+/// How to sign packages, from armory.toml's `[signing]` table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Signing {
+    pub tool: SignTool,
+    /// The gpg key ID or minisign secret key file to sign with, instead of
+    /// the tool's default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl Signing {
+    /// Signs `file`, writing a detached signature next to it, and returns
+    /// the signature's path.
+    pub fn sign(&self, file: &Path) -> Result<PathBuf, ArmoryError> {
+        let (program, signature) = match self.tool {
+            SignTool::Gpg => ("gpg", append_extension(file, "asc")),
+            SignTool::Minisign => ("minisign", append_extension(file, "minisig")),
+        };
+
+        let mut command = Command::new(program);
+        match self.tool {
+            SignTool::Gpg => {
+                command.args(["--batch", "--yes", "--armor", "--detach-sign", "--output"]).arg(&signature);
+                if let Some(key) = &self.key {
+                    command.args(["--local-user", key]);
+                }
+                command.arg(file);
+            }
+            SignTool::Minisign => {
+                command.arg("-S").arg("-m").arg(file).arg("-x").arg(&signature);
+                if let Some(key) = &self.key {
+                    command.args(["-s", key]);
+                }
+            }
+        }
+
+        let status = command.status().map_err(|e| format!("Failed to run {}: {}", program, e))?;
+        if !status.success() {
+            return Err(format!("{} failed to sign {}", program, file.display()).into());
+        }
+        Ok(signature)
+    }
+}
+
+fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
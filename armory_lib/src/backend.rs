@@ -0,0 +1,421 @@
+//! The ways armory can publish a crate.
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use cargo::{
+    core::{
+        resolver::CliFeatures,
+        shell::{Shell, Verbosity},
+        Workspace,
+    },
+    ops::{PackageOpts, Packages, PublishOpts},
+    util::auth::Secret,
+    Config,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    output::{self, Level},
+    ArmoryError, Stage,
+};
+
+/// What publishes crates for armory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The cargo library armory is built with.
+    #[default]
+    Library,
+    /// `cargo publish` from the installed toolchain, for when it's newer
+    /// than the library.
+    Cargo,
+}
+
+/// The features a crate is packaged and verified with. Without one in
+/// armory.toml, crates get all their features.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Features {
+    #[serde(default)]
+    pub features: Vec<String>,
+    #[serde(default)]
+    pub all_features: bool,
+    #[serde(default = "default_true")]
+    pub default_features: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features { features: Vec::new(), all_features: true, default_features: true }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A single crate to publish to a single registry.
+#[derive(Debug, Clone)]
+pub struct Upload<'a> {
+    /// The workspace root.
+    pub dir: &'a Path,
+    pub package: &'a str,
+    /// The registry to publish to, or crates.io if `None`.
+    pub registry: Option<&'a str>,
+    pub token: Option<&'a str>,
+    pub verify: bool,
+    pub dry_run: bool,
+    pub targets: &'a [String],
+    pub features: &'a Features,
+    /// How long the attempt may take before it's given up on.
+    pub timeout: Option<Duration>,
+    /// Local dependencies to verify against as they are in the workspace,
+    /// for when they aren't published yet.
+    pub local_deps: &'a [LocalDep],
+    /// Checked while the upload runs, which is given up on once it
+    /// returns `true`.
+    pub abort: Option<fn() -> bool>,
+}
+
+/// A local dependency to build against as it is in the workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalDep {
+    pub name: String,
+    /// The registry it's published to, or crates.io if `None`.
+    pub registry: Option<String>,
+    pub path: PathBuf,
+}
+
+impl Backend {
+    /// Runs a single attempt at `upload`, returning the error in full.
+    pub fn publish(self, upload: &Upload) -> Result<(), ArmoryError> {
+        match self {
+            Backend::Library if upload.timeout.is_none() && upload.abort.is_none() => publish_with_library(upload),
+            Backend::Library => publish_with_library_watched(upload),
+            Backend::Cargo => publish_with_cargo(upload),
+        }
+    }
+
+    /// Packages the crate of `upload` into a `.crate` file without
+    /// uploading it anywhere. Its registry, token and dry run are ignored.
+    pub fn package(self, upload: &Upload) -> Result<(), ArmoryError> {
+        match self {
+            Backend::Library => package_with_library(upload),
+            Backend::Cargo => run_cargo(cargo_command("package", upload), Stage::Package, upload),
+        }
+    }
+}
+
+/// Hands what the cargo library prints to [`output::cargo_output`] a line
+/// at a time, for when lines go to a sink or a log file.
+#[derive(Default)]
+struct CargoLines(Vec<u8>);
+
+impl Write for CargoLines {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        if let Some(end) = self.0.iter().rposition(|byte| *byte == b'\n') {
+            let lines: Vec<u8> = self.0.drain(..=end).collect();
+            output::cargo_output(&String::from_utf8_lossy(&lines));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for CargoLines {
+    fn drop(&mut self) {
+        if !self.0.is_empty() {
+            output::cargo_output(&String::from_utf8_lossy(&self.0));
+        }
+    }
+}
+
+/// An [`ArmoryError::Crate`] for the crate of `upload`, with the whole
+/// chain of cargo's error.
+fn failed_at(stage: Stage, upload: &Upload, e: impl fmt::Display) -> ArmoryError {
+    ArmoryError::at(stage, upload.package, format!("{:#}", e))
+}
+
+fn library_config(upload: &Upload) -> Result<Config, ArmoryError> {
+    fn failed(e: impl fmt::Display) -> ArmoryError {
+        ArmoryError::Cargo(format!("{:#}", e))
+    }
+    let mut cfg = Config::default().map_err(failed)?;
+    if output::has_sink() || output::log_file().is_some() {
+        *cfg.shell() = Shell::from_write(Box::<CargoLines>::default());
+    }
+    cfg.set_values(cfg.load_values().map_err(failed)?).map_err(failed)?;
+    cfg.load_credentials().map_err(failed)?;
+    // verification builds each crate in its own ephemeral workspace, so
+    // point them all at the workspace's target dir to reuse the
+    // dependencies already built there
+    let target_dir = (upload.verify && cfg.target_dir().map_err(failed)?.is_none()).then(|| upload.dir.join("target"));
+    let cli_config = patches(upload.local_deps);
+    if target_dir.is_some() || !cli_config.is_empty() {
+        cfg.configure(0, false, None, false, false, false, &target_dir, &[], &cli_config).map_err(failed)?;
+    }
+    // quiet cargo still has its say in the log, leaving it out of the
+    // terminal
+    cfg.shell().set_verbosity(match output::level() {
+        Level::Quiet if output::log_file().is_none() => Verbosity::Quiet,
+        Level::Quiet => Verbosity::Normal,
+        Level::Normal | Level::Verbose => Verbosity::Normal,
+        Level::Trace => Verbosity::Verbose,
+    });
+    Ok(cfg)
+}
+
+/// The `--config` values patching `local_deps` in for the registry's
+/// versions. Unlike path overrides, patches can add versions the registry
+/// doesn't have.
+fn patches(local_deps: &[LocalDep]) -> Vec<String> {
+    local_deps
+        .iter()
+        .map(|dep| {
+            let path = toml::Value::String(dep.path.display().to_string());
+            format!("patch.{}.{}.path = {}", dep.registry.as_deref().unwrap_or("crates-io"), dep.name, path)
+        })
+        .collect()
+}
+
+fn cli_features(upload: &Upload, stage: Stage) -> Result<CliFeatures, ArmoryError> {
+    let features = &upload.features;
+    CliFeatures::from_command_line(&features.features, features.all_features, features.default_features)
+        .map_err(|e| failed_at(stage, upload, format!("invalid features: {:#}", e)))
+}
+
+fn publish_with_library(upload: &Upload) -> Result<(), ArmoryError> {
+    let cfg = library_config(upload)?;
+    let workspace =
+        Workspace::new(&upload.dir.join("Cargo.toml"), &cfg).map_err(|e| failed_at(Stage::Publish, upload, e))?;
+
+    cargo::ops::publish(
+        &workspace,
+        &PublishOpts {
+            token: upload.token.map(|token| Secret::from(token.to_string())),
+            config: &cfg,
+            verify: upload.verify,
+            // armory checks the tree is clean before it edits manifests
+            allow_dirty: true,
+            registry: upload.registry.map(|registry| registry.to_string()),
+            dry_run: upload.dry_run,
+            targets: upload.targets.to_vec(),
+            to_publish: Packages::Packages(vec![upload.package.to_string()]),
+            cli_features: cli_features(upload, Stage::Publish)?,
+            index: None,
+            jobs: None,
+            keep_going: false,
+        },
+    )
+    .map_err(|e| failed_at(Stage::Publish, upload, e))
+}
+
+/// Publishes with the library on another thread, so a hung or aborted
+/// upload can be given up on. The thread is left behind if it is, since
+/// cargo can't be interrupted.
+fn publish_with_library_watched(upload: &Upload) -> Result<(), ArmoryError> {
+    let (dir, package) = (upload.dir.to_path_buf(), upload.package.to_string());
+    let (registry, token) = (upload.registry.map(str::to_string), upload.token.map(str::to_string));
+    let (targets, features) = (upload.targets.to_vec(), upload.features.clone());
+    let (verify, dry_run) = (upload.verify, upload.dry_run);
+    let local_deps = upload.local_deps.to_vec();
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let upload = Upload {
+            dir: &dir,
+            package: &package,
+            registry: registry.as_deref(),
+            token: token.as_deref(),
+            verify,
+            dry_run,
+            targets: &targets,
+            features: &features,
+            timeout: None,
+            local_deps: &local_deps,
+            abort: None,
+        };
+        let _ = sender.send(publish_with_library(&upload));
+    });
+
+    let start = Instant::now();
+    loop {
+        match receiver.recv_timeout(Duration::from_millis(100)) {
+            Ok(result) => return result,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(failed_at(Stage::Publish, upload, "cargo panicked while publishing"));
+            }
+        }
+        if let Some(timeout) = upload.timeout.filter(|timeout| start.elapsed() >= *timeout) {
+            return Err(failed_at(Stage::Publish, upload, format!("timed out after {}s", timeout.as_secs())));
+        }
+        if upload.abort.is_some_and(|abort| abort()) {
+            return Err(failed_at(Stage::Publish, upload, "interrupted during the upload"));
+        }
+    }
+}
+
+fn package_with_library(upload: &Upload) -> Result<(), ArmoryError> {
+    let cfg = library_config(upload)?;
+    let workspace =
+        Workspace::new(&upload.dir.join("Cargo.toml"), &cfg).map_err(|e| failed_at(Stage::Package, upload, e))?;
+
+    cargo::ops::package(
+        &workspace,
+        &PackageOpts {
+            config: &cfg,
+            list: false,
+            check_metadata: true,
+            allow_dirty: true,
+            verify: upload.verify,
+            jobs: None,
+            keep_going: false,
+            to_package: Packages::Packages(vec![upload.package.to_string()]),
+            targets: upload.targets.to_vec(),
+            cli_features: cli_features(upload, Stage::Package)?,
+        },
+    )
+    .map(|_| ())
+    .map_err(|e| failed_at(Stage::Package, upload, e))
+}
+
+/// `cargo <subcommand>` for the crate of `upload`, with the options both
+/// `publish` and `package` take.
+fn cargo_command(subcommand: &str, upload: &Upload) -> Command {
+    let mut cargo = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()));
+    cargo
+        .current_dir(upload.dir)
+        .args([subcommand, "--package", upload.package, "--allow-dirty"])
+        .args(output::captured_cargo_flags())
+        .stderr(Stdio::piped());
+    // Ctrl-C lets the crate being published finish, so it mustn't reach
+    // cargo, which the terminal would send it to along with armory
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut cargo, 0);
+    if upload.features.all_features {
+        cargo.arg("--all-features");
+    }
+    if !upload.features.features.is_empty() {
+        cargo.args(["--features", &upload.features.features.join(",")]);
+    }
+    if !upload.features.default_features {
+        cargo.arg("--no-default-features");
+    }
+    if !upload.verify {
+        cargo.arg("--no-verify");
+    }
+    for target in upload.targets {
+        cargo.args(["--target", target]);
+    }
+    // newer cargos resolve crates packaged together against each other,
+    // so unpublished local dependencies get packaged along with the crate
+    for dep in upload.local_deps {
+        cargo.args(["--package", &dep.name]);
+    }
+    cargo
+}
+
+fn publish_with_cargo(upload: &Upload) -> Result<(), ArmoryError> {
+    let mut cargo = cargo_command("publish", upload);
+    if upload.dry_run {
+        cargo.arg("--dry-run");
+    }
+    if let Some(registry) = upload.registry {
+        cargo.args(["--registry", registry]);
+    }
+    // through the environment rather than --token, which would show up in
+    // the process list
+    if let Some(token) = upload.token {
+        let var = match upload.registry {
+            Some(registry) => format!("CARGO_REGISTRIES_{}_TOKEN", registry.to_uppercase().replace('-', "_")),
+            None => "CARGO_REGISTRY_TOKEN".to_string(),
+        };
+        cargo.env(var, token);
+    }
+
+    run_cargo(cargo, Stage::Publish, upload)
+}
+
+/// Runs cargo at `stage` of `upload`, showing its output and returning its
+/// error if it fails. Cargo is killed if it takes longer than the upload's
+/// timeout.
+fn run_cargo(mut cargo: Command, stage: Stage, upload: &Upload) -> Result<(), ArmoryError> {
+    let timeout = upload.timeout;
+    let mut child = cargo.spawn().map_err(|e| failed_at(stage, upload, format!("couldn't run cargo: {}", e)))?;
+    let mut pipe = child.stderr.take().expect("stderr is piped");
+    let reader = thread::spawn(move || {
+        let mut stderr = Vec::new();
+        let _ = pipe.read_to_end(&mut stderr);
+        stderr
+    });
+
+    let start = Instant::now();
+    let mut aborted = false;
+    let status = loop {
+        let status = child.try_wait().map_err(|e| failed_at(stage, upload, format!("couldn't wait for cargo: {}", e)))?;
+        if let Some(status) = status {
+            break Some(status);
+        }
+        aborted = upload.abort.is_some_and(|abort| abort());
+        if aborted || timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+
+    let stderr = reader.join().unwrap_or_default();
+    let stderr = String::from_utf8_lossy(&stderr);
+    output::cargo_output(&stderr);
+
+    if aborted {
+        return Err(failed_at(stage, upload, "interrupted during the upload"));
+    }
+    let Some(status) = status else {
+        return Err(failed_at(stage, upload, format!("timed out after {}s", timeout.unwrap_or_default().as_secs())));
+    };
+    if status.success() {
+        return Ok(());
+    }
+    // leave out warnings, such as ones about retried downloads
+    let error = match stderr.find("error:") {
+        Some(start) => &stderr[start..],
+        None => &stderr,
+    };
+    Err(failed_at(stage, upload, error.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_deps_are_patched_in_for_their_registry() {
+        let local_deps = [
+            LocalDep { name: "core".to_string(), registry: None, path: PathBuf::from("/ws/core") },
+            LocalDep {
+                name: "macros".to_string(),
+                registry: Some("internal".to_string()),
+                path: PathBuf::from("/ws/my \"macros\""),
+            },
+        ];
+        assert_eq!(patches(&local_deps), [
+            r#"patch.crates-io.core.path = "/ws/core""#,
+            r#"patch.internal.macros.path = "/ws/my \"macros\"""#,
+        ]);
+    }
+}
@@ -0,0 +1,290 @@
+use std::{fmt, path::Path};
+
+use semver::{Prerelease, Version};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{ArmoryError, ConfigError};
+
+/// How versions are numbered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    /// Plain semantic versioning.
+    #[default]
+    Semver,
+    /// Calendar versioning as `YEAR.MONTH.PATCH`, e.g. `2024.6.0`.
+    Calver,
+}
+
+impl Scheme {
+    /// The bumps offered for `current` under this scheme, in menu order.
+    /// Releasing on a pre-release `channel` leaves out the other channels.
+    pub fn bumps(self, current: &Version, channel: Option<&str>) -> Vec<Bump> {
+        let mut bumps = Vec::new();
+        if !current.pre.is_empty() {
+            bumps.push(Bump::Promote);
+        }
+        match self {
+            Scheme::Semver => bumps.extend(Bump::ALL),
+            Scheme::Calver => bumps.push(Bump::Calendar),
+        }
+        if channel.is_some() {
+            bumps.retain(|bump| bump.channel().is_none());
+        }
+        bumps
+    }
+}
+
+/// The ways a version can be bumped from the release menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bump {
+    Patch,
+    Minor,
+    Major,
+    Alpha,
+    Beta,
+    Rc,
+    /// The next calendar version: this month's first release, or the next
+    /// patch if there already was one this month.
+    Calendar,
+    /// Turns a pre-release into its final release, e.g. `2.0.0-rc.3` to
+    /// `2.0.0`.
+    Promote,
+}
+
+impl Bump {
+    pub const ALL: [Bump; 6] = [
+        Bump::Patch,
+        Bump::Minor,
+        Bump::Major,
+        Bump::Alpha,
+        Bump::Beta,
+        Bump::Rc,
+    ];
+
+    /// The pre-release channel identifier for this bump, if it is one.
+    pub fn channel(self) -> Option<&'static str> {
+        match self {
+            Bump::Alpha => Some("alpha"),
+            Bump::Beta => Some("beta"),
+            Bump::Rc => Some("rc"),
+            _ => None,
+        }
+    }
+
+    /// Orders bumps by how much of the version they change; pre-releases
+    /// count as patches.
+    pub(crate) fn level(self) -> u8 {
+        match self {
+            Bump::Major => 3,
+            Bump::Minor => 2,
+            _ => 1,
+        }
+    }
+
+    /// The larger of two bumps.
+    pub fn max(self, other: Bump) -> Bump {
+        if other.level() > self.level() {
+            other
+        } else {
+            self
+        }
+    }
+
+    /// Shifts a bump down one level while `current` is still 0.x, as cargo
+    /// treats 0.x minors as breaking.
+    pub(crate) fn for_version(self, current: &Version) -> Bump {
+        if current.major != 0 {
+            return self;
+        }
+        match self {
+            Bump::Major => Bump::Minor,
+            Bump::Minor => Bump::Patch,
+            bump => bump,
+        }
+    }
+
+    /// The version this bump takes `version` to. Fails only if the
+    /// pre-release it would carry isn't valid.
+    pub fn apply(self, version: &Version) -> Result<Version, ArmoryError> {
+        let mut next = version.clone();
+        match self {
+            Bump::Patch => {
+                next.patch += 1;
+                next.pre = Prerelease::EMPTY;
+            }
+            Bump::Minor => {
+                next.minor += 1;
+                next.patch = 0;
+                next.pre = Prerelease::EMPTY;
+            }
+            Bump::Major => {
+                next.major += 1;
+                next.minor = 0;
+                next.patch = 0;
+                next.pre = Prerelease::EMPTY;
+            }
+            Bump::Promote => {
+                next.pre = Prerelease::EMPTY;
+            }
+            Bump::Calendar => {
+                let today = OffsetDateTime::now_utc();
+                let (year, month) = (today.year() as u64, u8::from(today.month()) as u64);
+                if version.major == year && version.minor == month && version.pre.is_empty() {
+                    next.patch += 1;
+                } else {
+                    next.major = year;
+                    next.minor = month;
+                    next.patch = 0;
+                }
+                next.pre = Prerelease::EMPTY;
+            }
+            Bump::Alpha | Bump::Beta | Bump::Rc => {
+                let channel = self.channel().unwrap_or_default();
+                next.pre = next_prerelease(version, channel)?;
+                // a stable version, or moving back to an earlier channel,
+                // starts the pre-release series of the next patch
+                if next <= *version {
+                    next.patch += 1;
+                }
+            }
+        }
+        Ok(next)
+    }
+}
+
+impl Bump {
+    /// Applies the bump as a pre-release on `channel`, if given: a patch
+    /// of `1.2.0` on `nightly` is `1.2.1-nightly.1`. A pre-release already
+    /// leading up to a version the bump would reach counts on, so a patch
+    /// of `1.2.1-nightly.1` is `1.2.1-nightly.2`. Promoting and the
+    /// channel bumps ignore `channel`. Fails if `channel` can't be a
+    /// pre-release identifier.
+    pub fn apply_on(self, version: &Version, channel: Option<&str>) -> Result<Version, ArmoryError> {
+        let channel = channel.filter(|_| matches!(self, Bump::Patch | Bump::Minor | Bump::Major | Bump::Calendar));
+        let Some(channel) = channel else {
+            return self.apply(version);
+        };
+
+        let promoted = Bump::Promote.apply(version)?;
+        let reached = !version.pre.is_empty()
+            && match self {
+                Bump::Major => version.minor == 0 && version.patch == 0,
+                Bump::Minor => version.patch == 0,
+                Bump::Calendar => Bump::Calendar.apply(version)? == promoted,
+                _ => true,
+            };
+        if reached {
+            let mut next = promoted;
+            next.pre = next_prerelease(version, channel)?;
+            return Ok(next);
+        }
+
+        let mut next = self.apply(version)?;
+        next.pre = prerelease(channel, 1)?;
+        Ok(next)
+    }
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Bump::Patch => "Patch",
+            Bump::Minor => "Minor",
+            Bump::Major => "Major",
+            Bump::Alpha => "Alpha",
+            Bump::Beta => "Beta",
+            Bump::Rc => "RC",
+            Bump::Calendar => "Release",
+            Bump::Promote => "Promote",
+        })
+    }
+}
+
+/// Splits a pre-release like `rc.3` into its channel and number.
+/// A missing number (e.g. `rc`) counts as 0.
+pub fn parse_prerelease(pre: &Prerelease) -> Option<(&str, u64)> {
+    if pre.is_empty() {
+        return None;
+    }
+    match pre.as_str().split_once('.') {
+        Some((channel, number)) => number.parse().ok().map(|number| (channel, number)),
+        None => Some((pre.as_str(), 0)),
+    }
+}
+
+fn next_prerelease(version: &Version, channel: &str) -> Result<Prerelease, ArmoryError> {
+    let number = match parse_prerelease(&version.pre) {
+        Some((current, number)) if current == channel => number + 1,
+        _ => 1,
+    };
+    prerelease(channel, number)
+}
+
+/// The pre-release `number` on `channel`, like `rc.2`.
+fn prerelease(channel: &str, number: u64) -> Result<Prerelease, ArmoryError> {
+    Prerelease::new(&format!("{}.{}", channel, number)).map_err(|e| {
+        let message = format!("`{}` isn't a valid pre-release channel: {}", channel, e);
+        ConfigError::invalid_value(Path::new("armory.toml"), "pre-release", message).into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn bumps_reset_the_parts_below_them() {
+        let current = version("1.2.3");
+        assert_eq!(Bump::Patch.apply(&current).unwrap(), version("1.2.4"));
+        assert_eq!(Bump::Minor.apply(&current).unwrap(), version("1.3.0"));
+        assert_eq!(Bump::Major.apply(&current).unwrap(), version("2.0.0"));
+        assert_eq!(Bump::Promote.apply(&version("2.0.0-rc.3")).unwrap(), version("2.0.0"));
+        assert_eq!(Bump::Minor.apply(&version("1.3.0-beta.2")).unwrap(), version("1.4.0"));
+    }
+
+    #[test]
+    fn pre_1_0_bumps_shift_down_a_level() {
+        let current = version("0.3.1");
+        assert_eq!(Bump::Major.for_version(&current), Bump::Minor);
+        assert_eq!(Bump::Minor.for_version(&current), Bump::Patch);
+        assert_eq!(Bump::Patch.for_version(&current), Bump::Patch);
+        assert_eq!(Bump::Major.for_version(&version("1.0.0")), Bump::Major);
+    }
+
+    #[test]
+    fn pre_releases_count_up_on_their_channel() {
+        assert_eq!(Bump::Rc.apply(&version("1.2.0")).unwrap(), version("1.2.1-rc.1"));
+        assert_eq!(Bump::Rc.apply(&version("1.2.1-rc.1")).unwrap(), version("1.2.1-rc.2"));
+        assert_eq!(Bump::Rc.apply(&version("1.2.1-beta.3")).unwrap(), version("1.2.1-rc.1"));
+        // an earlier channel than the current one starts the next patch's
+        assert_eq!(Bump::Beta.apply(&version("1.2.1-rc.2")).unwrap(), version("1.2.2-beta.1"));
+
+        // a channel without a number counts as its 0th pre-release
+        assert_eq!(next_prerelease(&version("1.0.0-rc"), "rc").unwrap().as_str(), "rc.1");
+        assert_eq!(next_prerelease(&version("1.0.0-rc.4"), "rc").unwrap().as_str(), "rc.5");
+        assert_eq!(next_prerelease(&version("1.0.0-rc.4"), "alpha").unwrap().as_str(), "alpha.1");
+        assert_eq!(next_prerelease(&version("1.0.0"), "beta").unwrap().as_str(), "beta.1");
+    }
+
+    #[test]
+    fn bumps_on_a_channel_count_on_until_they_reach_a_new_version() {
+        let nightly = Some("nightly");
+        assert_eq!(Bump::Patch.apply_on(&version("1.2.0"), nightly).unwrap(), version("1.2.1-nightly.1"));
+        assert_eq!(Bump::Patch.apply_on(&version("1.2.1-nightly.1"), nightly).unwrap(), version("1.2.1-nightly.2"));
+        assert_eq!(Bump::Minor.apply_on(&version("1.2.1-nightly.2"), nightly).unwrap(), version("1.3.0-nightly.1"));
+        assert_eq!(Bump::Rc.apply_on(&version("1.2.0"), nightly).unwrap(), version("1.2.1-rc.1"));
+    }
+
+    #[test]
+    fn invalid_channels_are_config_errors() {
+        let error = Bump::Patch.apply_on(&Version::new(1, 2, 0), Some("night ly")).unwrap_err();
+        let is_channel = |config: &ConfigError| config.key.as_deref() == Some("pre-release");
+        assert!(matches!(&error, ArmoryError::Config(config) if is_channel(config)), "{}", error);
+    }
+}
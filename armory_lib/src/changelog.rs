@@ -0,0 +1,231 @@
+//! Changelog sections written from conventional commits.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{conventional::ConventionalCommit, git::Commit, is_false};
+
+/// The heading changelogs start with, which new sections go under.
+const TITLE: &str = "# Changelog";
+
+/// The header of the section for changes not released yet, which the next
+/// section written replaces.
+pub const UNRELEASED: &str = "Unreleased";
+
+/// How changelogs and release notes are written, from armory.toml's
+/// `[changelog]` table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// The files each crate's changelog is written to, relative to its
+    /// directory.
+    #[serde(default = "default_paths", skip_serializing_if = "is_default_paths")]
+    pub paths: Vec<String>,
+    /// The sections of a release's notes, in order.
+    #[serde(default = "default_sections", skip_serializing_if = "is_default_sections")]
+    pub sections: Vec<Section>,
+    /// The header of each release's section, where `{tag}`, `{version}`,
+    /// `{crate}` and `{date}` expand to the release's.
+    #[serde(default = "default_header", skip_serializing_if = "is_default_header")]
+    pub header: String,
+    /// Credit the author of each entry.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub authors: bool,
+    /// Mention the pull request each entry came from, when there is one.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub pull_requests: bool,
+    /// The URL pull request numbers link to, where `{number}` expands to
+    /// the number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_request_url: Option<String>,
+    /// Crates whose changes go in another crate's changelog rather than
+    /// their own, keyed by that crate.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+/// A section of release notes, listing the commits of some types.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Section {
+    pub title: String,
+    /// The conventional commit types the section lists. `breaking` stands
+    /// for breaking changes of any type, which go there first.
+    pub types: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            paths: default_paths(),
+            sections: default_sections(),
+            header: default_header(),
+            authors: false,
+            pull_requests: true,
+            pull_request_url: None,
+            groups: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_paths() -> Vec<String> {
+    vec!["CHANGELOG.md".to_string()]
+}
+
+fn is_default_paths(paths: &Vec<String>) -> bool {
+    *paths == default_paths()
+}
+
+fn default_sections() -> Vec<Section> {
+    [("Breaking changes", &["breaking"][..]), ("Features", &["feat"]), ("Fixes", &["fix", "perf"])]
+        .into_iter()
+        .map(|(title, types)| Section {
+            title: title.to_string(),
+            types: types.iter().map(|kind| kind.to_string()).collect(),
+        })
+        .collect()
+}
+
+fn is_default_sections(sections: &Vec<Section>) -> bool {
+    *sections == default_sections()
+}
+
+fn default_header() -> String {
+    "{tag} ({date})".to_string()
+}
+
+fn is_default_header(header: &String) -> bool {
+    *header == default_header()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+impl Config {
+    /// The header of the section for a release of `crate_name` at
+    /// `version`, tagged `tag`.
+    pub fn header(&self, tag: &str, crate_name: &str, version: &semver::Version, date: &str) -> String {
+        self.header
+            .replace("{tag}", tag)
+            .replace("{crate}", crate_name)
+            .replace("{version}", &version.to_string())
+            .replace("{date}", date)
+    }
+
+    /// The crate whose changelog `crate_name`'s changes go in, if it's
+    /// grouped with another.
+    pub fn group_of(&self, crate_name: &str) -> Option<&str> {
+        self.groups
+            .iter()
+            .find(|(_, grouped)| grouped.iter().any(|name| name == crate_name))
+            .map(|(owner, _)| owner.as_str())
+    }
+
+    fn entry(&self, commit: &ConventionalCommit, source: &Commit) -> String {
+        let short_sha = &source.sha[..source.sha.len().min(7)];
+        let reference = match commit.pull_request.filter(|_| self.pull_requests) {
+            Some(number) => match &self.pull_request_url {
+                Some(url) => format!("[#{}]({}), {}", number, url.replace("{number}", &number.to_string()), short_sha),
+                None => format!("#{}, {}", number, short_sha),
+            },
+            None => short_sha.to_string(),
+        };
+        let mut entry = match &commit.scope {
+            Some(scope) => format!("- **{}:** {} ({})", scope, commit.description, reference),
+            None => format!("- {} ({})", commit.description, reference),
+        };
+        if self.authors && !source.author.is_empty() {
+            entry.push_str(&format!(" by {}", source.author));
+        }
+        entry
+    }
+}
+
+/// A changelog section headed `header`, with the [`notes`] for `commits`.
+pub fn section(header: &str, commits: &[Commit], config: &Config) -> String {
+    format!("## {}\n\n{}", header, notes(commits, config))
+}
+
+/// Release notes listing the commits among `commits` that fall in one of
+/// the sections of `config`, several per commit for squash merges.
+/// Commits that don't follow the conventional commit format, or are of
+/// other types, are left out.
+pub fn notes(commits: &[Commit], config: &Config) -> String {
+    let mut entries: Vec<Vec<String>> = vec![Vec::new(); config.sections.len()];
+    for commit in commits {
+        for conventional in ConventionalCommit::parse_all(&commit.message) {
+            let has = |section: &Section, kind: &str| section.types.iter().any(|listed| listed == kind);
+            let index = config
+                .sections
+                .iter()
+                .position(|section| conventional.breaking && has(section, "breaking"))
+                .or_else(|| config.sections.iter().position(|section| has(section, &conventional.kind)));
+            if let Some(index) = index {
+                entries[index].push(config.entry(&conventional, commit));
+            }
+        }
+    }
+
+    let notes: Vec<String> = config
+        .sections
+        .iter()
+        .zip(entries)
+        .filter(|(_, entries)| !entries.is_empty())
+        .map(|(section, entries)| format!("### {}\n\n{}\n", section.title, entries.join("\n")))
+        .collect();
+    if notes.is_empty() {
+        return "No notable changes.\n".to_string();
+    }
+    notes.join("\n")
+}
+
+/// `changelog` with `section` added as its newest entry, right below the
+/// title, in place of an [`UNRELEASED`] section if that's the newest. An
+/// empty changelog gets a title first.
+pub fn prepend(changelog: &str, section: &str) -> String {
+    let unreleased = format!("## {}\n", UNRELEASED);
+    if let Some(start) = changelog.find(&unreleased).filter(|start| is_newest(changelog, *start)) {
+        let rest = &changelog[start + unreleased.len()..];
+        let end = rest.find("\n## ").map_or(changelog.len(), |end| start + unreleased.len() + end + 1);
+        let before = match changelog[..start].trim_end() {
+            "" => String::new(),
+            before => format!("{}\n\n", before),
+        };
+        return match &changelog[end..] {
+            "" => format!("{}{}", before, section),
+            after => format!("{}{}\n{}", before, section, after),
+        };
+    }
+
+    let Some(title_end) = changelog.find(TITLE).map(|start| start + TITLE.len()) else {
+        return if changelog.trim().is_empty() {
+            format!("{}\n\n{}", TITLE, section)
+        } else {
+            format!("{}\n{}", section, changelog)
+        };
+    };
+
+    // keep any introduction under the title above the entries
+    let body = &changelog[title_end..];
+    let insert_at = title_end + body.find("\n## ").map(|start| start + 1).unwrap_or(body.len());
+    let (before, after) = changelog.split_at(insert_at);
+    let before = before.trim_end();
+    if after.is_empty() {
+        format!("{}\n\n{}", before, section)
+    } else {
+        format!("{}\n\n{}\n{}", before, section, after)
+    }
+}
+
+/// Whether a section starts at `start` in `changelog` and is its newest,
+/// with no other section above it.
+fn is_newest(changelog: &str, start: usize) -> bool {
+    let above = &changelog[..start];
+    (above.is_empty() || above.ends_with('\n')) && !above.starts_with("## ") && !above.contains("\n## ")
+}
@@ -0,0 +1,512 @@
+//! Reading armory's config, with errors that point at the offending line
+//! and key, and suggest a fix where they can, and the user's personal
+//! defaults from `~/.config/armory/config.toml`, and showing where each
+//! setting in effect came from.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{config_path, workspace_members, ArmoryError, ArmoryTOML, TokenSource, CRATES_IO_REGISTRY};
+
+/// When to color output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// When writing to a terminal, unless `NO_COLOR` or the like says not
+    /// to.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// The user's personal defaults, which a workspace's own config
+/// overrides. They're never written to the workspace's config.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UserConfig {
+    #[serde(default)]
+    pub color: ColorChoice,
+    /// Whether to ask before rewriting manifests or rolling back. Turning
+    /// it off is like always passing `--yes`.
+    #[serde(default = "default_true")]
+    pub confirm: bool,
+    /// The registry to publish to when the workspace doesn't name one.
+    #[serde(default)]
+    pub registry: Option<String>,
+    /// Where tokens come from for registries the workspace has no token
+    /// source for, keyed by registry name.
+    #[serde(default)]
+    pub tokens: BTreeMap<String, TokenSource>,
+}
+
+impl Default for UserConfig {
+    fn default() -> Self {
+        UserConfig { color: ColorChoice::default(), confirm: true, registry: None, tokens: BTreeMap::new() }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Where the user's config lives: `armory/config.toml` in
+/// `$XDG_CONFIG_HOME`, or else in `~/.config`.
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?).join(".config"),
+    };
+    Some(config_home.join("armory").join("config.toml"))
+}
+
+/// Loads the user's config, or the defaults if they have none.
+pub fn load_user_config() -> Result<UserConfig, ConfigError> {
+    let Some(path) = user_config_path().filter(|path| path.exists()) else {
+        return Ok(UserConfig::default());
+    };
+    let source = fs::read_to_string(&path).map_err(|e| ConfigError::read(&path, e))?;
+    parse_as(&path, &source)
+}
+
+/// What's wrong with a config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigErrorKind {
+    /// The file couldn't be read.
+    Read(String),
+    /// The file isn't valid TOML.
+    Syntax(String),
+    /// A key armory doesn't know, with the closest one it does.
+    UnknownKey { key: String, suggestion: Option<String> },
+    /// A value that isn't one of the allowed ones, with the closest one.
+    UnknownValue { value: String, suggestion: Option<String> },
+    /// A required key that isn't set.
+    MissingKey(String),
+    /// A value of the wrong type, or one that doesn't parse.
+    InvalidValue(String),
+}
+
+/// An error in armory's config, and where in the file it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub kind: ConfigErrorKind,
+    /// The dotted key the error is at, such as `hooks.post-bump`.
+    pub key: Option<String>,
+    snippet: Option<Box<Snippet>>,
+}
+
+/// Where in the file an error is, to show under the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Snippet {
+    /// The 1-based line and column of the error.
+    line: usize,
+    column: usize,
+    /// The text of the line.
+    text: String,
+    /// How many characters of the line the error spans.
+    width: usize,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        match &self.snippet {
+            Some(snippet) => write!(f, "{}:{}:{}: ", name, snippet.line, snippet.column)?,
+            None => write!(f, "{}: ", name)?,
+        }
+
+        let key = self.key.as_deref().unwrap_or_default();
+        let help = match &self.kind {
+            ConfigErrorKind::Read(error) => return write!(f, "failed to read the config: {}", error),
+            ConfigErrorKind::Syntax(message) => {
+                write!(f, "invalid TOML: {}", message)?;
+                None
+            }
+            ConfigErrorKind::UnknownKey { key, suggestion } => {
+                write!(f, "unknown key `{}`", key)?;
+                suggestion.as_ref().map(|suggestion| format!("did you mean `{}`?", suggestion))
+            }
+            ConfigErrorKind::UnknownValue { value, suggestion } => {
+                write!(f, "`{}` can't be `{}`", key, value)?;
+                suggestion.as_ref().map(|suggestion| format!("did you mean `{}`?", suggestion))
+            }
+            ConfigErrorKind::MissingKey(missing) => {
+                write!(f, "missing `{}`", missing)?;
+                None
+            }
+            ConfigErrorKind::InvalidValue(message) => {
+                write!(f, "invalid value for `{}`: {}", key, message)?;
+                None
+            }
+        };
+
+        if let Some(snippet) = &self.snippet {
+            let gutter = " ".repeat(snippet.line.to_string().len());
+            write!(f, "\n{} |\n{} | {}", gutter, snippet.line, snippet.text)?;
+            write!(f, "\n{} | {}{}", gutter, " ".repeat(snippet.column - 1), "^".repeat(snippet.width.max(1)))?;
+            if let Some(help) = &help {
+                write!(f, "\n{} = help: {}", gutter, help)?;
+            }
+        } else if let Some(help) = &help {
+            write!(f, ", {}", help)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ConfigError {
+    /// A `ConfigError` for a file that couldn't be read.
+    pub fn read(path: &Path, error: impl fmt::Display) -> Self {
+        ConfigError { path: path.to_path_buf(), kind: ConfigErrorKind::Read(error.to_string()), key: None, snippet: None }
+    }
+
+    /// A `ConfigError` for a `key` whose value isn't valid, as `message`
+    /// explains.
+    pub fn invalid_value(path: &Path, key: &str, message: impl fmt::Display) -> Self {
+        ConfigError {
+            path: path.to_path_buf(),
+            kind: ConfigErrorKind::InvalidValue(message.to_string()),
+            key: Some(key.to_string()),
+            snippet: None,
+        }
+    }
+
+    /// The 1-based line and column of the error, if it's at a known place.
+    pub fn location(&self) -> Option<(usize, usize)> {
+        self.snippet.as_ref().map(|snippet| (snippet.line, snippet.column))
+    }
+
+    /// A `ConfigError` for the error `message` at `span` of `source`,
+    /// which is either invalid TOML or, if `syntax` is false, TOML that
+    /// isn't a valid config.
+    fn new(path: &Path, source: &str, message: &str, span: Option<Range<usize>>, syntax: bool) -> Self {
+        let message = message.trim().replace('\n', ", ");
+        let span = span.filter(|span| span.end <= source.len());
+        let table = span.as_ref().map(|span| table_at(source, span.start)).unwrap_or_default();
+        let key_at = |span: &Range<usize>| {
+            let key = key_at(source, span.start)?;
+            Some(join_key(&table, &key))
+        };
+
+        let (kind, key) = if syntax {
+            (ConfigErrorKind::Syntax(message), None)
+        } else if let Some(unknown) = between_ticks(&message, "unknown field ") {
+            let suggestion = closest(&unknown, &expected(&message));
+            let key = join_key(&table, &unknown);
+            (ConfigErrorKind::UnknownKey { key: key.clone(), suggestion }, Some(key))
+        } else if let Some(value) = between_ticks(&message, "unknown variant ") {
+            let suggestion = closest(&value, &expected(&message));
+            (ConfigErrorKind::UnknownValue { value, suggestion }, span.as_ref().and_then(key_at))
+        } else if let Some(missing) = between_ticks(&message, "missing field ") {
+            let key = join_key(&table, &missing);
+            (ConfigErrorKind::MissingKey(key.clone()), Some(key))
+        } else {
+            (ConfigErrorKind::InvalidValue(message), span.as_ref().and_then(key_at))
+        };
+
+        let snippet = span.map(|span| {
+            let line_start = source[..span.start].rfind('\n').map(|start| start + 1).unwrap_or(0);
+            let line_end = source[span.start..].find('\n').map(|end| span.start + end).unwrap_or(source.len());
+            Box::new(Snippet {
+                line: source[..span.start].matches('\n').count() + 1,
+                column: source[line_start..span.start].chars().count() + 1,
+                text: source[line_start..line_end].trim_end().to_string(),
+                width: source[span.start..span.end.min(line_end)].chars().count(),
+            })
+        });
+        ConfigError { path: path.to_path_buf(), kind, key, snippet }
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    workspace: Workspace,
+}
+
+#[derive(Deserialize)]
+struct Workspace {
+    metadata: Metadata,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    armory: ArmoryTOML,
+}
+
+/// Parses the config in `source`, read from `path`. A Cargo.toml has it
+/// under `[workspace.metadata.armory]`, anything else is an armory.toml.
+pub(crate) fn parse(path: &Path, source: &str) -> Result<ArmoryTOML, ConfigError> {
+    if path.ends_with("Cargo.toml") {
+        return parse_as::<Manifest>(path, source).map(|manifest| manifest.workspace.metadata.armory);
+    }
+    parse_as(path, source)
+}
+
+fn parse_as<T: DeserializeOwned>(path: &Path, source: &str) -> Result<T, ConfigError> {
+    if let Err(e) = source.parse::<toml_edit::Document>() {
+        return Err(ConfigError::new(path, source, e.message(), e.span(), true));
+    }
+    toml::from_str(source).map_err(|e| ConfigError::new(path, source, e.message(), e.span(), false))
+}
+
+/// The dotted path of the table the byte `offset` of `source` is in,
+/// going by the last table header before it, or on its line.
+fn table_at(source: &str, offset: usize) -> String {
+    let line_end = source[offset..].find('\n').map(|end| offset + end).unwrap_or(source.len());
+    source[..line_end]
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| line.starts_with('['))
+        .map(|header| {
+            let header = header.split('#').next().unwrap_or_default().trim();
+            header.trim_start_matches('[').trim_end_matches(']').split('.').map(unquote).collect::<Vec<_>>().join(".")
+        })
+        .unwrap_or_default()
+}
+
+/// The key on the line the byte `offset` of `source` is on.
+fn key_at(source: &str, offset: usize) -> Option<String> {
+    let line_start = source[..offset].rfind('\n').map(|start| start + 1).unwrap_or(0);
+    let (key, _) = source[line_start..].split_once('=')?;
+    Some(key.split('.').map(unquote).collect::<Vec<_>>().join("."))
+}
+
+fn join_key(table: &str, key: &str) -> String {
+    if table.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", table, key)
+    }
+}
+
+fn unquote(key: &str) -> String {
+    key.trim().trim_matches('"').trim_matches('\'').to_string()
+}
+
+/// The text between the backticks following `prefix` in `message`.
+fn between_ticks(message: &str, prefix: &str) -> Option<String> {
+    let rest = message.strip_prefix(prefix)?.strip_prefix('`')?;
+    Some(rest[..rest.find('`')?].to_string())
+}
+
+/// The names serde lists after "expected" in `message`.
+fn expected(message: &str) -> Vec<String> {
+    let Some((_, expected)) = message.split_once("expected") else {
+        return Vec::new();
+    };
+    expected.split('`').skip(1).step_by(2).map(|name| name.to_string()).collect()
+}
+
+/// The one of `candidates` closest to `name`, if any is close enough to
+/// be what was meant.
+fn closest(name: &str, candidates: &[String]) -> Option<String> {
+    candidates
+        .iter()
+        .map(|candidate| (strsim::jaro_winkler(name, candidate), candidate))
+        .filter(|(similarity, _)| *similarity > 0.8)
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, candidate)| candidate.clone())
+}
+
+/// Where a setting in effect came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// The workspace's armory.toml, or Cargo.toml metadata.
+    Workspace(PathBuf),
+    /// The `[profile.NAME]` selected with `--profile`.
+    Profile(String),
+    /// The user's config.
+    User(PathBuf),
+    /// An environment variable.
+    Env(String),
+    /// armory's default.
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Workspace(path) if path.ends_with("Cargo.toml") => {
+                write!(f, "Cargo.toml [workspace.metadata.armory]")
+            }
+            Source::Workspace(path) => write!(f, "{}", path.file_name().unwrap_or_default().to_string_lossy()),
+            Source::Profile(name) => write!(f, "--profile {}", name),
+            Source::User(path) => write!(f, "{}", path.display()),
+            Source::Env(var) => write!(f, "${}", var),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// A setting in effect, with where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    /// The dotted key, such as `hooks.post-bump`.
+    pub key: String,
+    /// The value as TOML, except for secrets which are only said to be set.
+    pub value: String,
+    pub source: Source,
+    /// The key that decided the value, for settings that are worked out
+    /// from others.
+    pub via: Option<String>,
+}
+
+impl Setting {
+    fn new(key: impl Into<String>, value: impl Into<String>, source: Source) -> Self {
+        Setting { key: key.into(), value: value.into(), source, via: None }
+    }
+}
+
+/// Every setting of `armory_toml` that isn't at its default, then the
+/// user's and the environment's, each with where it came from. `profile`
+/// is the profile applied to `armory_toml`, if any.
+pub fn effective(dir: &Path, armory_toml: &ArmoryTOML, profile: Option<&str>) -> Vec<Setting> {
+    let workspace = Source::Workspace(config_path(dir));
+    let from_profile = profile
+        .and_then(|name| Some((name, armory_toml.profile.get(name)?)))
+        .map(|(name, profile)| (name, flatten(profile)))
+        .unwrap_or_default();
+
+    let mut settings: Vec<Setting> = flatten(armory_toml)
+        .into_iter()
+        // the profiles themselves aren't in effect, and the history isn't a
+        // setting
+        .filter(|(key, _)| !key.starts_with("profile.") && !key.starts_with("releases"))
+        .map(|(key, value)| {
+            let source = match from_profile.1.iter().any(|(set, _)| *set == key) {
+                true => Source::Profile(from_profile.0.to_string()),
+                false => workspace.clone(),
+            };
+            Setting::new(key, value, source)
+        })
+        .collect();
+
+    let user_path = user_config_path().unwrap_or_default();
+    let user_keys: Vec<String> = fs::read_to_string(&user_path)
+        .ok()
+        .and_then(|source| toml::from_str::<toml::Table>(&source).ok())
+        .map(|table| table.keys().cloned().collect())
+        .unwrap_or_default();
+    let user_source = |key: &str| match user_keys.iter().any(|set| set == key) {
+        true => Source::User(user_path.clone()),
+        false => Source::Default,
+    };
+    let user = &armory_toml.user;
+    let color = match user.color {
+        ColorChoice::Auto => "auto",
+        ColorChoice::Always => "always",
+        ColorChoice::Never => "never",
+    };
+    settings.push(Setting::new("color", format!("{:?}", color), user_source("color")));
+    settings.push(Setting::new("confirm", user.confirm.to_string(), user_source("confirm")));
+    if let Some(registry) = user.registry.as_ref().filter(|_| armory_toml.registry.is_none()) {
+        settings.push(Setting::new("registry", format!("{:?}", registry), user_source("registry")));
+    }
+    for (registry, token) in user.tokens.iter().filter(|(registry, _)| !armory_toml.tokens.contains_key(*registry)) {
+        let value: String = flatten(token)
+            .into_iter()
+            .map(|(key, value)| format!("{{ {} = {} }}", key, value))
+            .collect();
+        settings.push(Setting::new(format!("tokens.{}", registry), value, user_source("tokens")));
+    }
+
+    let set = |var: &str| std::env::var(var).ok().filter(|value| !value.is_empty());
+    let secrets = [
+        ("token", &["ARMORY_TOKEN"][..]),
+        ("github-token", &["GITHUB_TOKEN", "GH_TOKEN"]),
+        ("gitlab-token", &["GITLAB_TOKEN", "CI_JOB_TOKEN"]),
+    ];
+    for (key, vars) in secrets {
+        if let Some(var) = vars.iter().find(|var| set(var).is_some()) {
+            settings.push(Setting::new(key, "(set)", Source::Env(var.to_string())));
+        }
+    }
+    // used when armory.toml doesn't say
+    let fallbacks = [
+        ("github-repo", "GITHUB_REPOSITORY", armory_toml.github_repo.is_some()),
+        ("gitlab-project", "CI_PROJECT_PATH", armory_toml.gitlab_project.is_some()),
+        ("gitlab-url", "CI_SERVER_URL", armory_toml.gitlab_url.is_some()),
+    ];
+    for (key, var, configured) in fallbacks {
+        if let Some(value) = set(var).filter(|_| !configured) {
+            settings.push(Setting::new(key, format!("{:?}", value), Source::Env(var.to_string())));
+        }
+    }
+    settings
+}
+
+/// The registry each member of the workspace at `dir` is published to,
+/// keyed by crate name, with the setting that picked it.
+pub fn crate_registries(dir: &Path, armory_toml: &ArmoryTOML, profile: Option<&str>) -> Result<Vec<Setting>, ArmoryError> {
+    let workspace = Source::Workspace(config_path(dir));
+    let profile_registry = profile
+        .and_then(|name| armory_toml.profile.get(name))
+        .is_some_and(|profile| profile.registry.is_some());
+    Ok(workspace_members(dir)?
+        .into_iter()
+        .map(|member| {
+            let name = &member.name;
+            let crate_registry = armory_toml.crates.get(name).and_then(|config| config.registry.as_ref());
+            let (registry, via, source) = if let Some(registry) = crate_registry {
+                (Some(registry), Some(format!("crate.{}.registry", name)), workspace.clone())
+            } else if let Some(registry) = armory_toml.crate_registries.get(name) {
+                (Some(registry), Some(format!("crate-registries.{}", name)), workspace.clone())
+            } else if let Some(registry) = &armory_toml.registry {
+                let source = match profile_registry {
+                    true => Source::Profile(profile.unwrap_or_default().to_string()),
+                    false => workspace.clone(),
+                };
+                (Some(registry), Some("registry".to_string()), source)
+            } else if let Some(registry) = &armory_toml.user.registry {
+                (Some(registry), Some("registry".to_string()), Source::User(user_config_path().unwrap_or_default()))
+            } else {
+                (None, None, Source::Default)
+            };
+            let registry = registry.map(String::as_str).unwrap_or(CRATES_IO_REGISTRY);
+            Setting { key: name.clone(), value: format!("{:?}", registry), source, via }
+        })
+        .collect())
+}
+
+/// The leaf values of `value` serialized as TOML, keyed by their dotted
+/// keys, with arrays and inline tables written on one line.
+fn flatten<T: Serialize>(value: &T) -> Vec<(String, String)> {
+    let Ok(document) = toml::to_string(value).unwrap_or_default().parse::<toml_edit::Document>() else {
+        return Vec::new();
+    };
+    let mut leaves = Vec::new();
+    flatten_table("", document.as_table(), &mut leaves);
+    leaves
+}
+
+fn flatten_table(prefix: &str, table: &toml_edit::Table, leaves: &mut Vec<(String, String)>) {
+    for (key, item) in table.iter() {
+        let key = join_key(prefix, key);
+        let value = match item {
+            toml_edit::Item::None => continue,
+            toml_edit::Item::Table(table) => {
+                flatten_table(&key, table, leaves);
+                continue;
+            }
+            toml_edit::Item::Value(value) => value.clone(),
+            toml_edit::Item::ArrayOfTables(tables) => toml_edit::Value::Array(tables.clone().into_array()),
+        };
+        let mut value = value.decorated("", "");
+        match &mut value {
+            toml_edit::Value::Array(array) => array.fmt(),
+            toml_edit::Value::InlineTable(table) => table.fmt(),
+            _ => {}
+        }
+        leaves.push((key, value.to_string()));
+    }
+}
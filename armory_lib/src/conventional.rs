@@ -0,0 +1,220 @@
+//! Parsing of [conventional commits](https://www.conventionalcommits.org).
+
+use semver::Version;
+
+use crate::Bump;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConventionalCommit {
+    /// The commit type, e.g. `feat` or `fix`.
+    pub kind: String,
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub description: String,
+    /// The pull request the change came from, if the message ends with
+    /// one like `(#123)`.
+    pub pull_request: Option<u64>,
+}
+
+impl ConventionalCommit {
+    /// Parses a full commit message, returning `None` if its subject
+    /// doesn't follow the conventional commit format.
+    pub fn parse(message: &str) -> Option<Self> {
+        let mut lines = message.lines();
+        let (header, description) = lines.next()?.split_once(':')?;
+
+        let (header, bang) = match header.strip_suffix('!') {
+            Some(header) => (header, true),
+            None => (header, false),
+        };
+        let (kind, scope) = match header.split_once('(') {
+            Some((kind, scope)) => (kind, Some(scope.strip_suffix(')')?.trim().to_string())),
+            None => (header, None),
+        };
+        if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return None;
+        }
+
+        let breaking = bang || lines.any(|line| {
+            line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:")
+        });
+
+        let (description, pull_request) = split_pull_request(description.trim());
+        Some(ConventionalCommit {
+            kind: kind.to_ascii_lowercase(),
+            scope,
+            breaking,
+            description: description.to_string(),
+            pull_request,
+        })
+    }
+
+    /// Parses every change in a full commit message. Merge commits are
+    /// read from the pull request title in their body, and squash merges
+    /// that list the squashed commits as `*` or `-` bullets give one change
+    /// per conventional bullet instead of one for the whole squash.
+    /// Trailers such as `Co-authored-by` are skipped.
+    pub fn parse_all(message: &str) -> Vec<Self> {
+        let mut lines = message.lines();
+        let Some(subject) = lines.next() else {
+            return Vec::new();
+        };
+        if let Some(number) = merged_pull_request(subject) {
+            let body = lines.skip_while(|line| line.trim().is_empty()).collect::<Vec<_>>().join("\n");
+            return Self::parse_all(&body)
+                .into_iter()
+                .map(|commit| ConventionalCommit { pull_request: commit.pull_request.or(Some(number)), ..commit })
+                .collect();
+        }
+
+        let squash = Self::parse(message);
+        let pull_request = squash.as_ref().and_then(|squash| squash.pull_request)
+            .or_else(|| split_pull_request(subject).1);
+        let mut changes: Vec<Self> = lines
+            .filter_map(|line| line.trim().strip_prefix("* ").or_else(|| line.trim().strip_prefix("- ")))
+            .filter(|bullet| !is_trailer(bullet))
+            .filter_map(Self::parse)
+            .map(|commit| ConventionalCommit { pull_request: commit.pull_request.or(pull_request), ..commit })
+            .collect();
+        match squash {
+            Some(squash) if changes.is_empty() => vec![squash],
+            // a breaking change footer can't be pinned on one bullet, so
+            // the squash is kept as a change of its own
+            Some(squash) if squash.breaking && !changes.iter().any(|change| change.breaking) => {
+                changes.insert(0, squash);
+                changes
+            }
+            _ => changes,
+        }
+    }
+
+    /// The bump this commit calls for on its own, before taking 0.x
+    /// versions into account.
+    fn bump(&self) -> Option<Bump> {
+        if self.breaking {
+            Some(Bump::Major)
+        } else if self.kind == "feat" {
+            Some(Bump::Minor)
+        } else if self.kind == "fix" || self.kind == "perf" {
+            Some(Bump::Patch)
+        } else {
+            None
+        }
+    }
+}
+
+/// Splits a trailing pull request number like ` (#123)` off a description.
+fn split_pull_request(description: &str) -> (&str, Option<u64>) {
+    let number = description
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once("(#"))
+        .and_then(|(rest, number)| Some((rest.trim_end(), number.parse().ok()?)));
+    match number {
+        Some((rest, number)) => (rest, Some(number)),
+        None => (description, None),
+    }
+}
+
+/// The pull request a `Merge pull request #123 from ...` subject merged.
+fn merged_pull_request(subject: &str) -> Option<u64> {
+    let number = subject.strip_prefix("Merge pull request #")?;
+    number.split_whitespace().next()?.parse().ok()
+}
+
+fn is_trailer(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    ["co-authored-by:", "signed-off-by:", "reviewed-by:"].iter().any(|trailer| line.starts_with(trailer))
+}
+
+/// Picks the bump called for by a set of commits: breaking changes are
+/// major, features minor, and fixes patch, shifted down a level for 0.x
+/// versions.
+/// Returns `None` if none of the commits warrant a release.
+pub fn suggest_bump<'a>(
+    current: &Version,
+    commits: impl IntoIterator<Item = &'a ConventionalCommit>,
+) -> Option<Bump> {
+    commits
+        .into_iter()
+        .filter_map(ConventionalCommit::bump)
+        .reduce(Bump::max)
+        .map(|bump| bump.for_version(current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(message: &str) -> ConventionalCommit {
+        ConventionalCommit::parse(message).unwrap()
+    }
+
+    #[test]
+    fn subjects_give_the_kind_scope_and_pull_request() {
+        let commit = parse("Feat(cli): add --only (#12)");
+        assert_eq!(commit.kind, "feat");
+        assert_eq!(commit.scope.as_deref(), Some("cli"));
+        assert_eq!(commit.description, "add --only");
+        assert_eq!(commit.pull_request, Some(12));
+        assert!(!commit.breaking);
+
+        let commit = parse("fix: handle (#abc)");
+        assert_eq!((commit.description.as_str(), commit.pull_request), ("handle (#abc)", None));
+    }
+
+    #[test]
+    fn breaking_changes_are_marked_by_a_bang_or_a_footer() {
+        assert!(parse("feat!: drop the old config").breaking);
+        assert!(parse("refactor(lib)!: rename Bump").breaking);
+        assert!(parse("refactor: rename Bump\n\nBREAKING CHANGE: Bump is now Level").breaking);
+        assert!(parse("fix: x\n\nBREAKING-CHANGE: y").breaking);
+        // only as a footer, not in the middle of a line
+        assert!(!parse("fix: x\n\nthis isn't a BREAKING CHANGE: really").breaking);
+    }
+
+    #[test]
+    fn malformed_subjects_arent_conventional() {
+        let messages = ["update the docs", ": no kind", "feat add: a space", "feat(cli: unclosed", "fix/cli: slash", ""];
+        for message in messages {
+            assert_eq!(ConventionalCommit::parse(message), None, "{:?}", message);
+        }
+    }
+
+    #[test]
+    fn merges_and_squashes_give_each_change() {
+        let changes = ConventionalCommit::parse_all("Merge pull request #7 from me/branch\n\nfeat: add a thing");
+        assert_eq!(changes, [ConventionalCommit { pull_request: Some(7), ..parse("feat: add a thing") }]);
+
+        let squash = "feat: the squash (#9)\n\n* fix: one\n* feat: two (#3)\n- not conventional\n\n\
+                      * Co-authored-by: someone <someone@example.com>";
+        let changes = ConventionalCommit::parse_all(squash);
+        assert_eq!(changes.len(), 2);
+        assert_eq!((changes[0].kind.as_str(), changes[0].pull_request), ("fix", Some(9)));
+        assert_eq!((changes[1].kind.as_str(), changes[1].pull_request), ("feat", Some(3)));
+
+        // a footer can't be pinned on a bullet, so the squash stays
+        let changes = ConventionalCommit::parse_all("feat: the squash\n\n* fix: one\n\nBREAKING CHANGE: gone");
+        assert_eq!(changes.iter().map(|change| change.breaking).collect::<Vec<_>>(), [true, false]);
+
+        assert_eq!(ConventionalCommit::parse_all("just a message"), []);
+        assert_eq!(ConventionalCommit::parse_all(""), []);
+    }
+
+    #[test]
+    fn the_largest_change_picks_the_bump() {
+        let commits = [parse("fix: a"), parse("feat: b"), parse("docs: c")];
+        assert_eq!(suggest_bump(&Version::new(1, 2, 0), &commits), Some(Bump::Minor));
+        assert_eq!(suggest_bump(&Version::new(1, 2, 0), &[parse("perf: a")]), Some(Bump::Patch));
+        assert_eq!(suggest_bump(&Version::new(1, 2, 0), &[parse("fix!: a"), parse("feat: b")]), Some(Bump::Major));
+        assert_eq!(suggest_bump(&Version::new(1, 2, 0), &[parse("docs: a"), parse("chore: b")]), None);
+        assert_eq!(suggest_bump(&Version::new(1, 2, 0), []), None);
+    }
+
+    #[test]
+    fn pre_1_0_versions_bump_a_level_lower() {
+        let current = Version::new(0, 4, 2);
+        assert_eq!(suggest_bump(&current, &[parse("feat!: a")]), Some(Bump::Minor));
+        assert_eq!(suggest_bump(&current, &[parse("feat: a")]), Some(Bump::Patch));
+        assert_eq!(suggest_bump(&current, &[parse("fix: a")]), Some(Bump::Patch));
+    }
+}
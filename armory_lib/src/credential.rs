@@ -0,0 +1,174 @@
+//! Getting tokens from cargo credential providers, such as ones backed by
+//! an OS keychain or a password manager.
+//!
+//! External providers speak version 1 of cargo's credential provider
+//! protocol: JSON lines over stdin and stdout.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Command, Stdio},
+};
+
+use cargo::{util::config::StringList, Config};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{registry, ArmoryError, CRATES_IO_REGISTRY};
+
+#[derive(Debug, Deserialize)]
+struct Hello {
+    v: Vec<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+enum Response {
+    Ok { token: String },
+    Err(ProviderError),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+enum ProviderError {
+    NotFound,
+    UrlNotSupported,
+    Other { message: String },
+    #[serde(other)]
+    Unknown,
+}
+
+/// Asks the credential provider `provider`, a program followed by its
+/// arguments, for the token of `registry`.
+pub fn provider_token(provider: &[String], registry: Option<&str>) -> Result<String, ArmoryError> {
+    let (program, args) = provider
+        .split_first()
+        .ok_or_else(|| "The credential provider is empty".to_string())?;
+    let name = registry.unwrap_or(CRATES_IO_REGISTRY);
+
+    // cargo's built in providers live inside cargo, except for this one
+    // which is simple enough to run here
+    if let Some(builtin) = program.strip_prefix("cargo:") {
+        return match builtin {
+            "token-from-stdout" => token_from_stdout(args),
+            _ => Err(format!(
+                "The credential provider of {} is cargo:{}, which is built into cargo; \
+                 set `backend = \"cargo\"` in armory.toml to use it",
+                name, builtin
+            ).into()),
+        };
+    }
+
+    let mut child = Command::new(program)
+        .arg("--cargo-plugin")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run the credential provider {}: {}", program, e))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+    let mut read_message = || {
+        let mut line = String::new();
+        stdout
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to read from the credential provider {}: {}", program, e))?;
+        Ok::<_, String>(line)
+    };
+
+    let hello: Hello = serde_json::from_str(&read_message()?)
+        .map_err(|e| format!("{} isn't a cargo credential provider: {}", program, e))?;
+    if !hello.v.contains(&1) {
+        return Err(format!("{} doesn't support version 1 of the credential provider protocol", program).into());
+    }
+
+    let request = json!({
+        "v": 1,
+        "registry": {
+            "index-url": format!("sparse+{}", registry::index_url(registry)?),
+            "name": name,
+        },
+        "kind": "get",
+        "operation": "read",
+        "args": args,
+    });
+    writeln!(stdin, "{}", request)
+        .map_err(|e| format!("Failed to write to the credential provider {}: {}", program, e))?;
+
+    let response = read_message()?;
+    // the provider exits once its stdin is closed
+    drop(stdin);
+    let _ = child.wait();
+
+    let response: Response = serde_json::from_str(&response)
+        .map_err(|e| format!("Invalid response from the credential provider {}: {}", program, e))?;
+    match response {
+        Response::Ok { token } => Ok(token),
+        Response::Err(ProviderError::NotFound) => {
+            Err(format!("The credential provider {} has no token for {}", program, name).into())
+        }
+        Response::Err(ProviderError::UrlNotSupported) => {
+            Err(format!("The credential provider {} doesn't support {}", program, name).into())
+        }
+        Response::Err(ProviderError::Other { message }) => Err(format!("{}: {}", program, message).into()),
+        Response::Err(ProviderError::Unknown) => Err(format!("The credential provider {} failed", program).into()),
+    }
+}
+
+/// Stores `token` for `registry` with the installed `cargo login`, which
+/// hands it to the registry's credential provider. The token goes through
+/// stdin rather than the arguments, which would show up in the process
+/// list.
+pub fn cargo_login(registry: Option<&str>, token: &str) -> Result<(), ArmoryError> {
+    let mut cargo = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()));
+    cargo.arg("login").stdin(Stdio::piped()).stdout(Stdio::null());
+    if let Some(registry) = registry {
+        cargo.args(["--registry", registry]);
+    }
+    let mut child = cargo.spawn().map_err(|e| format!("Failed to run cargo: {}", e))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    writeln!(stdin, "{}", token).map_err(|e| format!("Failed to write to cargo login: {}", e))?;
+    drop(stdin);
+    let status = child.wait().map_err(|e| format!("Failed to wait for cargo: {}", e))?;
+    if !status.success() {
+        return Err(format!("cargo login failed for {}", registry.unwrap_or(CRATES_IO_REGISTRY)).into());
+    }
+    Ok(())
+}
+
+/// `cargo:token-from-stdout`, which runs a command printing the token.
+fn token_from_stdout(command: &[String]) -> Result<String, ArmoryError> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| "cargo:token-from-stdout needs a command to run".to_string())?;
+    let output = Command::new(program)
+        .args(args)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!("{} failed", command.join(" ")).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The credential provider cargo's config sets for `registry`, unless it's
+/// cargo's own credentials file.
+pub fn cargo_provider(registry: Option<&str>) -> Result<Option<Vec<String>>, ArmoryError> {
+    let cfg = Config::default().map_err(|e| ArmoryError::Cargo(format!("{:#}", e)))?;
+    let key = match registry {
+        Some(registry) => format!("registries.{}.credential-provider", registry),
+        None => "registry.credential-provider".to_string(),
+    };
+
+    let provider = match cfg.get::<Option<StringList>>(&key).map_err(|e| e.to_string())? {
+        Some(provider) => Some(provider.as_slice().to_vec()),
+        // cargo tries the global providers last to first
+        None => cfg
+            .get::<Option<Vec<String>>>("registry.global-credential-providers")
+            .map_err(|e| e.to_string())?
+            .and_then(|providers| {
+                let provider = providers.last()?;
+                Some(provider.split_whitespace().map(|arg| arg.to_string()).collect())
+            }),
+    };
+    Ok(provider.filter(|provider| provider.first().is_some_and(|program| program != "cargo:token")))
+}
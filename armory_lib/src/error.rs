@@ -0,0 +1,102 @@
+//! The errors the library returns, saying what armory was doing and to
+//! which crate or file when it failed.
+
+use std::{collections::BTreeMap, fmt, io, path::PathBuf};
+
+use crate::ConfigError;
+
+/// Something armory couldn't do.
+#[derive(Debug, thiserror::Error)]
+pub enum ArmoryError {
+    /// A file or directory couldn't be read, written or removed.
+    #[error("Failed to {action} {}: {source}", path.display())]
+    Io {
+        /// What was being done to it, such as "read" or "write".
+        action: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A manifest or other file isn't valid, or lacks what armory needs
+    /// from it.
+    #[error("Failed to parse {}: {message}", path.display())]
+    Parse { path: PathBuf, message: String },
+    /// armory's config couldn't be loaded.
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    /// cargo couldn't be set up, as with a broken `.cargo/config.toml`.
+    #[error("Failed to set up cargo: {0}")]
+    Cargo(String),
+    /// A crate failed at a stage of the release.
+    #[error("Failed to {stage} {name}: {message}")]
+    Crate { name: String, stage: Stage, message: String },
+    /// Local crates depend on each other, so none can be published first.
+    #[error("These crates depend on each other, so none can be published first: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    /// The publish was interrupted before getting to a crate.
+    #[error("Interrupted before publishing {0}")]
+    Interrupted(String),
+    /// The publish ran out of time before getting to a crate.
+    #[error("The publish ran out of time before {0}")]
+    TimedOut(String),
+    /// Stepping through a publish, the user stopped after a crate.
+    #[error("Stopped after publishing {0}")]
+    Stopped(String),
+    /// A publish that kept going left crates unpublished, with why.
+    #[error("{}", unpublished(.0))]
+    Unpublished(BTreeMap<String, String>),
+    /// A git command failed.
+    #[error("{0}")]
+    Git(String),
+    /// Anything else, described by its message.
+    #[error("{0}")]
+    Other(String),
+}
+
+impl ArmoryError {
+    /// An [`ArmoryError::Io`] for `path`.
+    pub fn io(action: &'static str, path: impl Into<PathBuf>, source: io::Error) -> Self {
+        ArmoryError::Io { action, path: path.into(), source }
+    }
+
+    /// An [`ArmoryError::Parse`] for `path`.
+    pub fn parse(path: impl Into<PathBuf>, message: impl fmt::Display) -> Self {
+        ArmoryError::Parse { path: path.into(), message: message.to_string() }
+    }
+
+    /// An [`ArmoryError::Crate`] for `name`.
+    pub fn at(stage: Stage, name: impl Into<String>, message: impl fmt::Display) -> Self {
+        ArmoryError::Crate { name: name.into(), stage, message: message.to_string() }
+    }
+}
+
+impl From<String> for ArmoryError {
+    fn from(message: String) -> Self {
+        ArmoryError::Other(message)
+    }
+}
+
+fn unpublished(failed: &BTreeMap<String, String>) -> String {
+    let mut summary = format!("{} crate(s) weren't published:", failed.len());
+    for (name, reason) in failed {
+        summary.push_str(&format!("\n    {}: {}", name, reason));
+    }
+    summary
+}
+
+/// The stage of a release a crate failed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Package,
+    Publish,
+    Yank,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Package => "package",
+            Stage::Publish => "publish",
+            Stage::Yank => "yank",
+        })
+    }
+}
@@ -0,0 +1,399 @@
+//! Thin wrappers around the `git` command line.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::ArmoryError;
+
+/// Runs git in `dir` and returns its stdout.
+pub fn git(dir: &Path, args: &[&str]) -> Result<String, ArmoryError> {
+    crate::trace!("ARMORY: running git {}", args.join(" "));
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .map_err(|e| ArmoryError::Git(format!("Failed to run git: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(ArmoryError::Git(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The full SHA of the commit checked out in `dir`.
+pub fn head_sha(dir: &Path) -> Result<String, ArmoryError> {
+    Ok(git(dir, &["rev-parse", "HEAD"])?.trim().to_string())
+}
+
+/// The most recent tag reachable from HEAD, if there is one.
+pub fn last_tag(dir: &Path) -> Option<String> {
+    last_tag_matching(dir, "*")
+}
+
+/// The most recent tag reachable from HEAD matching the glob `pattern`.
+pub fn last_tag_matching(dir: &Path, pattern: &str) -> Option<String> {
+    git(dir, &["describe", "--tags", "--abbrev=0", "--match", pattern])
+        .ok()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+}
+
+/// The submodules checked out under `dir`, relative to it.
+pub fn submodules(dir: &Path) -> Vec<PathBuf> {
+    git(dir, &["ls-files", "--stage"])
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| line.starts_with("160000 "))
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(_, path)| PathBuf::from(path))
+        .collect()
+}
+
+/// Splits `path` into the one of `submodules` it's in and the path inside
+/// that, or `None` if it isn't in any of them.
+fn split_submodule(submodules: &[PathBuf], path: &Path) -> Option<(PathBuf, PathBuf)> {
+    submodules.iter().find_map(|submodule| {
+        let inner = path.strip_prefix(submodule).ok()?;
+        let inner = if inner.as_os_str().is_empty() { Path::new(".") } else { inner };
+        Some((submodule.clone(), inner.to_path_buf()))
+    })
+}
+
+/// The commit of `submodule` recorded at `rev`, if it existed then.
+fn submodule_commit(dir: &Path, rev: &str, submodule: &Path) -> Option<String> {
+    let spec = format!("{}:./{}", rev, submodule.to_string_lossy());
+    git(dir, &["rev-parse", "--verify", "--quiet", &spec]).ok().map(|sha| sha.trim().to_string())
+}
+
+/// Groups `paths` by the submodule they're in, `None` being `dir`'s own
+/// repository, with submodule paths made relative to the submodule.
+fn by_repository<'a>(
+    dir: &Path,
+    paths: impl IntoIterator<Item = &'a Path>,
+) -> BTreeMap<Option<PathBuf>, Vec<PathBuf>> {
+    let submodules = submodules(dir);
+    let mut grouped: BTreeMap<Option<PathBuf>, Vec<PathBuf>> = BTreeMap::new();
+    for path in paths {
+        match split_submodule(&submodules, path) {
+            Some((submodule, inner)) => grouped.entry(Some(submodule)).or_default().push(inner),
+            None => grouped.entry(None).or_default().push(path.to_path_buf()),
+        }
+    }
+    grouped
+}
+
+/// Commits the changes to `paths`, and nothing else, with `message`,
+/// signing the commit if `sign`. Returns whether there was anything to
+/// commit. Paths inside submodules are committed in the submodule first,
+/// with the same message, and the superproject commits its new commit.
+pub fn commit(dir: &Path, paths: &[String], message: &str, sign: bool) -> Result<bool, ArmoryError> {
+    let mut own = Vec::new();
+    for (submodule, inner) in by_repository(dir, paths.iter().map(Path::new)) {
+        match submodule {
+            Some(submodule) => {
+                let inner: Vec<String> =
+                    inner.iter().map(|path| path.to_string_lossy().into_owned()).collect();
+                commit_paths(&dir.join(&submodule), &inner, message, sign)?;
+                own.push(submodule.to_string_lossy().into_owned());
+            }
+            None => own.extend(inner.iter().map(|path| path.to_string_lossy().into_owned())),
+        }
+    }
+    commit_paths(dir, &own, message, sign)
+}
+
+fn commit_paths(dir: &Path, paths: &[String], message: &str, sign: bool) -> Result<bool, ArmoryError> {
+    if paths.is_empty() {
+        return Ok(false);
+    }
+    let mut status = vec!["status", "--porcelain", "--"];
+    status.extend(paths.iter().map(|path| path.as_str()));
+    if git(dir, &status)?.trim().is_empty() {
+        return Ok(false);
+    }
+
+    let mut add = vec!["add", "--"];
+    add.extend(paths.iter().map(|path| path.as_str()));
+    git(dir, &add)?;
+    let mut commit = vec!["commit", "--message", message];
+    if sign {
+        commit.push("--gpg-sign");
+    }
+    commit.push("--");
+    commit.extend(paths.iter().map(|path| path.as_str()));
+    git(dir, &commit)?;
+    Ok(true)
+}
+
+/// The upstream branch of the current branch, such as `origin/main`.
+pub fn upstream(dir: &Path) -> Option<String> {
+    git(dir, &["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{upstream}"])
+        .ok()
+        .map(|upstream| upstream.trim().to_string())
+        .filter(|upstream| !upstream.is_empty())
+}
+
+/// Fetches `remote` so its branches are up to date locally.
+pub fn fetch(dir: &Path, remote: &str) -> Result<(), ArmoryError> {
+    git(dir, &["fetch", "--quiet", remote]).map(|_| ())
+}
+
+/// How many commits HEAD is ahead of and behind `upstream`.
+pub fn ahead_behind(dir: &Path, upstream: &str) -> Result<(usize, usize), ArmoryError> {
+    let range = format!("HEAD...{}", upstream);
+    let output = git(dir, &["rev-list", "--left-right", "--count", &range])?;
+    let mut counts = output.split_whitespace().map(|count| count.parse::<usize>());
+    match (counts.next(), counts.next()) {
+        (Some(Ok(ahead)), Some(Ok(behind))) => Ok((ahead, behind)),
+        _ => Err(format!("Unexpected output from git rev-list: {}", output.trim()).into()),
+    }
+}
+
+/// The branch checked out in `dir`, or `None` on a detached HEAD.
+pub fn current_branch(dir: &Path) -> Option<String> {
+    git(dir, &["symbolic-ref", "--quiet", "--short", "HEAD"])
+        .ok()
+        .map(|branch| branch.trim().to_string())
+        .filter(|branch| !branch.is_empty())
+}
+
+/// Pushes `refspecs` to `remote` all at once, so either all of them make
+/// it or none do. Submodule commits they need are pushed first.
+pub fn push(dir: &Path, remote: &str, refspecs: &[String]) -> Result<(), ArmoryError> {
+    let mut args = vec!["push", "--atomic", "--recurse-submodules=on-demand", remote];
+    args.extend(refspecs.iter().map(|refspec| refspec.as_str()));
+    git(dir, &args).map(|_| ())
+}
+
+/// Checks out `branch`.
+pub fn switch(dir: &Path, branch: &str) -> Result<(), ArmoryError> {
+    git(dir, &["switch", "--quiet", branch]).map(|_| ())
+}
+
+/// Checks out a new `branch` at HEAD, replacing it if it exists.
+pub fn switch_new(dir: &Path, branch: &str) -> Result<(), ArmoryError> {
+    git(dir, &["switch", "--quiet", "--force-create", branch]).map(|_| ())
+}
+
+/// Pushes `branch` to `remote`, replacing whatever the remote had.
+pub fn force_push(dir: &Path, remote: &str, branch: &str) -> Result<(), ArmoryError> {
+    git(dir, &["push", "--force", remote, &format!("{}:refs/heads/{}", branch, branch)]).map(|_| ())
+}
+
+/// Creates the annotated tag `tag` on HEAD, signed if `sign`.
+pub fn create_tag(dir: &Path, tag: &str, message: &str, sign: bool) -> Result<(), ArmoryError> {
+    // without --sign, git's own tag.gpgSign still applies
+    let mut args = vec!["tag", tag, "--message", message];
+    if sign {
+        args.push("--sign");
+    }
+    git(dir, &args).map(|_| ())
+}
+
+/// Checks that git has what it needs to sign commits and tags with the
+/// user's signing config. Only an SSH key can be known to be missing up
+/// front; gpg falls back to the key matching the committer.
+pub fn check_signing(dir: &Path) -> Result<(), ArmoryError> {
+    let config = |key: &str| git(dir, &["config", "--get", key]).ok().map(|value| value.trim().to_string());
+    let format = config("gpg.format").unwrap_or_else(|| "openpgp".to_string());
+    if format == "ssh" && config("user.signingkey").is_none() {
+        return Err("gpg.format is ssh but there's no user.signingkey to sign releases with".to_string().into());
+    }
+    Ok(())
+}
+
+/// The uncommitted changes in `dir`, as `git status --short` lists them.
+/// Outside a git repository there are none.
+pub fn dirty_files(dir: &Path) -> Result<Vec<String>, ArmoryError> {
+    if git(dir, &["rev-parse", "--is-inside-work-tree"]).is_err() {
+        return Ok(Vec::new());
+    }
+    let submodules = submodules(dir);
+    let mut dirty = Vec::new();
+    for line in git(dir, &["status", "--porcelain", "--untracked-files=normal", "--", "."])?.lines() {
+        let path = line.get(3..).map(Path::new);
+        let Some(submodule) = submodules.iter().find(|submodule| path == Some(submodule.as_path())) else {
+            if !line.is_empty() {
+                dirty.push(line.to_string());
+            }
+            continue;
+        };
+        // list what changed inside the submodule, rather than just that
+        // it did, unless it only moved to another commit
+        let inner = dirty_files(&dir.join(submodule))?;
+        if inner.is_empty() {
+            dirty.push(line.to_string());
+        }
+        dirty.extend(
+            inner.iter().map(|line| format!("{}{}", &line[..3], submodule.join(&line[3..]).display())),
+        );
+    }
+    Ok(dirty)
+}
+
+/// The most recent tag matching `pattern` that comes before `tag`.
+pub fn previous_tag(dir: &Path, tag: &str, pattern: &str) -> Option<String> {
+    git(dir, &["describe", "--tags", "--abbrev=0", "--match", pattern, &format!("{}^", tag)])
+        .ok()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+}
+
+/// The URL of `remote`.
+pub fn remote_url(dir: &Path, remote: &str) -> Option<String> {
+    git(dir, &["remote", "get-url", remote]).ok().map(|url| url.trim().to_string())
+}
+
+/// Whether `tag` exists in the local repository.
+pub fn tag_exists(dir: &Path, tag: &str) -> bool {
+    git(dir, &["rev-parse", "--quiet", "--verify", &format!("refs/tags/{}", tag)]).is_ok()
+}
+
+/// Deletes the local tag `tag`.
+pub fn delete_tag(dir: &Path, tag: &str) -> Result<(), ArmoryError> {
+    git(dir, &["tag", "--delete", tag]).map(|_| ())
+}
+
+/// The parent of HEAD and HEAD's message, if HEAD has a parent.
+pub fn head_commit(dir: &Path) -> Option<(String, String)> {
+    let parent = git(dir, &["rev-parse", "--verify", "--quiet", "HEAD^"]).ok()?;
+    let message = git(dir, &["log", "-1", "--format=%B"]).ok()?;
+    Some((parent.trim().to_string(), message.trim().to_string()))
+}
+
+/// Moves the current branch back to `commit`, keeping uncommitted changes
+/// and refusing if they'd be lost.
+pub fn reset_keep(dir: &Path, commit: &str) -> Result<(), ArmoryError> {
+    git(dir, &["reset", "--quiet", "--keep", commit]).map(|_| ())
+}
+
+/// Which of `tags` exist on `remote`.
+pub fn remote_tags(dir: &Path, remote: &str, tags: &[String]) -> Result<Vec<String>, ArmoryError> {
+    let refs: Vec<String> = tags.iter().map(|tag| format!("refs/tags/{}", tag)).collect();
+    let mut args = vec!["ls-remote", "--tags", remote];
+    args.extend(refs.iter().map(|name| name.as_str()));
+    let output = git(dir, &args)?;
+
+    let mut existing: Vec<String> = output
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter_map(|(_, name)| name.strip_prefix("refs/tags/"))
+        // annotated tags are listed a second time, peeled
+        .map(|tag| tag.trim_end_matches("^{}").to_string())
+        .filter(|tag| tags.contains(tag))
+        .collect();
+    existing.dedup();
+    Ok(existing)
+}
+
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub sha: String,
+    /// The author's name.
+    pub author: String,
+    /// The full commit message, subject and body.
+    pub message: String,
+}
+
+impl Commit {
+    pub fn subject(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+}
+
+/// Lists the commits after `since` (or all commits if `None`), newest first,
+/// limited to the ones touching `paths` unless it's empty.
+pub fn commits_since(dir: &Path, since: Option<&str>, paths: &[&Path]) -> Result<Vec<Commit>, ArmoryError> {
+    commits_between(dir, since, "HEAD", paths)
+}
+
+/// Lists the commits after `since` up to and including `until`, the way
+/// [`commits_since`] does up to HEAD.
+///
+/// Paths inside submodules get the submodule's own commits between the
+/// ones recorded at `since` and `until`.
+pub fn commits_between(dir: &Path, since: Option<&str>, until: &str, paths: &[&Path]) -> Result<Vec<Commit>, ArmoryError> {
+    if paths.is_empty() {
+        return log(dir, since, until, &[]);
+    }
+
+    let mut commits = Vec::new();
+    for (submodule, paths) in by_repository(dir, paths.iter().copied()) {
+        let paths: Vec<&Path> = paths.iter().map(|path| path.as_path()).collect();
+        match submodule {
+            Some(submodule) => {
+                let Some(until) = submodule_commit(dir, until, &submodule) else {
+                    continue;
+                };
+                let since = since.and_then(|since| submodule_commit(dir, since, &submodule));
+                commits.extend(log(&dir.join(&submodule), since.as_deref(), &until, &paths)?);
+            }
+            None => commits.extend(log(dir, since, until, &paths)?),
+        }
+    }
+    Ok(commits)
+}
+
+fn log(dir: &Path, since: Option<&str>, until: &str, paths: &[&Path]) -> Result<Vec<Commit>, ArmoryError> {
+    let range = match since {
+        Some(since) => format!("{}..{}", since, until),
+        None => until.to_string(),
+    };
+    let mut args = vec!["log", "--format=%H%x00%an%x00%B%x1e", &range];
+    let paths: Vec<_> = paths.iter().map(|path| path.to_string_lossy()).collect();
+    if !paths.is_empty() {
+        args.push("--");
+        args.extend(paths.iter().map(|path| path.as_ref()));
+    }
+
+    Ok(git(dir, &args)?
+        .split('\x1e')
+        .filter_map(|record| {
+            let (sha, rest) = record.trim_start().split_once('\0')?;
+            let (author, message) = rest.split_once('\0')?;
+            Some(Commit {
+                sha: sha.to_string(),
+                author: author.to_string(),
+                message: message.trim().to_string(),
+            })
+        })
+        .collect())
+}
+
+/// The files under `path` that changed between `since` and HEAD. For a
+/// path inside a submodule, those are the changes between the submodule
+/// commits recorded at `since` and HEAD.
+pub fn changed_files(dir: &Path, since: &str, path: &Path) -> Result<Vec<String>, ArmoryError> {
+    if let Some((submodule, inner)) = split_submodule(&submodules(dir), path) {
+        let Some(head) = submodule_commit(dir, "HEAD", &submodule) else {
+            return Ok(Vec::new());
+        };
+        let inner = inner.to_string_lossy();
+        let submodule_dir = dir.join(&submodule);
+        let files = match submodule_commit(dir, since, &submodule) {
+            Some(since) => git(&submodule_dir, &["diff", "--name-only", &since, &head, "--", &inner])?,
+            // everything is new if the submodule wasn't there yet
+            None => git(&submodule_dir, &["ls-tree", "-r", "--name-only", &head, "--", &inner])?,
+        };
+        return Ok(files
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| submodule.join(line).to_string_lossy().into_owned())
+            .collect());
+    }
+
+    let path = path.to_string_lossy();
+    Ok(git(dir, &["diff", "--name-only", since, "HEAD", "--", &path])?
+        .lines()
+        .map(|line| line.to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
@@ -0,0 +1,126 @@
+//! Creating releases and release pull requests on GitHub.
+
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use crate::{owners::USER_AGENT, ArmoryError};
+
+const GITHUB_API: &str = "https://api.github.com";
+
+#[derive(Debug, Deserialize)]
+struct CreatedRelease {
+    html_url: String,
+}
+
+/// A pull request, with just what armory needs of it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PullRequest {
+    pub number: u64,
+    pub html_url: String,
+    pub head: Head,
+    /// When it was merged, if it was.
+    #[serde(default)]
+    pub merged_at: Option<String>,
+}
+
+/// The branch a pull request is merged from.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Head {
+    #[serde(rename = "ref")]
+    pub branch: String,
+}
+
+/// The `owner/repo` of a GitHub remote URL, over HTTPS or SSH.
+pub fn repo_from_url(url: &str) -> Option<String> {
+    let path = url
+        .strip_prefix("git@github.com:")
+        .or_else(|| url.strip_prefix("ssh://git@github.com/"))
+        .or_else(|| url.strip_prefix("https://github.com/"))?;
+    let repo = path.trim_end_matches('/').trim_end_matches(".git");
+    (repo.split('/').count() == 2).then(|| repo.to_string())
+}
+
+/// The token to call the GitHub API with, from `GITHUB_TOKEN` or
+/// `GH_TOKEN`.
+pub fn token() -> Option<String> {
+    std::env::var("GITHUB_TOKEN")
+        .or_else(|_| std::env::var("GH_TOKEN"))
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+/// Calls the GitHub API, described by `action` in errors, and parses its
+/// response. `GITHUB_API_URL` points it at a GitHub Enterprise server
+/// instead of github.com.
+fn call<T: DeserializeOwned>(method: &str, path: &str, token: &str, body: Option<Value>, action: &str) -> Result<T, ArmoryError> {
+    let api = std::env::var("GITHUB_API_URL").unwrap_or_else(|_| GITHUB_API.to_string());
+    let request = ureq::request(method, &format!("{}{}", api.trim_end_matches('/'), path))
+        .set("User-Agent", USER_AGENT)
+        .set("Accept", "application/vnd.github+json")
+        .set("Authorization", &format!("Bearer {}", token));
+    let response = match body {
+        Some(body) => request.set("Content-Type", "application/json").send_string(&body.to_string()),
+        None => request.call(),
+    };
+
+    let body = match response {
+        Ok(response) => response
+            .into_string()
+            .map_err(|e| format!("Failed to read GitHub's response to {}: {}", action, e))?,
+        Err(ureq::Error::Status(status, response)) => {
+            let message = response.into_string().unwrap_or_default();
+            return Err(format!("GitHub refused to {} ({}): {}", action, status, message.trim()).into());
+        }
+        Err(e) => return Err(format!("Failed to {} on GitHub: {}", action, e).into()),
+    };
+    serde_json::from_str(&body).map_err(|e| format!("Failed to parse GitHub's response to {}: {}", action, e).into())
+}
+
+/// Creates a release of `tag` in `repo`, which must exist on GitHub
+/// already, and returns its URL.
+pub fn create_release(repo: &str, token: &str, tag: &str, name: &str, body: &str) -> Result<String, ArmoryError> {
+    let payload = json!({
+        "tag_name": tag,
+        "name": name,
+        "body": body,
+    });
+    let action = format!("create the release of {}", tag);
+    let release: CreatedRelease = call("POST", &format!("/repos/{}/releases", repo), token, Some(payload), &action)?;
+    Ok(release.html_url)
+}
+
+/// The open pull request from `branch` in `repo`, if there is one.
+pub fn find_pull_request(repo: &str, token: &str, branch: &str) -> Result<Option<PullRequest>, ArmoryError> {
+    let owner = repo.split('/').next().unwrap_or_default();
+    let path = format!("/repos/{}/pulls?state=open&head={}:{}", repo, owner, branch);
+    let pulls: Vec<PullRequest> = call("GET", &path, token, None, &format!("list the pull requests from {}", branch))?;
+    Ok(pulls.into_iter().next())
+}
+
+/// Opens a pull request merging `branch` into `base`.
+pub fn open_pull_request(repo: &str, token: &str, branch: &str, base: &str, title: &str, body: &str) -> Result<PullRequest, ArmoryError> {
+    let payload = json!({
+        "head": branch,
+        "base": base,
+        "title": title,
+        "body": body,
+    });
+    let action = format!("open a pull request from {}", branch);
+    call("POST", &format!("/repos/{}/pulls", repo), token, Some(payload), &action)
+}
+
+/// Replaces the title and description of pull request `number`.
+pub fn update_pull_request(repo: &str, token: &str, number: u64, title: &str, body: &str) -> Result<PullRequest, ArmoryError> {
+    let payload = json!({
+        "title": title,
+        "body": body,
+    });
+    let action = format!("update pull request #{}", number);
+    call("PATCH", &format!("/repos/{}/pulls/{}", repo, number), token, Some(payload), &action)
+}
+
+/// The pull requests commit `sha` belongs to, such as the one it merged.
+pub fn commit_pull_requests(repo: &str, token: &str, sha: &str) -> Result<Vec<PullRequest>, ArmoryError> {
+    let action = format!("list the pull requests of {}", sha);
+    call("GET", &format!("/repos/{}/commits/{}/pulls", repo, sha), token, None, &action)
+}
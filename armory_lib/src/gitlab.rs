@@ -0,0 +1,88 @@
+//! Creating releases on GitLab, including self-hosted instances.
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{owners::USER_AGENT, ArmoryError};
+
+const GITLAB_URL: &str = "https://gitlab.com";
+
+#[derive(Debug, Deserialize)]
+struct CreatedRelease {
+    #[serde(rename = "_links")]
+    links: Links,
+}
+
+#[derive(Debug, Deserialize)]
+struct Links {
+    #[serde(rename = "self")]
+    url: String,
+}
+
+/// A token for the GitLab API, and the header it goes in.
+#[derive(Debug, Clone)]
+pub enum Token {
+    /// A personal, project or group access token.
+    Private(String),
+    /// The job token of a GitLab CI pipeline.
+    Job(String),
+}
+
+/// The token to call the GitLab API with, from `GITLAB_TOKEN` or else the
+/// `CI_JOB_TOKEN` of the pipeline armory runs in.
+pub fn token() -> Option<Token> {
+    let var = |name| std::env::var(name).ok().filter(|token: &String| !token.is_empty());
+    var("GITLAB_TOKEN").map(Token::Private).or_else(|| var("CI_JOB_TOKEN").map(Token::Job))
+}
+
+/// The URL of the GitLab instance, from `CI_SERVER_URL` in pipelines and
+/// gitlab.com otherwise.
+pub fn default_url() -> String {
+    std::env::var("CI_SERVER_URL").unwrap_or_else(|_| GITLAB_URL.to_string())
+}
+
+/// The project path, such as `group/subgroup/project`, of a remote URL over
+/// HTTPS or SSH.
+pub fn project_from_url(url: &str) -> Option<String> {
+    let path = if let Some((_, rest)) = url.split_once("://") {
+        rest.split_once('/')?.1
+    } else {
+        url.split_once(':')?.1
+    };
+    let project = path.trim_end_matches('/').trim_end_matches(".git");
+    project.contains('/').then(|| project.to_string())
+}
+
+/// Creates a release of `tag` in `project` on the GitLab instance at `url`,
+/// and returns its URL. The tag must exist on GitLab already.
+pub fn create_release(url: &str, project: &str, token: &Token, tag: &str, name: &str, description: &str) -> Result<String, ArmoryError> {
+    let endpoint = format!("{}/api/v4/projects/{}/releases", url.trim_end_matches('/'), project.replace('/', "%2F"));
+    let request = json!({
+        "tag_name": tag,
+        "name": name,
+        "description": description,
+    });
+
+    let (header, token) = match token {
+        Token::Private(token) => ("PRIVATE-TOKEN", token),
+        Token::Job(token) => ("JOB-TOKEN", token),
+    };
+    let response = ureq::post(&endpoint)
+        .set("User-Agent", USER_AGENT)
+        .set(header, token)
+        .set("Content-Type", "application/json")
+        .send_string(&request.to_string());
+    let body = match response {
+        Ok(response) => response
+            .into_string()
+            .map_err(|e| format!("Failed to read GitLab's response for {}: {}", tag, e))?,
+        Err(ureq::Error::Status(status, response)) => {
+            let message = response.into_string().unwrap_or_default();
+            return Err(format!("GitLab refused to create the release of {} ({}): {}", tag, status, message.trim()).into());
+        }
+        Err(e) => return Err(format!("Failed to create the GitLab release of {}: {}", tag, e).into()),
+    };
+    serde_json::from_str::<CreatedRelease>(&body)
+        .map(|release| release.links.url)
+        .map_err(|e| format!("Failed to parse GitLab's response for {}: {}", tag, e).into())
+}
@@ -1,39 +1,1178 @@
 use std::{
-    collections::{HashMap, HashSet},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fs,
-    io::Write,
-    path::Path
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant},
 };
 
-use cargo::{
-    core::{resolver::CliFeatures, Workspace},
-    ops::{Packages, PublishOpts},
-    Config,
-};
-use retry::{delay, retry_with_index};
-use semver::Version;
+use cargo::{util::auth::Secret, Config};
+use retry::{delay, retry_with_index, OperationResult};
+use semver::{BuildMetadata, Version};
 use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use time::OffsetDateTime;
 use toml_edit::Document;
 
+pub mod artifacts;
+mod backend;
+mod bump;
+pub mod changelog;
+pub mod config;
+pub mod conventional;
+pub mod credential;
+mod error;
+pub mod git;
+pub mod github;
+pub mod gitlab;
+pub mod migrate;
+#[macro_use]
+pub mod output;
+pub mod owners;
+pub mod registry;
+pub mod scaffold;
+pub mod semver_checks;
+pub mod state;
+pub mod throttle;
+
+pub use artifacts::Signing;
+pub use backend::{Backend, Features};
+pub use bump::{parse_prerelease, Bump, Scheme};
+pub use config::{ColorChoice, ConfigError, ConfigErrorKind, UserConfig};
+pub use error::{ArmoryError, Stage};
+pub use state::{PublishState, ReleaseSnapshot};
+pub use throttle::RateLimit;
+
+use backend::{LocalDep, Upload};
+use throttle::Throttle;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct ArmoryTOML {
+    /// Whether versions are semver or calendar based.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub scheme: Scheme,
+    /// The shared version of every member. In independent mode this is
+    /// only the fallback for members without an entry in `versions`.
     pub version: Version,
+    /// Opt-in: version each member on its own instead of in lockstep.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub independent: bool,
+    /// Per-crate versions used in independent mode, keyed by crate name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub versions: BTreeMap<String, Version>,
+    /// Members pinned to their own version, keyed by crate name. They're
+    /// left out of bumps and only released when their pin changes.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub overrides: BTreeMap<String, Version>,
+    /// Run cargo-semver-checks before releasing to suggest the bump.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub semver_checks: bool,
+    /// Refuse major bumps, whether picked or worked out from commits,
+    /// unless `--allow-major` is passed.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub require_flag_for_major: bool,
+    /// Template for the build metadata of released versions, see
+    /// [`build_metadata`]. Bumps keep the current metadata when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_metadata: Option<String>,
+    /// A pre-release channel, such as `nightly`, that every bump releases
+    /// on: a patch of `1.2.0` becomes `1.2.1-nightly.1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_release: Option<String>,
+    /// Members that are never re-versioned or published, by crate name or
+    /// member path.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// How the version requirements of local dependencies are written
+    /// when bumping.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub dependency_requirement: Requirement,
+    /// What bumps do with dev-dependencies on other members that only
+    /// have a path, which cargo strips when publishing.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub dev_dependencies: DevDependencies,
+    /// Base bumps on the highest versions published on the registry
+    /// rather than the ones recorded here.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub version_from_registry: bool,
+    /// The minimum supported Rust version, stamped on every member's
+    /// `package.rust-version` when bumping.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rust_version: Option<String>,
+    /// Check that the workspace builds with `rust-version` after bumping.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub verify_rust_version: bool,
+    /// Add a section to the `CHANGELOG.md` of each crate when bumping,
+    /// listing the commits that touched it or its local dependencies.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub changelogs: bool,
+    /// How changelogs and release notes are written.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub changelog: changelog::Config,
+    /// Build the docs of every crate before publishing any, failing on
+    /// broken intra-doc links and other rustdoc warnings.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub check_docs: bool,
+    /// Once a release is out, build a new project depending on its
+    /// top-level crates from the registry.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub smoke_test: bool,
+    /// Publish even with uncommitted changes in the workspace, which are
+    /// otherwise refused so they don't end up in the packages.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub allow_dirty: bool,
+    /// Publish even with local commits that aren't on the upstream branch.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub allow_unpushed: bool,
+    /// Publish even when the upstream branch has commits that aren't
+    /// checked out.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub allow_behind: bool,
+    /// The branches releases may be published from, where `*` matches any
+    /// run of characters. Any branch if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub release_branches: Vec<String>,
+    /// What publishes the crates: the cargo library armory is built with,
+    /// or the installed `cargo publish`.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub backend: Backend,
+    /// Build each crate from its packaged sources before uploading it.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub verify: bool,
+    /// Target triples to verify crates for, the host if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<String>,
+    /// Targets for specific crates, overriding `targets`, e.g. for members
+    /// that only build on one platform.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub crate_targets: BTreeMap<String, Vec<String>>,
+    /// The features specific crates are packaged and verified with, for
+    /// ones that can't enable all of them at once.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub crate_features: BTreeMap<String, Features>,
+    /// How to sign packaged crates, which are left unsigned if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing: Option<Signing>,
+    /// The registry from `.cargo/config.toml` to publish to, crates.io if
+    /// unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    /// Registries for specific crates, overriding `registry`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub crate_registries: BTreeMap<String, String>,
+    /// More registries every crate is published to after its own. Local
+    /// dependencies still point at the crate's own registry.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    /// Which registries crates may be published to at all.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub registries: Registries,
+    /// How fast each registry accepts publishes, keyed by registry name.
+    /// crates.io's limits apply to it unless overridden.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rate_limits: BTreeMap<String, RateLimit>,
+    /// Seconds a single attempt at publishing a crate may take before it's
+    /// given up on and retried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_timeout: Option<u64>,
+    /// Seconds a whole publish may take. Crates not published by then are
+    /// left for `cargo armory resume`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_timeout: Option<u64>,
+    /// Where to find the publish token of each registry, keyed by registry
+    /// name (`crates-io` for crates.io). Registries without an entry use
+    /// the credentials from `cargo login`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tokens: BTreeMap<String, TokenSource>,
+    /// Template for release tags, also used for changelog headers and
+    /// release names. `{version}` and `{crate}` are filled in. Independent
+    /// releases tag each crate, under `{crate}/` unless the template says
+    /// where the crate goes.
+    #[serde(default = "default_tag", skip_serializing_if = "is_default_tag")]
+    pub tag: String,
+    /// Commit the manifests and armory.toml after bumping, and again once
+    /// a release is recorded.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub create_commit: bool,
+    /// Template for the message of those commits. `{version}` is the
+    /// shared version and `{crates}` lists every crate with its version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_message: Option<String>,
+    /// Tag the commit released from once a release is published.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub create_tags: bool,
+    /// Sign the release commits and tags with the user's git signing
+    /// config, failing if they can't be signed.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub sign_git: bool,
+    /// Push the release commit and tags once they're created.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub push: bool,
+    /// The remote to push to, `origin` if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub push_remote: Option<String>,
+    /// The branch to push the release commit to, the current one if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub push_branch: Option<String>,
+    /// Create a GitHub release for each tag once it's pushed.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub github_releases: bool,
+    /// The `owner/repo` to create GitHub releases in. Otherwise it's taken
+    /// from `GITHUB_REPOSITORY` or the push remote's URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github_repo: Option<String>,
+    /// The branch `release-pr` pushes release pull requests from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_pr_branch: Option<String>,
+    /// Shell commands to run around the stages of a release.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub hooks: Hooks,
+    /// Create a GitLab release for each tag once it's pushed.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub gitlab_releases: bool,
+    /// The URL of a self-hosted GitLab instance to create releases on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gitlab_url: Option<String>,
+    /// The path of the GitLab project to create releases in, such as
+    /// `group/project`. Otherwise it's taken from `CI_PROJECT_PATH` or the
+    /// push remote's URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gitlab_project: Option<String>,
+    /// A directory `new-member` copies new crates from, relative to the
+    /// workspace root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub member_template: Option<PathBuf>,
+    /// Settings for specific crates, overriding the ones above.
+    #[serde(default, rename = "crate", skip_serializing_if = "BTreeMap::is_empty")]
+    pub crates: BTreeMap<String, CrateConfig>,
+    /// Named sets of overrides, selected with `--profile`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profile: BTreeMap<String, Profile>,
+    /// Every release published through armory, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub releases: Vec<Release>,
+    /// What the selected profile replaced, to put back when saving.
+    #[serde(skip)]
+    replaced: Option<Profile>,
+    /// The user's personal defaults, for what the workspace leaves unset.
+    #[serde(skip)]
+    pub user: UserConfig,
+}
+
+/// A release recorded in armory.toml's `[[releases]]` history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Release {
+    /// The shared version, for lockstep releases.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Version>,
+    /// When the release finished, in RFC 3339.
+    pub date: String,
+    /// The commit released from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha: Option<String>,
+    /// Every crate published, with its version.
+    pub crates: BTreeMap<String, Version>,
+    /// The tag each crate was released under, if the release was tagged.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// The SHA-256 of each crate's package, for the ones packaged locally.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<String, String>,
+    /// How the smoke test after the release went, `passed` or the error,
+    /// if there was one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smoke_test: Option<String>,
+}
+
+/// Where a registry token comes from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenSource {
+    /// An environment variable holding the token.
+    Env(String),
+    /// A shell command printing the token.
+    Command(String),
+    /// A cargo credential provider, and its arguments.
+    Provider(String),
+}
+
+impl TokenSource {
+    /// The token for `registry`, `None` being crates.io.
+    pub fn resolve(&self, registry: Option<&str>) -> Result<String, ArmoryError> {
+        let token = match self {
+            TokenSource::Env(var) => std::env::var(var)
+                .map_err(|e| format!("Failed to read the token from ${}: {}", var, e))?,
+            TokenSource::Command(command) => {
+                let output = shell(command)
+                    .output()
+                    .map_err(|e| format!("Failed to run `{}`: {}", command, e))?;
+                if !output.status.success() {
+                    return Err(format!(
+                        "`{}` failed: {}",
+                        command,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ).into());
+                }
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            }
+            TokenSource::Provider(provider) => {
+                let provider: Vec<String> = provider.split_whitespace().map(|arg| arg.to_string()).collect();
+                credential::provider_token(&provider, registry)?
+            }
+        };
+
+        let token = token.trim();
+        if token.is_empty() {
+            return Err(format!("{:?} gave an empty token", self).into());
+        }
+        Ok(token.to_string())
+    }
+}
+
+/// Shell commands run around the stages of a release, from armory.toml's
+/// `[hooks]` table. They run in the workspace root, with the crates being
+/// released in `ARMORY_CRATES`, the version in `ARMORY_VERSION` and, for
+/// the per-crate publish hooks, the crate in `ARMORY_CRATE`. A failing
+/// hook stops the release.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Hooks {
+    /// Runs before the manifests are rewritten for a bump.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_bump: Option<String>,
+    /// Runs once the manifests are rewritten, before they're committed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_bump: Option<String>,
+    /// Runs before each crate is packaged and uploaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_publish: Option<String>,
+    /// Runs after each crate is published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_publish: Option<String>,
+    /// Runs once the whole release is published, recorded, tagged and
+    /// pushed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_release: Option<String>,
+}
+
+impl Hooks {
+    /// Replaces the hooks `other` sets, keeping the rest.
+    fn overlay(&mut self, other: &Hooks) {
+        for (hook, replacement) in [
+            (&mut self.pre_bump, &other.pre_bump),
+            (&mut self.post_bump, &other.post_bump),
+            (&mut self.pre_publish, &other.pre_publish),
+            (&mut self.post_publish, &other.post_publish),
+            (&mut self.post_release, &other.post_release),
+        ] {
+            if replacement.is_some() {
+                hook.clone_from(replacement);
+            }
+        }
+    }
+}
+
+/// Settings for one crate, from armory.toml's `[crate.NAME]` tables. They
+/// take precedence over the workspace-wide settings and the older
+/// `crate-registries` and `crate-features` maps.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CrateConfig {
+    /// The registry to publish the crate to, `crates-io` included.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    /// The features to package and verify the crate with. Setting any of
+    /// these three turns off `all-features` unless it's set too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub all_features: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_features: Option<bool>,
+    /// Whether to build the crate from its packaged sources before
+    /// uploading it, whatever `verify` and `--verify` say.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<bool>,
+    /// The crate's changelog, relative to its directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changelog: Option<String>,
+    /// Crates to publish before this one, as if it depended on them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub publish_after: Vec<String>,
+}
+
+/// Overrides from one of armory.toml's `[profile.NAME]` tables, for
+/// releasing from the same workspace in different ways, such as nightly
+/// pre-releases and stable releases.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<Scheme>,
+    /// The registry to publish to, `crates-io` included.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    /// The pre-release channel to release on, or `""` for none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_release: Option<String>,
+    /// Hooks replacing the ones of the same name, the others still run.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub hooks: Hooks,
+}
+
+/// armory.toml's `[registries]` table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Registries {
+    /// The only registries armory publishes to, `crates-io` being
+    /// crates.io. Publishing anywhere else is refused before anything
+    /// changes. Any registry if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<Vec<String>>,
+}
+
+/// How a bump writes the version requirement of a local dependency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Requirement {
+    /// The bare version, `1.2.3`, which cargo reads as `^1.2.3`.
+    #[default]
+    Caret,
+    /// Exactly the version, `=1.2.3`.
+    Exact,
+    /// The version with a tilde, `~1.2.3`.
+    Tilde,
+    /// The new version behind whatever operator the requirement already
+    /// had, or the bare version if it had none.
+    Keep,
+}
+
+impl Requirement {
+    /// The requirement on `version`, replacing `current` if there is one.
+    pub fn format(self, current: Option<&str>, version: &Version) -> String {
+        match self {
+            Requirement::Caret => version.to_string(),
+            Requirement::Exact => format!("={}", version),
+            Requirement::Tilde => format!("~{}", version),
+            Requirement::Keep => {
+                let current = current.unwrap_or_default().trim_start();
+                let operator = &current[..current.len() - current.trim_start_matches(['=', '^', '~']).len()];
+                format!("{}{}", operator, version)
+            }
+        }
+    }
+}
+
+/// What to do with dev-dependencies on other members that only have a
+/// path. Ones that also have a version are always kept in step, like any
+/// other local dependency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DevDependencies {
+    /// Leave them alone, so cargo strips them from the published crate.
+    #[default]
+    Ignore,
+    /// Give them a version requirement when bumping, so they're kept in
+    /// the published crate, which then has to come after them.
+    Inject,
+    /// Refuse to release while there are any.
+    Error,
+}
+
+/// A stage of a release that can have a hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PreBump,
+    PostBump,
+    PrePublish,
+    PostPublish,
+    PostRelease,
+}
+
+impl Hook {
+    /// The hook's key in armory.toml.
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PreBump => "pre-bump",
+            Hook::PostBump => "post-bump",
+            Hook::PrePublish => "pre-publish",
+            Hook::PostPublish => "post-publish",
+            Hook::PostRelease => "post-release",
+        }
+    }
+}
+
+/// A command running `command` through the platform's shell.
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
+
+impl ArmoryTOML {
+    /// A config releasing every member in lockstep at `version`.
+    pub fn new(version: Version) -> Self {
+        ArmoryTOML {
+            scheme: Scheme::default(),
+            version,
+            independent: false,
+            versions: BTreeMap::new(),
+            overrides: BTreeMap::new(),
+            semver_checks: false,
+            require_flag_for_major: false,
+            build_metadata: None,
+            pre_release: None,
+            exclude: Vec::new(),
+            dependency_requirement: Requirement::default(),
+            dev_dependencies: DevDependencies::default(),
+            version_from_registry: false,
+            rust_version: None,
+            verify_rust_version: false,
+            changelogs: false,
+            changelog: changelog::Config::default(),
+            check_docs: false,
+            smoke_test: false,
+            allow_dirty: false,
+            allow_unpushed: false,
+            allow_behind: false,
+            release_branches: Vec::new(),
+            backend: Backend::default(),
+            verify: false,
+            targets: Vec::new(),
+            crate_targets: BTreeMap::new(),
+            crate_features: BTreeMap::new(),
+            signing: None,
+            registry: None,
+            crate_registries: BTreeMap::new(),
+            mirrors: Vec::new(),
+            registries: Registries::default(),
+            rate_limits: BTreeMap::new(),
+            publish_timeout: None,
+            release_timeout: None,
+            tokens: BTreeMap::new(),
+            tag: default_tag(),
+            create_commit: false,
+            commit_message: None,
+            create_tags: false,
+            sign_git: false,
+            push: false,
+            push_remote: None,
+            push_branch: None,
+            github_releases: false,
+            github_repo: None,
+            release_pr_branch: None,
+            hooks: Hooks::default(),
+            gitlab_releases: false,
+            gitlab_url: None,
+            gitlab_project: None,
+            member_template: None,
+            crates: BTreeMap::new(),
+            profile: BTreeMap::new(),
+            releases: Vec::new(),
+            replaced: None,
+            user: UserConfig::default(),
+        }
+    }
+
+    /// Applies the overrides of the profile `name`. They only last while
+    /// armory runs, saving the config keeps what it had before.
+    pub fn use_profile(&mut self, name: &str) -> Result<(), ArmoryError> {
+        let Some(profile) = self.profile.get(name).cloned() else {
+            let known: Vec<&str> = self.profile.keys().map(|name| name.as_str()).collect();
+            return Err(match known.is_empty() {
+                true => format!("There's no profile {}, armory.toml has no [profile.*] tables", name).into(),
+                false => format!("There's no profile {}, armory.toml has {}", name, known.join(", ")).into(),
+            });
+        };
+
+        self.replaced = Some(Profile {
+            scheme: Some(self.scheme),
+            registry: self.registry.clone(),
+            pre_release: self.pre_release.clone(),
+            hooks: self.hooks.clone(),
+        });
+        if let Some(scheme) = profile.scheme {
+            self.scheme = scheme;
+        }
+        if profile.registry.is_some() {
+            self.registry = profile.registry;
+        }
+        if let Some(pre_release) = profile.pre_release {
+            self.pre_release = Some(pre_release).filter(|pre_release| !pre_release.is_empty());
+        }
+        self.hooks.overlay(&profile.hooks);
+        Ok(())
+    }
+
+    /// Fails if releasing `name` at `new` rather than `current` is a major
+    /// bump that `require-flag-for-major` refuses without `allow_major`.
+    /// Calendar versions are never refused, their years aren't breaking.
+    pub fn check_major_bump(&self, name: &str, current: &Version, new: &Version, allow_major: bool) -> Result<(), ArmoryError> {
+        if !self.require_flag_for_major || allow_major || self.scheme == Scheme::Calver || new.major <= current.major {
+            return Ok(());
+        }
+        Err(format!(
+            "{}: {} -> {} is a major bump, which require-flag-for-major only allows with --allow-major",
+            name, current, new
+        ).into())
+    }
+
+    /// The pre-release channel bumps release on, if any.
+    pub fn pre_release(&self) -> Option<&str> {
+        self.pre_release.as_deref()
+    }
+
+    /// The registry `crate_name` is published to, or `None` for crates.io.
+    pub fn registry_for(&self, crate_name: &str) -> Option<&str> {
+        self.crates
+            .get(crate_name)
+            .and_then(|config| config.registry.as_ref())
+            .or(self.crate_registries.get(crate_name))
+            .or(self.registry.as_ref())
+            .or(self.user.registry.as_ref())
+            .map(|registry| registry.as_str())
+            .filter(|registry| *registry != CRATES_IO_REGISTRY)
+    }
+
+    /// The mirrors `crate_name` is also published to, `None` being
+    /// crates.io.
+    pub fn mirrors_for(&self, crate_name: &str) -> Vec<Option<&str>> {
+        let registry = self.registry_for(crate_name);
+        let mut mirrors: Vec<Option<&str>> = Vec::new();
+        for mirror in &self.mirrors {
+            let mirror = Some(mirror.as_str()).filter(|mirror| *mirror != CRATES_IO_REGISTRY);
+            if mirror != registry && !mirrors.contains(&mirror) {
+                mirrors.push(mirror);
+            }
+        }
+        mirrors
+    }
+
+    /// The registries `crates` are published or mirrored to, `None` being
+    /// crates.io.
+    pub fn registries_for<'a>(&self, crates: impl IntoIterator<Item = &'a String>) -> BTreeSet<Option<&str>> {
+        crates
+            .into_iter()
+            .flat_map(|name| std::iter::once(self.registry_for(name)).chain(self.mirrors_for(name)))
+            .collect()
+    }
+
+    /// Whether `registries.allowed` lets crates be published to
+    /// `registry`, `None` being crates.io.
+    pub fn is_registry_allowed(&self, registry: Option<&str>) -> bool {
+        let name = registry.unwrap_or(CRATES_IO_REGISTRY);
+        match &self.registries.allowed {
+            Some(allowed) => allowed.iter().any(|allowed| allowed == name),
+            None => true,
+        }
+    }
+
+    /// Fails if any of `crates` would be published, or mirrored, to a
+    /// registry that `registries.allowed` doesn't list.
+    pub fn check_registries_allowed<'a>(&self, crates: impl IntoIterator<Item = &'a String>) -> Result<(), ArmoryError> {
+        let mut refused = Vec::new();
+        for name in crates {
+            let registries = std::iter::once(self.registry_for(name)).chain(self.mirrors_for(name));
+            for registry in registries.filter(|registry| !self.is_registry_allowed(*registry)) {
+                refused.push(format!("{} to {}", name, registry.unwrap_or(CRATES_IO_REGISTRY)));
+            }
+        }
+        if refused.is_empty() {
+            return Ok(());
+        }
+        Err(format!(
+            "Refusing to publish {}, registries.allowed only allows {}",
+            refused.join(", "),
+            self.registries.allowed.as_deref().unwrap_or_default().join(", ")
+        ).into())
+    }
+
+    /// The rate limit to pace publishes to `registry` with, if any.
+    pub fn rate_limit_for(&self, registry: Option<&str>) -> Option<RateLimit> {
+        match self.rate_limits.get(registry.unwrap_or(CRATES_IO_REGISTRY)) {
+            Some(limit) => Some(*limit),
+            None => registry.is_none().then_some(RateLimit::CRATES_IO),
+        }
+    }
+
+    /// The targets to verify `crate_name` for.
+    pub fn targets_for(&self, crate_name: &str) -> &[String] {
+        self.crate_targets.get(crate_name).unwrap_or(&self.targets)
+    }
+
+    /// The features to package `crate_name` with.
+    pub fn features_for(&self, crate_name: &str) -> Features {
+        if let Some(config) = self.crates.get(crate_name) {
+            if config.features.is_some() || config.all_features.is_some() || config.default_features.is_some() {
+                return Features {
+                    features: config.features.clone().unwrap_or_default(),
+                    all_features: config.all_features.unwrap_or(false),
+                    default_features: config.default_features.unwrap_or(true),
+                };
+            }
+        }
+        self.crate_features.get(crate_name).cloned().unwrap_or_default()
+    }
+
+    /// Whether to verify `crate_name` before uploading it, given whether
+    /// verifying was asked for otherwise.
+    pub fn verify_for(&self, crate_name: &str, requested: bool) -> bool {
+        self.crates.get(crate_name).and_then(|config| config.verify).unwrap_or(requested)
+    }
+
+    /// The paths of `member`'s changelogs, relative to the workspace root.
+    pub fn changelogs_for(&self, member: &Member) -> Vec<PathBuf> {
+        match self.crates.get(&member.name).and_then(|config| config.changelog.as_ref()) {
+            Some(changelog) => vec![Path::new(&member.path).join(changelog)],
+            None => self.changelog.paths.iter().map(|path| Path::new(&member.path).join(path)).collect(),
+        }
+    }
+
+    /// The token to publish to `registry` with, or `None` to leave it to
+    /// cargo's own credentials. Registries without a token source here use
+    /// the user's token source for them, or else the credential provider
+    /// cargo's config sets for them, if any.
+    pub fn token_for(&self, registry: Option<&str>) -> Result<Option<String>, ArmoryError> {
+        let name = registry.unwrap_or(CRATES_IO_REGISTRY);
+        if let Some(source) = self.tokens.get(name).or(self.user.tokens.get(name)) {
+            return source.resolve(registry).map(Some);
+        }
+        match credential::cargo_provider(registry)? {
+            // cargo runs its own providers when it's doing the publishing
+            Some(_) if self.backend == Backend::Cargo => Ok(None),
+            Some(provider) => credential::provider_token(&provider, registry).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The tag of `version`, for `crate_name` if given.
+    pub fn tag_name(&self, crate_name: Option<&str>, version: &Version) -> String {
+        self.tag_template(crate_name)
+            .replace("{crate}", crate_name.unwrap_or_default())
+            .replace("{version}", &version.to_string())
+    }
+
+    /// A pattern for `git describe --match` that matches the tags of
+    /// `crate_name`, or of any crate if `None`.
+    pub fn tag_pattern(&self, crate_name: Option<&str>) -> String {
+        self.tag_template(crate_name)
+            .replace("{crate}", crate_name.unwrap_or("*"))
+            .replace("{version}", "*")
+    }
+
+    fn tag_template(&self, crate_name: Option<&str>) -> Cow<'_, str> {
+        if crate_name.is_some() && self.independent && !self.tag.contains("{crate}") {
+            Cow::Owned(format!("{{crate}}/{}", self.tag))
+        } else {
+            Cow::Borrowed(&self.tag)
+        }
+    }
+
+    /// Whether releases may be published from `branch`, `None` being a
+    /// detached HEAD.
+    pub fn allows_branch(&self, branch: Option<&str>) -> bool {
+        if self.release_branches.is_empty() {
+            return true;
+        }
+        branch.is_some_and(|branch| self.release_branches.iter().any(|pattern| glob_match(pattern, branch)))
+    }
+
+    /// The message to commit a release of `crates` with.
+    pub fn commit_message_for(&self, crates: &HashMap<String, Version>) -> String {
+        let template = match &self.commit_message {
+            Some(template) => template.as_str(),
+            None if self.independent => "chore(release): {crates}",
+            None => "chore(release): v{version}",
+        };
+        let mut crates: Vec<_> = crates.iter().map(|(name, version)| format!("{} {}", name, version)).collect();
+        crates.sort();
+        template
+            .replace("{version}", &self.version.to_string())
+            .replace("{crates}", &crates.join(", "))
+    }
+
+    /// The title of a release of `crates`, for release pull requests.
+    pub fn release_title(&self, crates: &HashMap<String, Version>) -> String {
+        if self.independent {
+            let mut crates: Vec<_> = crates.iter().map(|(name, version)| format!("{} {}", name, version)).collect();
+            crates.sort();
+            format!("Release {}", crates.join(", "))
+        } else {
+            format!("Release {}", self.tag_name(None, &self.version))
+        }
+    }
+
+    /// The branch release pull requests are opened from.
+    pub fn release_pr_branch(&self) -> &str {
+        self.release_pr_branch.as_deref().unwrap_or(DEFAULT_RELEASE_PR_BRANCH)
+    }
+
+    /// The tags marking a release of `crates`, with the crate each one is
+    /// for: one per crate if the template has `{crate}` or crates are
+    /// versioned independently, otherwise one for the shared version.
+    pub fn release_tags(&self, crates: &HashMap<String, Version>) -> BTreeMap<String, Option<String>> {
+        if self.independent || self.tag.contains("{crate}") {
+            crates.iter().map(|(name, version)| (self.tag_name(Some(name), version), Some(name.clone()))).collect()
+        } else {
+            BTreeMap::from([(self.tag_name(None, &self.version), None)])
+        }
+    }
+
+    /// The `owner/repo` GitHub releases are created in.
+    pub fn github_repo(&self, dir: &Path) -> Result<String, ArmoryError> {
+        if let Some(repo) = self.github_repo.clone().or_else(|| std::env::var("GITHUB_REPOSITORY").ok()) {
+            return Ok(repo);
+        }
+        let remote = self.push_remote.as_deref().unwrap_or("origin");
+        git::remote_url(dir, remote)
+            .and_then(|url| github::repo_from_url(&url))
+            .ok_or_else(|| format!("Couldn't tell the GitHub repository from remote {}, set github-repo in armory.toml", remote).into())
+    }
+
+    /// The URL of the GitLab instance releases are created on.
+    pub fn gitlab_url(&self) -> String {
+        self.gitlab_url.clone().unwrap_or_else(gitlab::default_url)
+    }
+
+    /// The path of the GitLab project releases are created in.
+    pub fn gitlab_project(&self, dir: &Path) -> Result<String, ArmoryError> {
+        if let Some(project) = self.gitlab_project.clone().or_else(|| std::env::var("CI_PROJECT_PATH").ok()) {
+            return Ok(project);
+        }
+        let remote = self.push_remote.as_deref().unwrap_or("origin");
+        git::remote_url(dir, remote)
+            .and_then(|url| gitlab::project_from_url(&url))
+            .ok_or_else(|| format!("Couldn't tell the GitLab project from remote {}, set gitlab-project in armory.toml", remote).into())
+    }
+
+    /// The tag of the last release, of `crate_name` if given: the tag of
+    /// the current version if it exists, otherwise the latest tag that fits
+    /// the template.
+    pub fn last_release_tag(&self, dir: &Path, crate_name: Option<&str>) -> Option<String> {
+        let version = match crate_name {
+            Some(crate_name) => self.version_of(crate_name),
+            None => &self.version,
+        };
+        let tag = self.tag_name(crate_name, version);
+        if git::tag_exists(dir, &tag) {
+            return Some(tag);
+        }
+
+        git::last_tag_matching(dir, &self.tag_pattern(crate_name))
+    }
+
+    /// Runs the `hook` for a release of `crates`, or of just `crate_name`
+    /// from them for the publish hooks, if armory.toml has one.
+    pub fn run_hook(
+        &self,
+        dir: &Path,
+        hook: Hook,
+        crates: &HashMap<String, Version>,
+        crate_name: Option<&str>,
+    ) -> Result<(), ArmoryError> {
+        let command = match hook {
+            Hook::PreBump => &self.hooks.pre_bump,
+            Hook::PostBump => &self.hooks.post_bump,
+            Hook::PrePublish => &self.hooks.pre_publish,
+            Hook::PostPublish => &self.hooks.post_publish,
+            Hook::PostRelease => &self.hooks.post_release,
+        };
+        let Some(command) = command else {
+            return Ok(());
+        };
+
+        let mut names: Vec<&str> = crates.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        let version = match crate_name {
+            Some(crate_name) => crates.get(crate_name),
+            None => (!self.independent).then_some(&self.version),
+        };
+
+        let mut shell = shell(command);
+        shell.current_dir(dir).env("ARMORY_HOOK", hook.name()).env("ARMORY_CRATES", names.join(" "));
+        if let Some(version) = version {
+            shell.env("ARMORY_VERSION", version.to_string());
+        }
+        if let Some(crate_name) = crate_name {
+            shell.env("ARMORY_CRATE", crate_name);
+        }
+
+        match crate_name {
+            Some(crate_name) => say!("ARMORY: running the {} hook for {}", hook.name(), crate_name),
+            None => say!("ARMORY: running the {} hook", hook.name()),
+        }
+        trace!("ARMORY: running `{}`", command);
+        let status = shell.status().map_err(|e| format!("Failed to run the {} hook: {}", hook.name(), e))?;
+        if !status.success() {
+            return Err(format!("The {} hook `{}` failed", hook.name(), command).into());
+        }
+        Ok(())
+    }
+
+    /// The members changed since their last release, with why, as
+    /// [`member_changes`] sees them.
+    pub fn changes(&self, dir: &Path) -> Result<BTreeMap<String, Change>, ArmoryError> {
+        member_changes(dir, |name| self.last_release_tag(dir, self.independent.then_some(name)))
+    }
+
+    /// Appends a release of `crates` to the history, stamped with the
+    /// current time and commit, and with the tags each crate gets if it's
+    /// `tagged`.
+    pub fn record_release(&mut self, dir: &Path, crates: &HashMap<String, Version>, tagged: bool) {
+        let date = output::timestamp(OffsetDateTime::now_utc());
+        let version = (!self.independent).then(|| self.version.clone());
+        let tags = if tagged {
+            self.release_tags(crates)
+                .into_iter()
+                .flat_map(|(tag, crate_name)| match crate_name {
+                    Some(crate_name) => vec![(crate_name, tag.clone())],
+                    None => crates.keys().map(|name| (name.clone(), tag.clone())).collect(),
+                })
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+
+        self.releases.push(Release {
+            version,
+            date,
+            sha: git::head_sha(dir).ok(),
+            crates: crates.iter().map(|(name, version)| (name.clone(), version.clone())).collect(),
+            tags,
+            checksums: crates
+                .iter()
+                .filter_map(|(name, version)| {
+                    let checksum = artifacts::sha256(&package_path(dir, name, version)).ok()?;
+                    Some((name.clone(), checksum))
+                })
+                .collect(),
+            smoke_test: None,
+        });
+    }
+
+    /// Adds saving the config to `edits`, as an edit that can be applied
+    /// along with manifest edits. If the config lives in the root
+    /// Cargo.toml and `edits` already rewrite it, that edit saves it too.
+    pub fn add_edit(&self, dir: &Path, edits: &mut Vec<ManifestEdit>) -> Result<(), ArmoryError> {
+        let path = config_path(dir);
+        match edits.iter_mut().find(|edit| edit.path == path) {
+            Some(edit) => edit.after = self.to_file(&path, &edit.after)?,
+            None => {
+                let existed = path.exists();
+                let before = fs::read_to_string(&path).unwrap_or_default();
+                let after = self.to_file(&path, &before)?;
+                edits.push(ManifestEdit { path, existed, before, after });
+            }
+        }
+        Ok(())
+    }
+
+    /// The contents of the config file at `path` once it holds this
+    /// config, given its `current` contents. A Cargo.toml keeps everything
+    /// but its `[workspace.metadata.armory]` table.
+    fn to_file(&self, path: &Path, current: &str) -> Result<String, ArmoryError> {
+        let config = match &self.replaced {
+            Some(replaced) => {
+                let mut saved = self.clone();
+                saved.scheme = replaced.scheme.unwrap_or_default();
+                saved.registry.clone_from(&replaced.registry);
+                saved.pre_release.clone_from(&replaced.pre_release);
+                saved.hooks.clone_from(&replaced.hooks);
+                toml::to_string(&saved)
+            }
+            None => toml::to_string(self),
+        };
+        let config = config.map_err(|e| format!("Failed to serialize armory.toml: {}", e))?;
+        if path.file_name().is_some_and(|name| name == "armory.toml") {
+            return Ok(config);
+        }
+        let mut manifest: Document = current.parse().map_err(|e| ArmoryError::parse(path, e))?;
+        let mut table = config
+            .parse::<Document>()
+            .map_err(|e| format!("Failed to serialize armory.toml: {}", e))?
+            .as_table()
+            .clone();
+        table.set_implicit(false);
+        manifest["workspace"]["metadata"]["armory"] = toml_edit::Item::Table(table);
+        Ok(manifest.to_string())
+    }
+
+    /// Whether `member` is on the exclude list.
+    pub fn is_excluded(&self, member: &Member) -> bool {
+        self.exclude.iter().any(|excluded| {
+            let excluded = excluded.trim().trim_end_matches('/');
+            excluded == member.name || excluded == member.path.trim_end_matches('/')
+        })
+    }
+
+    /// The members armory manages, i.e. all but the excluded ones.
+    pub fn members(&self, dir: &Path) -> Result<Vec<Member>, ArmoryError> {
+        Ok(workspace_members(dir)?.into_iter().filter(|member| !self.is_excluded(member)).collect())
+    }
+
+    /// The current version of a member, taking overrides and independent
+    /// mode into account.
+    pub fn version_of(&self, crate_name: &str) -> &Version {
+        if let Some(version) = self.overrides.get(crate_name) {
+            version
+        } else if self.independent {
+            self.versions.get(crate_name).unwrap_or(&self.version)
+        } else {
+            &self.version
+        }
+    }
+
+    /// Replaces the recorded versions with the highest ones published on
+    /// the registry. Returns every version that had drifted as
+    /// `(name, recorded, published)`, where the name is "workspace" for
+    /// the shared version.
+    pub fn sync_from_registry(&mut self, members: &[Member]) -> Result<Vec<(String, Version, Version)>, ArmoryError> {
+        let mut drifted = Vec::new();
+
+        if self.independent {
+            for member in members {
+                if self.overrides.contains_key(&member.name) {
+                    continue;
+                }
+                if let Some(published) = registry::latest_version(&member.name, self.registry_for(&member.name))? {
+                    let recorded = self.version_of(&member.name).clone();
+                    if published != recorded {
+                        drifted.push((member.name.clone(), recorded, published.clone()));
+                        self.versions.insert(member.name.clone(), published);
+                    }
+                }
+            }
+        } else {
+            let mut highest: Option<Version> = None;
+            for member in members {
+                if self.overrides.contains_key(&member.name) {
+                    continue;
+                }
+                highest = highest.max(registry::latest_version(&member.name, self.registry_for(&member.name))?);
+            }
+            if let Some(published) = highest {
+                if published != self.version {
+                    drifted.push(("workspace".to_string(), self.version.clone(), published.clone()));
+                    self.version = published;
+                }
+            }
+        }
+
+        Ok(drifted)
+    }
+
+    /// Pinned members whose manifest isn't at their pinned version yet,
+    /// and so need releasing.
+    pub fn changed_overrides(&self, members: &[Member]) -> HashMap<String, Version> {
+        members
+            .iter()
+            .filter_map(|member| {
+                let pinned = self.overrides.get(&member.name)?;
+                (member.version.as_ref() != Some(pinned))
+                    .then(|| (member.name.clone(), pinned.clone()))
+            })
+            .collect()
+    }
+}
+
+/// What cargo calls crates.io when it's named as a registry.
+const CRATES_IO_REGISTRY: &str = "crates-io";
+
+/// The branch release pull requests come from unless armory.toml names one.
+const DEFAULT_RELEASE_PR_BRANCH: &str = "armory/release";
+
+/// Whether `text` matches `pattern`, where `*` matches any run of
+/// characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=text.len())
+                .filter(|&index| text.is_char_boundary(index))
+                .any(|index| glob_match(rest, &text[index..]))
+        }
+    }
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+fn default_tag() -> String {
+    "v{version}".to_string()
+}
+
+fn is_default_tag(tag: &String) -> bool {
+    *tag == default_tag()
+}
+
+/// The file armory's config lives in: armory.toml in the workspace root,
+/// or else the root Cargo.toml if it has a `[workspace.metadata.armory]`
+/// table.
+pub fn config_path(workspace_dir: &Path) -> PathBuf {
+    let armory_toml = workspace_dir.join("armory.toml");
+    let manifest = workspace_dir.join("Cargo.toml");
+    if !armory_toml.exists() && manifest_config(&manifest).is_some() {
+        return manifest;
+    }
+    armory_toml
+}
+
+/// The `[workspace.metadata.armory]` table of the manifest at `path`.
+fn manifest_config(path: &Path) -> Option<toml::Value> {
+    let manifest: toml::Value = toml::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    manifest.get("workspace")?.get("metadata")?.get("armory").cloned()
+}
+
+/// Loads armory's config from wherever [`config_path`] finds it, along
+/// with the user's defaults. Unknown keys are errors, so typos don't go
+/// unnoticed.
+pub fn load_armory_toml(workspace_dir: &Path) -> Result<ArmoryTOML, ConfigError> {
+    let path = config_path(workspace_dir);
+    let source = fs::read_to_string(&path).map_err(|e| ConfigError::read(&path, e))?;
+    let mut armory_toml = config::parse(&path, &source)?;
+    armory_toml.user = config::load_user_config()?;
+    Ok(armory_toml)
 }
 
-pub fn load_armory_toml(workspace_dir: &Path) -> Result<ArmoryTOML, String> {
-    toml::from_str(
-        &fs::read_to_string(workspace_dir.join("armory.toml"))
-            .expect("Failed to read armory.toml in workspace root"),
-    )
-    .map_err(|_| "Failed to parse armory.toml".to_string())
+/// A config for the workspace at `dir` as it is: members released in
+/// lockstep from the highest of their versions, or each at its own version
+/// if `independent` or their versions differ.
+pub fn initial_config(dir: &Path, independent: bool) -> Result<ArmoryTOML, ArmoryError> {
+    let versions: BTreeMap<String, Version> = workspace_members(dir)?
+        .into_iter()
+        .filter_map(|member| Some((member.name, member.version?)))
+        .collect();
+    let version = versions.values().max().cloned().unwrap_or_else(|| Version::new(0, 1, 0));
+    let mut armory_toml = ArmoryTOML::new(version.clone());
+    if independent || versions.values().any(|other| *other != version) {
+        armory_toml.independent = true;
+        armory_toml.versions = versions;
+    }
+    Ok(armory_toml)
 }
 
-pub fn save_armory_toml(workspace_dir: &Path, armory_toml: &ArmoryTOML) {
-    let mut file = fs::File::create(workspace_dir.join("armory.toml")).unwrap();
-    file.write_all(toml::to_string(armory_toml)
-        .expect("Failed to serialize armory.toml")
-        .as_bytes()
-    ).expect("Failed to write armory.toml");
+pub fn save_armory_toml(workspace_dir: &Path, armory_toml: &ArmoryTOML) -> Result<(), ArmoryError> {
+    let path = config_path(workspace_dir);
+    let current = fs::read_to_string(&path).unwrap_or_default();
+    let config = armory_toml.to_file(&path, &current)?;
+    fs::write(&path, config).map_err(|e| ArmoryError::io("write", &path, e))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,124 +1183,2561 @@ struct WorkspaceManifest {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct WorkspaceDefinition {
     pub members: Vec<String>,
+    #[serde(default)]
+    pub package: Option<WorkspacePackage>,
 }
 
-fn update_member_deps(dir: &Path, version: &Version) -> HashMap<String, HashSet<String>>{
-    // directed acyclic graph to figure out which dependencies
-    // to publish first.
-    let mut graph: HashMap<String, HashSet<String>> = HashMap::new();
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WorkspacePackage {
+    pub version: Option<Version>,
+    #[serde(default)]
+    pub publish: Option<PublishField>,
+}
 
-    let workspace_toml: WorkspaceManifest = toml::from_str(
-        &fs::read_to_string(dir.join("Cargo.toml"))
-            .expect("Failed to read Cargo.toml in workspace root"),
-    ).expect("Failed to parse Cargo.toml in workspace root");
+/// A manifest's `publish` key: `false`, or the registries it may go to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum PublishField {
+    Bool(bool),
+    Registries(Vec<String>),
+}
 
-    for member in workspace_toml.workspace.members {
-        let member_dir = dir.join(&member);
-        let member_toml = fs::read_to_string(member_dir.join("Cargo.toml")).unwrap();
-        let mut member_toml = member_toml.parse::<Document>().unwrap();
-        let mut local_deps = HashSet::new();
+impl PublishField {
+    fn from_item(item: &toml_edit::Item) -> Option<Self> {
+        if let Some(publish) = item.as_bool() {
+            return Some(PublishField::Bool(publish));
+        }
+        let registries = item.as_array()?
+            .iter()
+            .filter_map(|registry| registry.as_str().map(|registry| registry.to_string()))
+            .collect();
+        Some(PublishField::Registries(registries))
+    }
 
-        member_toml["package"]["version"] = toml_edit::value(version.to_string());
-        let deps = member_toml.get_mut("dependencies").map(|deps| deps.as_table_mut());
-        match deps {
-            Some(Some(table)) => {
-                for (name, dep) in table.iter_mut() {
-                    if let Some(dep) = dep.as_table_like_mut() {
-                        if let Some(Some(_)) = dep.get("path").map(|dep| dep.as_str()) {
-                            // this is a local dependency, so we will need to update the version
-                            dep.insert("version", toml_edit::value(version.to_string()));
-                            local_deps.insert(name.trim().into());
-                        }
-                    }
-                }
-            }
-            _ => {}
+    fn registries(self) -> Option<Vec<String>> {
+        match self {
+            PublishField::Bool(true) => None,
+            PublishField::Bool(false) => Some(Vec::new()),
+            PublishField::Registries(registries) => Some(registries),
         }
+    }
+}
+
+/// Whether a manifest value is inherited from the workspace,
+/// i.e. `key.workspace = true`.
+fn is_inherited(item: Option<&toml_edit::Item>) -> bool {
+    item.and_then(|item| item.as_table_like()).is_some_and(inherits_workspace)
+}
 
-        let mut file = fs::File::create(member_dir.join("Cargo.toml")).unwrap();
-        file.write_all(member_toml.to_string().as_bytes()).unwrap();
+fn inherits_workspace(table: &dyn toml_edit::TableLike) -> bool {
+    table.get("workspace")
+        .and_then(|workspace| workspace.as_bool())
+        .unwrap_or(false)
+}
 
+/// Replaces a string value in a manifest, keeping its comments and
+/// surrounding whitespace.
+fn set_string(item: &mut toml_edit::Item, value: &str) {
+    let decor = item.as_value().map(|current| current.decor().clone());
+    *item = toml_edit::value(value);
+    if let (Some(decor), Some(value)) = (decor, item.as_value_mut()) {
+        *value.decor_mut() = decor;
+    }
+}
 
-        graph.insert(member.trim().into(), local_deps);
+/// Sets a string key of a dependency entry, tidying up the entry's
+/// formatting if the key is new.
+fn set_dependency_key(dep: &mut dyn toml_edit::TableLike, key: &str, value: &str) {
+    match dep.get_mut(key) {
+        Some(item) => set_string(item, value),
+        None => {
+            dep.insert(key, toml_edit::value(value));
+            dep.fmt();
+        }
     }
+}
 
-    // now we have a graph of dependencies, we can figure out which
-    // dependencies to publish first, in the next stage
-    graph
+/// Sets the version requirement of a local dependency entry to `version`,
+/// written as `requirement` says.
+fn set_dependency_version(dep: &mut dyn toml_edit::TableLike, version: &Version, requirement: Requirement) {
+    let current = dep.get("version").and_then(|current| current.as_str());
+    let value = requirement.format(current, version);
+    set_dependency_key(dep, "version", &value);
+}
+
+/// The crate a dependency entry points at, which differs from its key when
+/// it's renamed with `package = "..."`.
+fn dependency_name(key: &str, dep: &dyn toml_edit::TableLike) -> String {
+    dep.get("package")
+        .and_then(|package| package.as_str())
+        .unwrap_or(key)
+        .trim()
+        .to_string()
+}
+
+/// A workspace member: its crate name and the directory it lives in,
+/// relative to the workspace root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    pub path: String,
+    /// The version in the member's manifest, if it declares one.
+    pub version: Option<Version>,
+    /// The registries the member may be published to, from its `publish`
+    /// key; empty for `publish = false` and `None` for anywhere.
+    pub publish: Option<Vec<String>>,
 }
 
-pub fn publish_workspace(dir: &Path, version: &Version) {
+impl Member {
+    /// Whether the member's manifest allows publishing it to `registry`,
+    /// or crates.io if `None`.
+    pub fn can_publish_to(&self, registry: Option<&str>) -> bool {
+        let registry = registry.unwrap_or(CRATES_IO_REGISTRY);
+        self.publish
+            .as_ref()
+            .is_none_or(|registries| registries.iter().any(|allowed| allowed == registry))
+    }
+}
 
-    let graph = update_member_deps(dir, version);
+/// The root of the workspace `dir` is in, the way cargo finds it: the
+/// closest package above `dir`, then the workspace its `package.workspace`
+/// points to or the closest directory above it with a `[workspace]`
+/// table. A package outside any workspace is its own root.
+pub fn workspace_root(dir: &Path) -> Result<PathBuf, ArmoryError> {
+    let dir = fs::canonicalize(dir).map_err(|e| ArmoryError::io("find", dir, e))?;
+    let read = |dir: &Path| -> Result<Option<toml::Value>, ArmoryError> {
+        let path = dir.join("Cargo.toml");
+        let Ok(source) = fs::read_to_string(&path) else {
+            return Ok(None);
+        };
+        toml::from_str(&source).map(Some).map_err(|e| ArmoryError::parse(&path, e))
+    };
 
-    let mut already_published: HashSet<String> = HashSet::new();
+    let mut package = None;
+    for ancestor in dir.ancestors() {
+        if let Some(manifest) = read(ancestor)? {
+            package = Some((ancestor, manifest));
+            break;
+        }
+    }
+    let Some((package_dir, manifest)) = package else {
+        return Err(format!("Couldn't find a Cargo.toml in {} or any directory above it", dir.display()).into());
+    };
 
-    for current_package in graph.keys() {
-        publish_crate(
-            dir,
-            current_package,
-            &graph,
-            &mut already_published,
-        )
+    let workspace = manifest.get("package").and_then(|package| package.get("workspace"));
+    if let Some(workspace) = workspace.and_then(|path| path.as_str()) {
+        let root = package_dir.join(workspace);
+        return fs::canonicalize(&root).map_err(|e| ArmoryError::io("find", &root, e));
     }
+    for ancestor in package_dir.ancestors() {
+        if read(ancestor)?.is_some_and(|manifest| manifest.get("workspace").is_some()) {
+            return Ok(ancestor.to_path_buf());
+        }
+    }
+    Ok(package_dir.to_path_buf())
 }
 
-fn publish_crate(
-    dir: &Path,
-    current_package: &str,
-    all_packages: &HashMap<String, HashSet<String>>,
-    already_published: &mut HashSet<String>,
-) {
-
-    if already_published.contains(current_package) {
-        return;
-    }
-    // publish all the local dependencies first
-    for local_dep in all_packages.get(current_package).unwrap() {
-        if !already_published.contains(local_dep) {
-            publish_crate(dir, local_dep, all_packages, already_published);
-        }
-    }
-
-    retry_with_index(delay::Fibonacci::from_millis(4000), |current_try| {
-        let cfg = Config::default().unwrap();
-        cfg.set_values(cfg.load_values().unwrap()).unwrap();
-        cfg.load_credentials().unwrap();
-
-        let workspace = Workspace::new(&dir.clone().join("Cargo.toml"), &cfg).unwrap();
-
-        match cargo::ops::publish(
-            &workspace,
-            &PublishOpts {
-                token: None,
-                config: &cfg,
-                verify: false,
-                allow_dirty: true,
-                registry: None,
-                dry_run: false,
-                targets: vec![],
-                to_publish: Packages::Packages(vec![current_package.to_string()]),
-                cli_features: CliFeatures::new_all(true),
-                index: None,
-                jobs: None,
-                keep_going: false,
-            },
-        ) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                if current_try > 5{
-                    panic!("ARMORY: failed to publish {} after {} attempts: {:#?}",
-                            current_package, current_try, e);
-                } else {
-                    println!("ARMORY: failed to publish {} after {} attempts: {:#?}",
-                        current_package, current_try, e);
-                }
-                Err(e)
+/// Lists the members of the workspace in the order they are declared.
+pub fn workspace_members(dir: &Path) -> Result<Vec<Member>, ArmoryError> {
+    let root_path = dir.join("Cargo.toml");
+    let root_source = fs::read_to_string(&root_path).map_err(|e| ArmoryError::io("read", &root_path, e))?;
+    let workspace_toml: WorkspaceManifest =
+        toml::from_str(&root_source).map_err(|e| ArmoryError::parse(&root_path, e))?;
+    let workspace_package = workspace_toml.workspace.package;
+    let workspace_version = workspace_package.as_ref().and_then(|package| package.version.clone());
+    let workspace_publish = workspace_package
+        .and_then(|package| package.publish)
+        .and_then(PublishField::registries);
+
+    workspace_toml.workspace.members
+        .into_iter()
+        .map(|path| {
+            let member_path = dir.join(&path).join("Cargo.toml");
+            let member_toml = fs::read_to_string(&member_path)
+                .map_err(|e| ArmoryError::io("read", &member_path, e))?
+                .parse::<Document>()
+                .map_err(|e| ArmoryError::parse(&member_path, e))?;
+            let package = member_toml
+                .get("package")
+                .filter(|package| package.is_table_like())
+                .ok_or_else(|| ArmoryError::parse(&member_path, "a workspace member needs a [package]"))?;
+            let name = package
+                .get("name")
+                .and_then(|name| name.as_str())
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| path.trim().to_string());
+            let version = if is_inherited(package.get("version")) {
+                workspace_version.clone()
+            } else {
+                package
+                    .get("version")
+                    .and_then(|version| version.as_str())
+                    .and_then(|version| version.parse().ok())
+            };
+            let publish = if is_inherited(package.get("publish")) {
+                workspace_publish.clone()
+            } else {
+                package
+                    .get("publish")
+                    .and_then(PublishField::from_item)
+                    .and_then(PublishField::registries)
+            };
+            Ok(Member { name, path: path.trim().to_string(), version, publish })
+        })
+        .collect()
+}
+
+/// A rewrite of a manifest, as planned by [`plan_bump`], or of armory.toml.
+#[derive(Debug, Clone)]
+pub struct ManifestEdit {
+    pub path: PathBuf,
+    /// Whether the file existed before the edit. Reverting the edit of one
+    /// that didn't removes it.
+    pub existed: bool,
+    pub before: String,
+    pub after: String,
+}
+
+impl ManifestEdit {
+    /// A unified diff of the edit, with paths relative to `dir`.
+    pub fn diff(&self, dir: &Path) -> String {
+        let path = self.path.strip_prefix(dir).unwrap_or(&self.path).display().to_string();
+        TextDiff::from_lines(&self.before, &self.after)
+            .unified_diff()
+            .header(&format!("a/{}", path), &format!("b/{}", path))
+            .to_string()
+    }
+
+    pub fn apply(&self) -> Result<(), ArmoryError> {
+        fs::write(&self.path, &self.after).map_err(|e| ArmoryError::io("write", &self.path, e))
+    }
+
+    /// Puts the file back the way it was before the edit, removing it and
+    /// the directories left empty if it's new.
+    pub fn revert(&self) -> Result<(), ArmoryError> {
+        if self.existed {
+            return fs::write(&self.path, &self.before).map_err(|e| ArmoryError::io("restore", &self.path, e));
+        }
+        match fs::remove_file(&self.path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(ArmoryError::io("remove", &self.path, e)),
+        }
+        // apply_edits creates the directories new files go in, and only
+        // removing a directory that's empty fails once one isn't
+        for dir in self.path.ancestors().skip(1) {
+            if fs::remove_dir(dir).is_err() {
+                break;
             }
         }
-    })
-    .unwrap();
+        Ok(())
+    }
+}
+
+/// Writes `edits` all together: they're checked to still apply and, for
+/// TOML files, to produce valid TOML first, and if writing one fails, the
+/// ones already written are reverted.
+pub fn apply_edits(edits: &[ManifestEdit]) -> Result<(), ArmoryError> {
+    for edit in edits {
+        let current = fs::read_to_string(&edit.path).unwrap_or_default();
+        if edit.path.exists() != edit.existed || current != edit.before {
+            return Err(format!("{} changed since the edit was planned", edit.path.display()).into());
+        }
+        if edit.path.extension().is_some_and(|extension| extension == "toml") {
+            edit.after.parse::<Document>().map_err(|e| ArmoryError::parse(&edit.path, e))?;
+        }
+    }
 
-    already_published.insert(current_package.to_string());
+    for (index, edit) in edits.iter().enumerate() {
+        // changelogs can be configured to live in directories that don't
+        // exist yet
+        if let Some(parent) = edit.path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(e) = fs::write(&edit.path, &edit.after) {
+            revert_edits(&edits[..index])?;
+            return Err(ArmoryError::io("write", &edit.path, e));
+        }
+    }
+    Ok(())
+}
+
+/// Undoes edits written by [`apply_edits`].
+pub fn revert_edits(edits: &[ManifestEdit]) -> Result<(), ArmoryError> {
+    for edit in edits.iter().rev() {
+        edit.revert()?;
+    }
+    Ok(())
+}
+
+/// The local dependencies of every member, keyed by crate name.
+pub fn dependency_graph(dir: &Path) -> Result<HashMap<String, HashSet<String>>, ArmoryError> {
+    Ok(update_member_deps(dir, None, &HashMap::new())?.0)
+}
+
+/// The dependencies of every member from outside the workspace, from a
+/// registry or git, keyed by crate name. Build and target-specific
+/// dependencies count, dev-dependencies don't.
+pub fn external_dependencies(dir: &Path) -> Result<HashMap<String, BTreeSet<String>>, ArmoryError> {
+    let root_toml = fs::read_to_string(dir.join("Cargo.toml"))
+        .ok()
+        .and_then(|source| source.parse::<Document>().ok());
+    // the crates in [workspace.dependencies], keyed by the name members
+    // inherit them with, and `None` for local ones
+    let mut workspace_deps: HashMap<String, Option<String>> = HashMap::new();
+    let workspace_table = root_toml.as_ref().and_then(|root| root.get("workspace")?.get("dependencies")?.as_table_like());
+    for (key, dep) in workspace_table.into_iter().flat_map(|table| table.iter()) {
+        workspace_deps.insert(key.trim().to_string(), external_dependency(key, dep));
+    }
+
+    Ok(workspace_members(dir)?
+        .into_iter()
+        .map(|member| {
+            let member_toml = fs::read_to_string(dir.join(&member.path).join("Cargo.toml"))
+                .ok()
+                .and_then(|source| source.parse::<Document>().ok());
+            let mut tables = Vec::new();
+            if let Some(member_toml) = &member_toml {
+                let targets = member_toml.get("target").and_then(|targets| targets.as_table_like());
+                for table in std::iter::once(member_toml.as_item()).chain(targets.into_iter().flat_map(|targets| {
+                    targets.iter().map(|(_, target)| target)
+                })) {
+                    tables.extend(table.get("dependencies").and_then(|deps| deps.as_table_like()));
+                    tables.extend(table.get("build-dependencies").and_then(|deps| deps.as_table_like()));
+                }
+            }
+            let deps = tables
+                .into_iter()
+                .flat_map(|table| table.iter())
+                .filter_map(|(key, dep)| match dep.as_table_like() {
+                    Some(table) if inherits_workspace(table) => workspace_deps.get(key.trim()).cloned().flatten(),
+                    _ => external_dependency(key, dep),
+                })
+                .collect();
+            (member.name, deps)
+        })
+        .collect())
+}
+
+/// The crate `dep` under `key` depends on, unless it's a local path
+/// dependency.
+fn external_dependency(key: &str, dep: &toml_edit::Item) -> Option<String> {
+    match dep.as_table_like() {
+        Some(table) if table.contains_key("path") => None,
+        Some(table) => Some(dependency_name(key, table)),
+        None => Some(key.trim().to_string()),
+    }
+}
+
+/// The dependency graph of the members armory would publish, leaving out
+/// excluded members and those whose manifest doesn't allow publishing them
+/// to their registry.
+pub fn publish_graph(dir: &Path, armory_toml: &ArmoryTOML) -> Result<HashMap<String, HashSet<String>>, ArmoryError> {
+    let publishable: HashSet<String> = armory_toml.members(dir)?
+        .into_iter()
+        .filter(|member| member.can_publish_to(armory_toml.registry_for(&member.name)))
+        .map(|member| member.name)
+        .collect();
+
+    Ok(with_publish_after(dependency_graph(dir)?, armory_toml)
+        .into_iter()
+        .filter(|(name, _)| publishable.contains(name))
+        .map(|(name, deps)| {
+            let deps = deps.into_iter().filter(|dep| publishable.contains(dep)).collect();
+            (name, deps)
+        })
+        .collect())
+}
+
+/// `graph` with the crates each one's `[crate.NAME].publish-after` lists
+/// added to its dependencies, so they're published before it. Crates
+/// `graph` doesn't have are left out.
+pub fn with_publish_after(
+    mut graph: HashMap<String, HashSet<String>>,
+    armory_toml: &ArmoryTOML,
+) -> HashMap<String, HashSet<String>> {
+    let names: HashSet<String> = graph.keys().cloned().collect();
+    for (name, deps) in graph.iter_mut() {
+        if let Some(config) = armory_toml.crates.get(name) {
+            deps.extend(config.publish_after.iter().filter(|after| names.contains(*after)).cloned());
+        }
+    }
+    graph
+}
+
+/// `crates` along with every local dependency they need, directly or not.
+pub fn with_dependencies(graph: &HashMap<String, HashSet<String>>, crates: &HashSet<String>) -> HashSet<String> {
+    let mut needed = crates.clone();
+    let mut queue: Vec<String> = crates.iter().cloned().collect();
+    while let Some(name) = queue.pop() {
+        for dep in graph.get(&name).into_iter().flatten() {
+            if needed.insert(dep.clone()) {
+                queue.push(dep.clone());
+            }
+        }
+    }
+    needed
+}
+
+/// The members armory manages that `only` and `exclude` pick, with their
+/// versions. Crates picked with `only` bring the local dependencies they
+/// need along, and picking every crate takes leaving `only` empty.
+pub fn select_members(
+    dir: &Path,
+    armory_toml: &ArmoryTOML,
+    only: &[String],
+    exclude: &[String],
+) -> Result<HashMap<String, Version>, ArmoryError> {
+    let members = armory_toml.members(dir)?;
+    for name in only.iter().chain(exclude) {
+        if !members.iter().any(|member| member.name == *name) {
+            return Err(format!("{} isn't a member armory publishes", name).into());
+        }
+    }
+    let crates = if only.is_empty() {
+        members.iter().map(|member| member.name.clone()).collect()
+    } else {
+        with_dependencies(&dependency_graph(dir)?, &only.iter().cloned().collect())
+    };
+    Ok(members
+        .into_iter()
+        .filter(|member| crates.contains(&member.name) && !exclude.contains(&member.name))
+        .filter_map(|member| Some((member.name, member.version?)))
+        .collect())
+}
+
+/// The dev-dependencies of members on other members that only have a
+/// path, as `(member, dependency)` pairs.
+pub fn path_only_dev_dependencies(dir: &Path) -> Result<Vec<(String, String)>, ArmoryError> {
+    let members = workspace_members(dir)?;
+    let mut found = Vec::new();
+    for member in &members {
+        let Ok(source) = fs::read_to_string(dir.join(&member.path).join("Cargo.toml")) else {
+            continue;
+        };
+        let Ok(member_toml) = source.parse::<Document>() else {
+            continue;
+        };
+        let Some(table) = member_toml.get("dev-dependencies").and_then(|deps| deps.as_table_like()) else {
+            continue;
+        };
+        for (name, dep) in table.iter() {
+            let Some(dep) = dep.as_table_like() else {
+                continue;
+            };
+            let dep_name = dependency_name(name, dep);
+            let is_member = members.iter().any(|other| other.name == dep_name);
+            if is_member && dep.contains_key("path") && !dep.contains_key("version") {
+                found.push((member.name.clone(), dep_name));
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Fails if `dev-dependencies = "error"` and some member has a path-only
+/// dev-dependency on another.
+pub fn check_dev_dependencies(dir: &Path, armory_toml: &ArmoryTOML) -> Result<(), ArmoryError> {
+    if armory_toml.dev_dependencies != DevDependencies::Error {
+        return Ok(());
+    }
+    let managed: HashSet<String> = armory_toml.members(dir)?.into_iter().map(|member| member.name).collect();
+    let found: Vec<(String, String)> = path_only_dev_dependencies(dir)?
+        .into_iter()
+        .filter(|(member, _)| managed.contains(member))
+        .collect();
+    if found.is_empty() {
+        return Ok(());
+    }
+    let mut message = "These dev-dependencies only have a path, which dev-dependencies = \"error\" refuses:".to_string();
+    for (member, dep) in found {
+        message.push_str(&format!("\n    {} -> {}", member, dep));
+    }
+    Err(message.into())
+}
+
+/// The dependencies of the members armory would publish on members it
+/// wouldn't, being excluded or not allowed to be published by their
+/// manifest, as `(member, dependency)` pairs. Publishing such a member
+/// fails, as the registry doesn't have its dependency.
+pub fn unpublishable_dependencies(dir: &Path, armory_toml: &ArmoryTOML) -> Result<Vec<(String, String)>, ArmoryError> {
+    let graph = publish_graph(dir, armory_toml)?;
+    let members: HashSet<String> = workspace_members(dir)?.into_iter().map(|member| member.name).collect();
+    let mut found: Vec<(String, String)> = dependency_graph(dir)?
+        .into_iter()
+        .filter(|(name, _)| graph.contains_key(name))
+        .flat_map(|(name, deps)| deps.into_iter().map(move |dep| (name.clone(), dep)))
+        .filter(|(_, dep)| members.contains(dep) && !graph.contains_key(dep))
+        .collect();
+    found.sort();
+    Ok(found)
+}
+
+/// A cycle among the local dependencies in `graph`, as the crates along
+/// it with the first one repeated at the end, if there's one. Cargo can't
+/// publish any crate on a cycle, as each needs another published first.
+pub fn dependency_cycle(graph: &HashMap<String, HashSet<String>>) -> Option<Vec<String>> {
+    fn visit(
+        name: &str,
+        graph: &HashMap<String, HashSet<String>>,
+        path: &mut Vec<String>,
+        done: &mut HashSet<String>,
+    ) -> Option<Vec<String>> {
+        if let Some(start) = path.iter().position(|visited| visited == name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name.to_string());
+            return Some(cycle);
+        }
+        if done.contains(name) {
+            return None;
+        }
+        path.push(name.to_string());
+        let mut deps: Vec<&String> = graph.get(name).into_iter().flatten().collect();
+        deps.sort();
+        for dep in deps {
+            if let Some(cycle) = visit(dep, graph, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(name.to_string());
+        None
+    }
+
+    let mut names: Vec<&String> = graph.keys().collect();
+    names.sort();
+    let mut done = HashSet::new();
+    names.into_iter().find_map(|name| visit(name, graph, &mut Vec::new(), &mut done))
+}
+
+/// The metadata crates.io requires that the members armory would publish
+/// lack, keyed by member: a `description`, and a `license` or
+/// `license-file`. Keys inherited from `[workspace.package]` count.
+pub fn missing_metadata(dir: &Path, armory_toml: &ArmoryTOML) -> Result<BTreeMap<String, Vec<String>>, ArmoryError> {
+    let graph = publish_graph(dir, armory_toml)?;
+    let root_toml = fs::read_to_string(dir.join("Cargo.toml"))
+        .ok()
+        .and_then(|source| source.parse::<Document>().ok());
+    let workspace_package = root_toml.as_ref().and_then(|root| root.get("workspace")?.get("package")?.as_table_like());
+
+    let mut missing = BTreeMap::new();
+    for member in workspace_members(dir)? {
+        if !graph.contains_key(&member.name) {
+            continue;
+        }
+        let member_toml = fs::read_to_string(dir.join(&member.path).join("Cargo.toml"))
+            .ok()
+            .and_then(|source| source.parse::<Document>().ok());
+        let package = member_toml.as_ref().and_then(|member_toml| member_toml.get("package")?.as_table_like());
+        let has = |key: &str| {
+            let Some(value) = package.and_then(|package| package.get(key)) else {
+                return false;
+            };
+            match value.as_table_like() {
+                Some(table) if inherits_workspace(table) => workspace_package.is_some_and(|package| package.contains_key(key)),
+                _ => value.as_str().is_some_and(|value| !value.trim().is_empty()),
+            }
+        };
+        let mut fields = Vec::new();
+        if !has("description") {
+            fields.push("description".to_string());
+        }
+        if !has("license") && !has("license-file") {
+            fields.push("license or license-file".to_string());
+        }
+        if !fields.is_empty() {
+            missing.insert(member.name, fields);
+        }
+    }
+    Ok(missing)
+}
+
+/// Orders the members of `graph` so that every crate comes after its local
+/// dependencies, breaking ties by name.
+pub fn publish_order(graph: &HashMap<String, HashSet<String>>) -> Vec<String> {
+    fn visit(name: &str, graph: &HashMap<String, HashSet<String>>, order: &mut Vec<String>, seen: &mut HashSet<String>) {
+        if !seen.insert(name.to_string()) {
+            return;
+        }
+        let mut deps: Vec<&String> = graph.get(name).into_iter().flatten().collect();
+        deps.sort();
+        for dep in deps {
+            visit(dep, graph, order, seen);
+        }
+        order.push(name.to_string());
+    }
+
+    let mut names: Vec<&String> = graph.keys().collect();
+    names.sort();
+    let mut order = Vec::new();
+    let mut seen = HashSet::new();
+    for name in names {
+        visit(name, graph, &mut order, &mut seen);
+    }
+    order
+}
+
+/// Splits the members of `graph` into groups that can be published in
+/// parallel: every crate's local dependencies are in earlier groups.
+pub fn publish_groups(graph: &HashMap<String, HashSet<String>>) -> Vec<Vec<String>> {
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    let mut placed: HashMap<String, usize> = HashMap::new();
+
+    // in publish order, a crate's dependencies are always placed first
+    for name in publish_order(graph) {
+        let group = graph.get(&name)
+            .into_iter()
+            .flatten()
+            .filter_map(|dep| placed.get(dep))
+            .map(|group| group + 1)
+            .max()
+            .unwrap_or(0);
+        if group == groups.len() {
+            groups.push(Vec::new());
+        }
+        groups[group].push(name.clone());
+        placed.insert(name, group);
+    }
+
+    for group in &mut groups {
+        group.sort();
+    }
+    groups
+}
+
+/// The chains of local dependencies that put `name` in its
+/// [`publish_groups`] group: each starts at `name` and goes down through a
+/// dependency in the group before, to a crate in the first group. Empty if
+/// `name` is in the first group, or not in `graph`.
+pub fn publish_chains(graph: &HashMap<String, HashSet<String>>, name: &str) -> Vec<Vec<String>> {
+    fn extend(
+        chain: &mut Vec<String>,
+        graph: &HashMap<String, HashSet<String>>,
+        group_of: &HashMap<&str, usize>,
+        chains: &mut Vec<Vec<String>>,
+    ) {
+        let name = chain.last().unwrap().clone();
+        let group = group_of[name.as_str()];
+        let mut deps: Vec<&String> = graph[&name]
+            .iter()
+            .filter(|dep| group_of.get(dep.as_str()).is_some_and(|dep_group| dep_group + 1 == group))
+            .collect();
+        if deps.is_empty() {
+            chains.push(chain.clone());
+            return;
+        }
+        deps.sort();
+        for dep in deps {
+            chain.push(dep.clone());
+            extend(chain, graph, group_of, chains);
+            chain.pop();
+        }
+    }
+
+    let groups = publish_groups(graph);
+    let group_of: HashMap<&str, usize> = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(index, group)| group.iter().map(move |name| (name.as_str(), index)))
+        .collect();
+    if group_of.get(name).copied().unwrap_or(0) == 0 {
+        return Vec::new();
+    }
+    let mut chains = Vec::new();
+    extend(&mut vec![name.to_string()], graph, &group_of, &mut chains);
+    chains
+}
+
+/// The local dependencies of every member, and the edits to their
+/// manifests, from [`update_member_deps`].
+type MemberDeps = (HashMap<String, HashSet<String>>, Vec<ManifestEdit>);
+
+/// Works out the edits that stamp `versions` on the members and their local
+/// dependency requirements. With a config, this also applies its
+/// rust-version and registries, and leaves excluded members alone.
+/// Nothing is written. Fails if members inheriting the workspace version
+/// are given different versions.
+fn update_member_deps(
+    dir: &Path,
+    armory_toml: Option<&ArmoryTOML>,
+    versions: &HashMap<String, Version>,
+) -> Result<MemberDeps, ArmoryError> {
+    let members = workspace_members(dir)?;
+    let skipped: HashSet<String> = members
+        .iter()
+        .filter(|member| armory_toml.is_some_and(|armory_toml| armory_toml.is_excluded(member)))
+        .map(|member| member.name.clone())
+        .collect();
+    let versions: HashMap<String, Version> = versions
+        .iter()
+        .filter(|(name, _)| !skipped.contains(*name))
+        .map(|(name, version)| (name.clone(), version.clone()))
+        .collect();
+    let rust_version = armory_toml.and_then(|armory_toml| armory_toml.rust_version.as_deref());
+    let registry_for = |name: &str| armory_toml.and_then(|armory_toml| armory_toml.registry_for(name));
+    let requirement = armory_toml.map(|armory_toml| armory_toml.dependency_requirement).unwrap_or_default();
+    let dev_dependencies = armory_toml.map(|armory_toml| armory_toml.dev_dependencies).unwrap_or_default();
+    let current_versions: HashMap<String, Version> = members
+        .iter()
+        .filter_map(|member| Some((member.name.clone(), member.version.clone()?)))
+        .collect();
+
+    // directed acyclic graph to figure out which dependencies
+    // to publish first.
+    let mut graph: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut edits = Vec::new();
+    let mut edit = |path: PathBuf, before: String, after: String| {
+        if before != after {
+            edits.push(ManifestEdit { path, existed: true, before, after });
+        }
+    };
+
+    let root_path = dir.join("Cargo.toml");
+    let root_source = fs::read_to_string(&root_path).map_err(|e| ArmoryError::io("read", &root_path, e))?;
+    let mut root_toml = root_source.parse::<Document>().map_err(|e| ArmoryError::parse(&root_path, e))?;
+
+    // local crates declared once in [workspace.dependencies], keyed by the
+    // name members use to inherit them
+    let mut workspace_deps: HashMap<String, String> = HashMap::new();
+    if let Some(table) = root_toml["workspace"].get_mut("dependencies").and_then(|deps| deps.as_table_like_mut()) {
+        for (key, dep) in table.iter_mut() {
+            if let Some(dep) = dep.as_table_like_mut() {
+                if let Some(Some(_)) = dep.get("path").map(|dep| dep.as_str()) {
+                    let dep_name = dependency_name(key.get(), dep);
+                    if let Some(version) = versions.get(&dep_name) {
+                        set_dependency_version(dep, version, requirement);
+                    }
+                    if let Some(registry) = registry_for(&dep_name) {
+                        set_dependency_key(dep, "registry", registry);
+                    }
+                    workspace_deps.insert(key.get().trim().to_string(), dep_name);
+                }
+            }
+        }
+    }
+
+    // the version shared by members with `version.workspace = true`
+    let mut inherited_version: Option<(String, Version)> = None;
+    let mut inherited_rust_version = false;
+    // (member, dependency) for versioned dev-dependencies
+    let mut dev_edges: Vec<(String, String)> = Vec::new();
+
+    for member in members {
+        let member_dir = dir.join(&member.path);
+        let member_path = member_dir.join("Cargo.toml");
+        let member_source = fs::read_to_string(&member_path).map_err(|e| ArmoryError::io("read", &member_path, e))?;
+        let mut member_toml = member_source.parse::<Document>().map_err(|e| ArmoryError::parse(&member_path, e))?;
+        let mut local_deps = HashSet::new();
+
+        if let Some(version) = versions.get(&member.name) {
+            if is_inherited(member_toml["package"].get("version")) {
+                // the version lives in the root manifest, so bump it there
+                // rather than breaking the inheritance
+                match &inherited_version {
+                    Some((other, other_version)) if other_version != version => return Err(format!(
+                        "{} and {} both inherit the workspace version but are released as {} and {}",
+                        other, member.name, other_version, version
+                    ).into()),
+                    _ => inherited_version = Some((member.name.clone(), version.clone())),
+                }
+            } else {
+                set_string(&mut member_toml["package"]["version"], &version.to_string());
+            }
+        }
+        if let Some(rust_version) = rust_version.filter(|_| !skipped.contains(&member.name)) {
+            if is_inherited(member_toml["package"].get("rust-version")) {
+                inherited_rust_version = true;
+            } else {
+                set_string(&mut member_toml["package"]["rust-version"], rust_version);
+            }
+        }
+        let deps = member_toml.get_mut("dependencies").map(|deps| deps.as_table_mut());
+        if let Some(Some(table)) = deps {
+            for (name, dep) in table.iter_mut() {
+                if let Some(dep) = dep.as_table_like_mut() {
+                    if let Some(Some(_)) = dep.get("path").map(|dep| dep.as_str()) {
+                        // this is a local dependency, so we will need to update the version
+                        let dep_name = dependency_name(name.get(), dep);
+                        if let Some(version) = versions.get(&dep_name) {
+                            set_dependency_version(dep, version, requirement);
+                        }
+                        // cargo needs to know where the dependency will
+                        // be published to package it
+                        if let Some(registry) = registry_for(&dep_name) {
+                            set_dependency_key(dep, "registry", registry);
+                        }
+                        local_deps.insert(dep_name);
+                    } else if inherits_workspace(dep) {
+                        // inherited from [workspace.dependencies], which we've already updated
+                        if let Some(dep_name) = workspace_deps.get(name.get().trim()) {
+                            local_deps.insert(dep_name.clone());
+                        }
+                    }
+                }
+            }
+        }
+        // dev-dependencies only need publishing first when they have a
+        // version, otherwise cargo strips them
+        let dev_deps = member_toml.get_mut("dev-dependencies").map(|deps| deps.as_table_mut());
+        if let Some(Some(table)) = dev_deps {
+            for (name, dep) in table.iter_mut() {
+                let Some(dep) = dep.as_table_like_mut() else {
+                    continue;
+                };
+                if let Some(Some(_)) = dep.get("path").map(|dep| dep.as_str()) {
+                    let dep_name = dependency_name(name.get(), dep);
+                    let inject = dev_dependencies == DevDependencies::Inject && current_versions.contains_key(&dep_name);
+                    if !dep.contains_key("version") && !inject {
+                        continue;
+                    }
+                    if let Some(version) = versions.get(&dep_name).or(current_versions.get(&dep_name)) {
+                        set_dependency_version(dep, version, requirement);
+                    }
+                    if let Some(registry) = registry_for(&dep_name) {
+                        set_dependency_key(dep, "registry", registry);
+                    }
+                    dev_edges.push((member.name.clone(), dep_name));
+                } else if inherits_workspace(dep) {
+                    if let Some(dep_name) = workspace_deps.get(name.get().trim()) {
+                        dev_edges.push((member.name.clone(), dep_name.clone()));
+                    }
+                }
+            }
+        }
+
+        if !skipped.contains(&member.name) {
+            edit(member_dir.join("Cargo.toml"), member_source, member_toml.to_string());
+        }
+
+        graph.insert(member.name, local_deps);
+    }
+
+    if let Some((_, version)) = inherited_version {
+        set_string(&mut root_toml["workspace"]["package"]["version"], &version.to_string());
+    }
+    if let Some(rust_version) = rust_version.filter(|_| inherited_rust_version) {
+        set_string(&mut root_toml["workspace"]["package"]["rust-version"], rust_version);
+    }
+    edit(dir.join("Cargo.toml"), root_source, root_toml.to_string());
+
+    // a dev-dependency that depends on the member itself can't go first,
+    // and mustn't upset the order of the regular dependencies
+    for (member, dep) in dev_edges {
+        if !with_dependencies(&graph, &HashSet::from([dep.clone()])).contains(&member) {
+            graph.entry(member).or_default().insert(dep);
+        }
+    }
+
+    // now we have a graph of dependencies, we can figure out which
+    // dependencies to publish first, in the next stage
+    Ok((graph, edits))
+}
+
+/// Picks the bump called for by the conventional commits since the `since`
+/// tag, only counting commits that touch `path` if one is given.
+/// Returns `None` if nothing since then warrants a release.
+pub fn conventional_bump(
+    dir: &Path,
+    since: Option<&str>,
+    version: &Version,
+    path: Option<&Path>,
+) -> Result<Option<Bump>, ArmoryError> {
+    let commits = git::commits_since(dir, since, path.as_slice())?;
+    let commits = commits
+        .iter()
+        .flat_map(|commit| conventional::ConventionalCommit::parse_all(&commit.message))
+        .collect::<Vec<_>>();
+
+    Ok(conventional::suggest_bump(version, &commits))
+}
+
+/// Why a member counts as changed since its last release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// It has no release tag yet.
+    Unreleased,
+    /// These files under it changed.
+    Files(Vec<String>),
+    /// This local dependency of it changed, directly or not.
+    Dependency(String),
+}
+
+/// The members with changes since their last release tag, as given by
+/// `since`, plus every member that depends on one of them, directly or
+/// not. Members without a tag count as changed.
+pub fn changed_members(dir: &Path, since: impl Fn(&str) -> Option<String>) -> Result<HashSet<String>, ArmoryError> {
+    Ok(member_changes(dir, since)?.into_keys().collect())
+}
+
+/// The members [`changed_members`] picks, with why each one changed.
+pub fn member_changes(dir: &Path, since: impl Fn(&str) -> Option<String>) -> Result<BTreeMap<String, Change>, ArmoryError> {
+    let mut changed = BTreeMap::new();
+    for member in workspace_members(dir)? {
+        let change = match since(&member.name) {
+            Some(since) => {
+                let files = git::changed_files(dir, &since, Path::new(&member.path))?;
+                (!files.is_empty()).then_some(Change::Files(files))
+            }
+            None => Some(Change::Unreleased),
+        };
+        if let Some(change) = change {
+            changed.insert(member.name, change);
+        }
+    }
+
+    // keep adding dependents of changed crates until nothing new turns up.
+    // publish-after only orders crates, it doesn't make one's changes
+    // another's, so it's left out
+    let graph = dependency_graph(dir)?;
+    loop {
+        let dependents: BTreeMap<String, Change> = graph
+            .iter()
+            .filter(|(name, _)| !changed.contains_key(*name))
+            .filter_map(|(name, deps)| {
+                let dep = deps.iter().filter(|dep| changed.contains_key(*dep)).min()?;
+                Some((name.clone(), Change::Dependency(dep.clone())))
+            })
+            .collect();
+        if dependents.is_empty() {
+            break;
+        }
+        changed.extend(dependents);
+    }
+
+    Ok(changed)
+}
+
+/// Saves a snapshot of the workspace before a release of `versions` from
+/// HEAD, with what the already applied `edits` replaced, so it can be
+/// rolled back.
+pub fn snapshot_release(
+    dir: &Path,
+    armory_toml: &ArmoryTOML,
+    versions: &HashMap<String, Version>,
+    edits: &[ManifestEdit],
+) -> Result<(), ArmoryError> {
+    let snapshot = ReleaseSnapshot {
+        head: git::head_sha(dir)?,
+        crates: versions.iter().map(|(name, version)| (name.clone(), version.clone())).collect(),
+        files: edits
+            .iter()
+            .map(|edit| {
+                let path = edit.path.strip_prefix(dir).unwrap_or(&edit.path).to_path_buf();
+                (path, edit.before.clone())
+            })
+            .collect(),
+        tags: armory_toml.release_tags(versions).into_keys().collect(),
+        commit_message: armory_toml.commit_message_for(versions),
+    };
+    snapshot.save(dir)?;
+    Ok(())
+}
+
+/// Undoes what an unfinished release left behind: its commit if it's
+/// still HEAD, its local tags and the files its bump rewrote. Published
+/// crates are left for the caller to yank.
+pub fn rollback(dir: &Path, snapshot: &ReleaseSnapshot) -> Result<(), ArmoryError> {
+    if git::head_sha(dir)? != snapshot.head {
+        match git::head_commit(dir) {
+            Some((parent, message)) if parent == snapshot.head && message == snapshot.commit_message.trim() => {
+                git::reset_keep(dir, &snapshot.head)?;
+                say!("ARMORY: dropped the release commit");
+            }
+            _ => say!("ARMORY: HEAD moved on since the release started, leaving the commits alone"),
+        }
+    }
+
+    for tag in &snapshot.tags {
+        if git::tag_exists(dir, tag) {
+            git::delete_tag(dir, tag)?;
+            say!("ARMORY: deleted the tag {}", tag);
+        }
+    }
+
+    for (path, before) in &snapshot.files {
+        let full_path = dir.join(path);
+        if before.is_empty() {
+            if full_path.exists() {
+                fs::remove_file(&full_path).map_err(|e| ArmoryError::io("remove", path, e))?;
+                say!("ARMORY: removed {}", path.display());
+            }
+        } else {
+            fs::write(&full_path, before).map_err(|e| ArmoryError::io("restore", path, e))?;
+            say!("ARMORY: restored {}", path.display());
+        }
+    }
+
+    PublishState::remove(dir)?;
+    ReleaseSnapshot::remove(dir)?;
+    Ok(())
+}
+
+/// Plans a new section in the changelogs of each crate in `versions`, for
+/// the commits since its last release that touched it or any of its local
+/// dependencies. Crates grouped with another in `[changelog.groups]` are
+/// covered by that crate's section, or get their own section in its
+/// changelogs if it isn't released. Meant to be called with `armory_toml`
+/// holding the new versions.
+pub fn plan_changelogs(
+    dir: &Path,
+    armory_toml: &ArmoryTOML,
+    versions: &HashMap<String, Version>,
+) -> Result<Vec<ManifestEdit>, ArmoryError> {
+    let versions = versions.iter().map(|(name, version)| (name.clone(), Some(version.clone()))).collect();
+    Ok(changelog_edits(dir, &preview_changelogs(dir, armory_toml, &versions, None)?))
+}
+
+/// A section planned for a crate's changelogs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangelogSection {
+    pub crate_name: String,
+    /// The changelogs it goes in, relative to the workspace root.
+    pub paths: Vec<PathBuf>,
+    pub text: String,
+}
+
+/// The sections [`plan_changelogs`] would write, ahead of a release:
+/// crates without a version yet get a section headed "Unreleased", and
+/// with a `since` tag the sections cover the commits since it rather than
+/// since each crate's last release.
+pub fn preview_changelogs(
+    dir: &Path,
+    armory_toml: &ArmoryTOML,
+    versions: &HashMap<String, Option<Version>>,
+    since: Option<&str>,
+) -> Result<Vec<ChangelogSection>, ArmoryError> {
+    let members = workspace_members(dir)?;
+    // what a crate's changelog covers is its code, so publish-after, which
+    // only orders crates, is left out
+    let graph = dependency_graph(dir)?;
+    let date = OffsetDateTime::now_utc().date().to_string();
+    let config = &armory_toml.changelog;
+
+    let mut names: Vec<&String> = versions.keys().collect();
+    names.sort();
+    let mut sections = Vec::new();
+    for name in names {
+        let owner = config.group_of(name);
+        if owner.is_some_and(|owner| versions.contains_key(owner)) {
+            continue;
+        }
+        let Some(member) = members.iter().find(|member| member.name == owner.unwrap_or(name)) else {
+            continue;
+        };
+
+        let mut crates = HashSet::from([name.clone()]);
+        crates.extend(config.groups.get(name).into_iter().flatten().cloned());
+        let scope = with_dependencies(&graph, &crates);
+        let paths: Vec<&Path> = members.iter()
+            .filter(|member| scope.contains(&member.name))
+            .map(|member| Path::new(&member.path))
+            .collect();
+
+        let since = match since {
+            Some(since) => Some(since.to_string()),
+            None => armory_toml.last_release_tag(dir, armory_toml.independent.then_some(name.as_str())),
+        };
+        let commits = git::commits_since(dir, since.as_deref(), &paths)?;
+        let header = match &versions[name] {
+            Some(version) => config.header(&armory_toml.tag_name(Some(name), version), name, version, &date),
+            None => changelog::UNRELEASED.to_string(),
+        };
+        sections.push(ChangelogSection {
+            crate_name: name.clone(),
+            paths: armory_toml.changelogs_for(member),
+            text: changelog::section(&header, &commits, config),
+        });
+    }
+    Ok(sections)
+}
+
+/// The edits adding `sections` to their changelogs.
+pub fn changelog_edits(dir: &Path, sections: &[ChangelogSection]) -> Vec<ManifestEdit> {
+    // a group's crates released on their own share its changelogs, and are
+    // added together so one's unreleased section doesn't replace another's
+    let mut by_path: Vec<(&PathBuf, Vec<&str>)> = Vec::new();
+    for section in sections {
+        for path in &section.paths {
+            match by_path.iter_mut().find(|(other, _)| *other == path) {
+                Some((_, texts)) => texts.insert(0, &section.text),
+                None => by_path.push((path, vec![&section.text])),
+            }
+        }
+    }
+    by_path
+        .into_iter()
+        .map(|(path, texts)| {
+            let path = dir.join(path);
+            let existed = path.exists();
+            let before = fs::read_to_string(&path).unwrap_or_default();
+            let after = changelog::prepend(&before, &texts.join("\n"));
+            ManifestEdit { path, existed, before, after }
+        })
+        .collect()
+}
+
+/// The notes for the release tagged `tag`, of `crate_name` if given: the
+/// changes since the tag before it that touched the crate or its local
+/// dependencies, or anything for a shared release. `tag` doesn't need to
+/// exist yet, in which case the notes cover everything up to HEAD.
+pub fn release_notes(dir: &Path, armory_toml: &ArmoryTOML, tag: &str, crate_name: Option<&str>) -> Result<String, ArmoryError> {
+    let pattern = armory_toml.tag_pattern(crate_name);
+    let (since, until) = if git::tag_exists(dir, tag) {
+        (git::previous_tag(dir, tag, &pattern), tag)
+    } else {
+        (git::last_tag_matching(dir, &pattern), "HEAD")
+    };
+
+    let members = workspace_members(dir)?;
+    let scope = match crate_name {
+        Some(name) => with_dependencies(&dependency_graph(dir)?, &HashSet::from([name.to_string()])),
+        None => HashSet::new(),
+    };
+    let paths: Vec<&Path> = members.iter()
+        .filter(|member| scope.contains(&member.name))
+        .map(|member| Path::new(&member.path))
+        .collect();
+
+    let commits = git::commits_between(dir, since.as_deref(), until, &paths)?;
+    Ok(changelog::notes(&commits, &armory_toml.changelog))
+}
+
+/// Expands a build metadata template, where `{sha}` and `{short-sha}` stand
+/// for the commit checked out in `dir`. An empty template clears it.
+pub fn build_metadata(dir: &Path, template: &str) -> Result<BuildMetadata, ArmoryError> {
+    let mut metadata = template.to_string();
+    if metadata.contains("{sha}") || metadata.contains("{short-sha}") {
+        let sha = git::head_sha(dir)?;
+        metadata = metadata
+            .replace("{sha}", &sha)
+            .replace("{short-sha}", &sha[..sha.len().min(7)]);
+    }
+    BuildMetadata::new(&metadata)
+        .map_err(|e| format!("Invalid build metadata \"{}\": {}", metadata, e).into())
+}
+
+/// Publishes every member of the workspace, or those the filters of
+/// `options` pick, at the same version.
+pub fn publish_workspace(dir: &Path, version: &Version, options: &PublishOptions) -> Result<PublishState, ArmoryError> {
+    let armory_toml = ArmoryTOML::new(version.clone());
+    let versions = armory_toml.members(dir)?
+        .into_iter()
+        .map(|member| (member.name, version.clone()))
+        .collect();
+
+    publish_workspace_versions(dir, &armory_toml, &versions, options)
+}
+
+/// Publishes the members listed in `versions`, each at its own version,
+/// leaving out those the filters of `options` don't pick. Members that are
+/// not published keep their current version, but dependents still pick up
+/// the new versions of published crates.
+pub fn publish_workspace_versions(
+    dir: &Path,
+    armory_toml: &ArmoryTOML,
+    versions: &HashMap<String, Version>,
+    options: &PublishOptions,
+) -> Result<PublishState, ArmoryError> {
+    let armory_toml = &options.configure(armory_toml);
+    let selected = select_members(dir, armory_toml, &options.only, &options.exclude)?;
+    let versions: HashMap<String, Version> = versions
+        .iter()
+        .filter(|(name, _)| selected.contains_key(*name))
+        .map(|(name, version)| (name.clone(), version.clone()))
+        .collect();
+    bump_workspace(dir, armory_toml, &versions)?;
+    publish_members(dir, armory_toml, &versions.keys().cloned().collect(), options)
+}
+
+/// Rewrites the manifests of the workspace for the new `versions` without
+/// publishing anything. Excluded members are left untouched.
+pub fn bump_workspace(dir: &Path, armory_toml: &ArmoryTOML, versions: &HashMap<String, Version>) -> Result<(), ArmoryError> {
+    apply_edits(&plan_bump(dir, armory_toml, versions)?)
+}
+
+/// The manifest edits [`bump_workspace`] would make, without writing them.
+/// Fails if `versions` can't all be stamped, as when members inheriting the
+/// workspace version are given different ones.
+pub fn plan_bump(dir: &Path, armory_toml: &ArmoryTOML, versions: &HashMap<String, Version>) -> Result<Vec<ManifestEdit>, ArmoryError> {
+    update_member_deps(dir, Some(armory_toml), versions).map(|(_, edits)| edits)
+}
+
+/// Checks that the workspace still builds with the `rust_version`
+/// toolchain, installed through rustup.
+pub fn verify_rust_version(dir: &Path, rust_version: &str) -> Result<(), ArmoryError> {
+    let status = std::process::Command::new("cargo")
+        .current_dir(dir)
+        .arg(format!("+{}", rust_version))
+        .args(["check", "--workspace"])
+        .args(output::cargo_flags())
+        .status()
+        .map_err(|e| format!("Failed to run cargo +{}: {}", rust_version, e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("The workspace doesn't build with Rust {}", rust_version).into())
+    }
+}
+
+/// Commits the workspace's manifests, changelogs and armory.toml, which
+/// hold a release of `crates`. Returns whether there was anything to commit.
+pub fn commit_release(dir: &Path, armory_toml: &ArmoryTOML, crates: &HashMap<String, Version>) -> Result<bool, ArmoryError> {
+    let mut paths = vec!["Cargo.toml".to_string(), "armory.toml".to_string()];
+    for member in workspace_members(dir)? {
+        paths.push(format!("{}/Cargo.toml", member.path));
+        for changelog in armory_toml.changelogs_for(&member) {
+            paths.push(changelog.to_string_lossy().into_owned());
+        }
+    }
+    paths.retain(|path| dir.join(path).exists());
+    git::commit(dir, &paths, &armory_toml.commit_message_for(crates), armory_toml.sign_git)
+}
+
+/// Pushes the commit released from to its branch, along with `tags`.
+/// On a detached HEAD without `push-branch`, only the tags are pushed.
+pub fn push_release(dir: &Path, armory_toml: &ArmoryTOML, tags: &[String]) -> Result<(), ArmoryError> {
+    let remote = armory_toml.push_remote.as_deref().unwrap_or("origin");
+    let mut refspecs: Vec<String> = armory_toml.push_branch.clone()
+        .or_else(|| git::current_branch(dir))
+        .map(|branch| format!("HEAD:refs/heads/{}", branch))
+        .into_iter()
+        .collect();
+    refspecs.extend(tags.iter().map(|tag| format!("refs/tags/{}", tag)));
+
+    say!("ARMORY: pushing {} to {}", refspecs.join(" "), remote);
+    git::push(dir, remote, &refspecs)
+}
+
+/// Runs `cargo doc --no-deps` on `crates`, treating rustdoc warnings such
+/// as broken intra-doc links as errors, the way docs.rs would show them.
+pub fn check_docs(dir: &Path, crates: &HashSet<String>) -> Result<(), ArmoryError> {
+    let mut crates: Vec<_> = crates.iter().collect();
+    crates.sort();
+
+    let mut cargo = std::process::Command::new("cargo");
+    cargo.current_dir(dir).args(["doc", "--no-deps"]).args(output::cargo_flags());
+    for name in crates {
+        cargo.args(["--package", name]);
+    }
+    let rustdocflags = std::env::var("RUSTDOCFLAGS").unwrap_or_default();
+    cargo.env("RUSTDOCFLAGS", format!("{} -D warnings", rustdocflags).trim_start());
+
+    let status = cargo.status().map_err(|e| format!("Failed to run cargo doc: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err("The docs don't build cleanly".to_string().into())
+    }
+}
+
+/// Checks that a release can be used from its registry: creates a new
+/// project outside the workspace, `cargo add`s each of the released crates
+/// nothing else in the release depends on at its new version, and builds
+/// it.
+pub fn smoke_test(dir: &Path, armory_toml: &ArmoryTOML, versions: &HashMap<String, Version>) -> Result<(), ArmoryError> {
+    let graph = publish_graph(dir, armory_toml)?;
+    let released: Vec<&String> = graph.keys().filter(|name| versions.contains_key(*name)).collect();
+    let mut top_level: Vec<&String> = released.iter()
+        .filter(|name| !released.iter().any(|other| graph[*other].contains(**name)))
+        .copied()
+        .collect();
+    top_level.sort();
+    if top_level.is_empty() {
+        return Ok(());
+    }
+
+    let project = std::env::temp_dir().join(format!("armory-smoke-test-{}", std::process::id()));
+    fs::create_dir_all(&project).map_err(|e| ArmoryError::io("create", &project, e))?;
+    let cargo = |args: &[&str]| {
+        let output = std::process::Command::new("cargo")
+            .current_dir(&project)
+            .args(args)
+            .output()
+            .map_err(|e| format!("Failed to run cargo: {}", e))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!("cargo {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()))
+        }
+    };
+
+    let result = (|| {
+        cargo(&["init", "--lib", "--vcs", "none", "--name", "armory-smoke-test"])?;
+        for name in top_level {
+            let version = &versions[name];
+            let registry = armory_toml.registry_for(name);
+            if !registry::wait_for_version(name, version, registry, INDEX_TIMEOUT, || false) {
+                return Err(format!("{} {} isn't in the index after {}s", name, version, INDEX_TIMEOUT.as_secs()));
+            }
+            say!("ARMORY: adding {} {} to the smoke test", name, version);
+            let dependency = format!("{}@={}", name, version);
+            match registry {
+                Some(registry) => cargo(&["add", &dependency, "--registry", registry])?,
+                None => cargo(&["add", &dependency])?,
+            }
+        }
+        say!("ARMORY: building the smoke test");
+        cargo(&["build"])
+    })();
+
+    let _ = fs::remove_dir_all(&project);
+    Ok(result?)
+}
+
+/// Yanks `version` of `crates` from their registries and mirrors,
+/// dependents first.
+/// Crates that don't have `version` on the registry, or have it yanked
+/// already, are skipped. With `undo`, un-yanks them instead.
+pub fn yank_members(
+    dir: &Path,
+    armory_toml: &ArmoryTOML,
+    crates: &HashSet<String>,
+    version: &Version,
+    undo: bool,
+    token: Option<&str>,
+) -> Result<(), ArmoryError> {
+    let cfg = Config::default().map_err(|e| ArmoryError::Cargo(format!("{:#}", e)))?;
+    cfg.load_credentials().map_err(|e| ArmoryError::Cargo(format!("{:#}", e)))?;
+
+    let mut order = publish_order(&with_publish_after(dependency_graph(dir)?, armory_toml));
+    order.reverse();
+    for name in order.iter().filter(|name| crates.contains(*name)) {
+        for registry in [armory_toml.registry_for(name)].into_iter().chain(armory_toml.mirrors_for(name)) {
+            let registry_name = registry.unwrap_or(CRATES_IO_REGISTRY);
+
+            // a lookup failure shouldn't stop the yank itself, cargo will
+            // complain if the version doesn't exist
+            if let Ok(published) = registry::published_versions(name, registry) {
+                match published.iter().find(|published| published.version == *version) {
+                    None => {
+                        say!("ARMORY: skipping {}, which has no version {} on {}", name, version, registry_name);
+                        continue;
+                    }
+                    Some(published) if published.yanked != undo => {
+                        say!("ARMORY: skipping {}, whose version {} is already {} on {}",
+                            name, version, if undo { "available" } else { "yanked" }, registry_name);
+                        continue;
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            let token = resolve_token(armory_toml, token, registry)?;
+            cargo::ops::yank(
+                &cfg,
+                Some(name.clone()),
+                Some(version.to_string()),
+                token.map(Secret::from),
+                None,
+                undo,
+                registry.map(|registry| registry.to_string()),
+            )
+            .map_err(|e| ArmoryError::at(Stage::Yank, name, format!("{} from {}: {:#}", version, registry_name, e)))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the crates.io token may publish every one of `crates` that
+/// goes to crates.io, so a release doesn't stop halfway for want of
+/// ownership. Returns the crates it may not publish, with the reason.
+pub fn check_ownership(
+    armory_toml: &ArmoryTOML,
+    crates: &HashSet<String>,
+    token: Option<&str>,
+) -> Result<BTreeMap<String, String>, ArmoryError> {
+    let mut crates: Vec<&String> = crates.iter()
+        .filter(|name| armory_toml.registry_for(name).is_none() || armory_toml.mirrors_for(name).contains(&None))
+        .collect();
+    if crates.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    crates.sort();
+
+    let token = match resolve_token(armory_toml, token, None)? {
+        Some(token) => token,
+        None => cargo_token(None)?.ok_or_else(|| "There's no crates.io token to check ownership with".to_string())?,
+    };
+    let login = owners::token_owner(&token)?;
+
+    let mut rejected = BTreeMap::new();
+    for name in crates {
+        // anyone can publish a crate that doesn't exist yet
+        if let Some(owners) = owners::owners(name)? {
+            if let Some(reason) = owners::rejection(&login, &owners) {
+                rejected.insert(name.clone(), reason);
+            }
+        }
+    }
+    Ok(rejected)
+}
+
+/// The owners of crates keyed by crate and registry, or `None` where a
+/// crate was never published.
+pub type CrateOwners = BTreeMap<String, BTreeMap<String, Option<Vec<owners::Owner>>>>;
+
+/// The owners of each of `crates` on every registry it goes to, mirrors
+/// included.
+pub fn crate_owners(armory_toml: &ArmoryTOML, crates: &HashSet<String>, token: Option<&str>) -> Result<CrateOwners, ArmoryError> {
+    // the API of each registry and the token to ask it with, looked up once
+    let mut apis = HashMap::new();
+    let mut found = BTreeMap::new();
+    for name in crates {
+        let mut by_registry = BTreeMap::new();
+        for registry in [armory_toml.registry_for(name)].into_iter().chain(armory_toml.mirrors_for(name)) {
+            let api = apis.entry(registry).or_insert_with(|| -> Result<_, ArmoryError> {
+                // crates.io tells anyone, other registries may want a token
+                let token = match registry {
+                    Some(_) => resolve_token(armory_toml, token, registry)?.or(cargo_token(registry)?),
+                    None => None,
+                };
+                Ok((format!("{}/api/v1", registry::api_url(registry)?), token))
+            });
+            let (api, token) = api.as_ref().map_err(|e| e.to_string())?;
+            let owners = owners::registry_owners(api, name, token.as_deref())?;
+            by_registry.insert(registry.unwrap_or(CRATES_IO_REGISTRY).to_string(), owners);
+        }
+        found.insert(name.clone(), by_registry);
+    }
+    Ok(found)
+}
+
+/// Invites `add` to own each of `crates`, and removes `remove` from their
+/// owners, on every registry it goes to, mirrors included. Owners are
+/// users' logins or teams like `github:org:team`. Crates never published
+/// to a registry are skipped there, as there's nothing to own yet.
+pub fn modify_owners(
+    armory_toml: &ArmoryTOML,
+    crates: &HashSet<String>,
+    add: &[String],
+    remove: &[String],
+    token: Option<&str>,
+) -> Result<(), ArmoryError> {
+    let cfg = Config::default().map_err(|e| ArmoryError::Cargo(format!("{:#}", e)))?;
+    cfg.load_credentials().map_err(|e| ArmoryError::Cargo(format!("{:#}", e)))?;
+
+    let mut names: Vec<&String> = crates.iter().collect();
+    names.sort();
+    for name in names {
+        for registry in [armory_toml.registry_for(name)].into_iter().chain(armory_toml.mirrors_for(name)) {
+            let registry_name = registry.unwrap_or(CRATES_IO_REGISTRY);
+            if registry::published_versions(name, registry).is_ok_and(|published| published.is_empty()) {
+                say!("ARMORY: skipping {}, which was never published to {}", name, registry_name);
+                continue;
+            }
+            let options = cargo::ops::OwnersOptions {
+                krate: Some(name.clone()),
+                token: resolve_token(armory_toml, token, registry)?.map(Secret::from),
+                index: None,
+                to_add: (!add.is_empty()).then(|| add.to_vec()),
+                to_remove: (!remove.is_empty()).then(|| remove.to_vec()),
+                list: false,
+                registry: registry.map(|registry| registry.to_string()),
+            };
+            cargo::ops::modify_owners(&cfg, &options)
+                .map_err(|e| format!("Failed to change the owners of {} on {}: {:#}", name, registry_name, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// The registries some of `crates` go to, mirrors included, that there's
+/// no token to publish to: not `token`, nor one from armory.toml's token
+/// sources, cargo's credentials or a credential provider.
+pub fn missing_credentials(
+    armory_toml: &ArmoryTOML,
+    crates: &HashSet<String>,
+    token: Option<&str>,
+) -> Result<Vec<String>, ArmoryError> {
+    let mut missing = Vec::new();
+    for registry in armory_toml.registries_for(crates) {
+        let found = resolve_token(armory_toml, token, registry)?.is_some()
+            || credential::cargo_provider(registry)?.is_some()
+            || cargo_token(registry)?.is_some();
+        if !found {
+            missing.push(registry.unwrap_or(CRATES_IO_REGISTRY).to_string());
+        }
+    }
+    Ok(missing)
+}
+
+/// Stores `token` as the one to publish to `registry` with, crates.io if
+/// `None`, in cargo's credential store: the credential provider cargo's
+/// config sets for the registry, or cargo's credentials file.
+pub fn login(registry: Option<&str>, token: &str) -> Result<(), ArmoryError> {
+    // only the installed cargo can hand tokens to providers
+    if credential::cargo_provider(registry)?.is_some() {
+        return credential::cargo_login(registry, token);
+    }
+    let cfg = Config::default().map_err(|e| ArmoryError::Cargo(format!("{:#}", e)))?;
+    cfg.load_credentials().map_err(|e| ArmoryError::Cargo(format!("{:#}", e)))?;
+    cargo::ops::registry_login(&cfg, Some(Secret::from(token)), registry, false, false, None)
+        .map_err(|e| format!("Failed to log in to {}: {:#}", registry.unwrap_or(CRATES_IO_REGISTRY), e).into())
+}
+
+/// The token cargo would publish to `registry` with, crates.io if `None`,
+/// from `cargo login` or variables like `CARGO_REGISTRY_TOKEN`.
+fn cargo_token(registry: Option<&str>) -> Result<Option<String>, ArmoryError> {
+    let cfg = Config::default().map_err(|e| ArmoryError::Cargo(format!("{:#}", e)))?;
+    cfg.load_credentials().map_err(|e| ArmoryError::Cargo(format!("{:#}", e)))?;
+    let key = match registry {
+        Some(registry) => format!("registries.{}.token", registry),
+        None => "registry.token".to_string(),
+    };
+    Ok(cfg.get_string(&key)
+        .map_err(|e| e.to_string())?
+        .map(|token| token.val))
+}
+
+/// The token to use for `registry`: `token` if given, otherwise the one
+/// from armory.toml's token sources.
+fn resolve_token(armory_toml: &ArmoryTOML, token: Option<&str>, registry: Option<&str>) -> Result<Option<String>, ArmoryError> {
+    match token {
+        Some(token) => Ok(Some(token.to_string())),
+        None => armory_toml.token_for(registry),
+    }
+}
+
+/// Packages `crates` into `.crate` files under `target/package`, local
+/// dependencies first, without uploading anything. Returns the packaged
+/// files in the order they were made, which is the order to upload them
+/// in.
+///
+/// Only `verify` and `targets` of `options` apply. Verifying a crate
+/// needs its local dependencies on the registry already, so it's off
+/// unless asked for.
+pub fn package_members(
+    dir: &Path,
+    armory_toml: &ArmoryTOML,
+    crates: &HashSet<String>,
+    options: &PublishOptions,
+) -> Result<Vec<PathBuf>, ArmoryError> {
+    let armory_toml = &options.configure(armory_toml);
+    let members = workspace_members(dir)?;
+
+    let mut packaged = Vec::new();
+    for name in publish_order(&publish_graph(dir, armory_toml)?) {
+        if !crates.contains(&name) {
+            continue;
+        }
+        let version = members.iter()
+            .find(|member| member.name == name)
+            .and_then(|member| member.version.as_ref())
+            .ok_or_else(|| format!("{} has no version to package", name))?;
+
+        let targets = if options.targets.is_empty() {
+            armory_toml.targets_for(&name).to_vec()
+        } else {
+            options.targets.clone()
+        };
+        let features = options.features_for(armory_toml, &name);
+        let upload = Upload {
+            dir,
+            package: &name,
+            registry: armory_toml.registry_for(&name),
+            token: None,
+            // package can't verify crates whose dependencies aren't
+            // published, so a crate's own setting can only turn it off
+            verify: options.verify && armory_toml.verify_for(&name, true),
+            dry_run: false,
+            targets: &targets,
+            features: &features,
+            timeout: None,
+            local_deps: &[],
+            abort: None,
+        };
+
+        armory_toml.run_hook(dir, Hook::PrePublish, &HashMap::from([(name.clone(), version.clone())]), Some(&name))?;
+        say!("ARMORY: packaging {} {}", name, version);
+        armory_toml.backend.package(&upload)?;
+        packaged.push(package_path(dir, &name, version));
+    }
+    Ok(packaged)
+}
+
+/// Each crate [`verify_members`] packaged, with how it went.
+pub type Verification = Vec<(String, Result<(), ArmoryError>)>;
+
+/// Packages and verifies `crates` in publish order without uploading
+/// anything, to find the ones that would fail to publish before release
+/// day. Each crate is built against its local dependencies as they are in
+/// the workspace, standing in for the versions that aren't published yet.
+/// Returns each crate with how packaging it went, in the order they were
+/// packaged. Crates depending on one that failed aren't packaged.
+///
+/// Only `targets` of `options` applies, and hooks aren't run.
+pub fn verify_members(
+    dir: &Path,
+    armory_toml: &ArmoryTOML,
+    crates: &HashSet<String>,
+    options: &PublishOptions,
+) -> Result<Verification, ArmoryError> {
+    let members = workspace_members(dir)?;
+    let graph = publish_graph(dir, armory_toml)?;
+
+    let mut results: Verification = Vec::new();
+    for name in publish_order(&graph) {
+        if !crates.contains(&name) {
+            continue;
+        }
+        let failed_dep = graph[&name].iter().find(|dep| {
+            results.iter().any(|(failed, result)| failed == *dep && result.is_err())
+        });
+        if let Some(failed_dep) = failed_dep {
+            say!("ARMORY: skipping {}, which depends on {}", name, failed_dep);
+            results.push((name.clone(), Err(format!("depends on {}, which failed to package", failed_dep).into())));
+            continue;
+        }
+
+        let local_deps: Vec<LocalDep> = with_dependencies(&graph, &HashSet::from([name.clone()]))
+            .into_iter()
+            .filter(|dep| *dep != name)
+            .filter_map(|dep| {
+                let member = members.iter().find(|member| member.name == dep)?;
+                let registry = armory_toml.registry_for(&dep).map(str::to_string);
+                Some(LocalDep { path: dir.join(&member.path), name: dep, registry })
+            })
+            .collect();
+        let targets = if options.targets.is_empty() {
+            armory_toml.targets_for(&name).to_vec()
+        } else {
+            options.targets.clone()
+        };
+        let features = armory_toml.features_for(&name);
+        let upload = Upload {
+            dir,
+            package: &name,
+            registry: armory_toml.registry_for(&name),
+            token: None,
+            verify: armory_toml.verify_for(&name, true),
+            dry_run: false,
+            targets: &targets,
+            features: &features,
+            timeout: None,
+            local_deps: &local_deps,
+            abort: None,
+        };
+
+        say!("ARMORY: verifying {}", name);
+        let result = armory_toml.backend.package(&upload);
+        results.push((name, result));
+    }
+    Ok(results)
+}
+
+/// Where cargo leaves the package of `crate_name` at `version`, whether
+/// it was packaged on its own or on the way to being published.
+pub fn package_path(dir: &Path, crate_name: &str, version: &Version) -> PathBuf {
+    target_dir(dir).join("package").join(format!("{}-{}.crate", crate_name, version))
+}
+
+/// Writes the checksums of `packages` next to them, and signs each one if
+/// armory.toml asks to. Returns the files written.
+pub fn seal_packages(dir: &Path, armory_toml: &ArmoryTOML, packages: &[PathBuf]) -> Result<Vec<PathBuf>, ArmoryError> {
+    let mut written = vec![artifacts::write_checksums(&target_dir(dir).join("package"), packages)?];
+    if let Some(signing) = &armory_toml.signing {
+        for package in packages {
+            written.push(signing.sign(package)?);
+        }
+    }
+    Ok(written)
+}
+
+/// The target directory cargo builds `dir` into.
+fn target_dir(dir: &Path) -> PathBuf {
+    Config::default()
+        .ok()
+        .and_then(|cfg| cfg.target_dir().ok().flatten())
+        .map(|target_dir| target_dir.into_path_unlocked())
+        .unwrap_or_else(|| dir.join("target"))
+}
+
+/// How [`publish_members`] runs, taking precedence over armory.toml, and
+/// which of the crates it's given it publishes, set through its builder
+/// methods:
+///
+/// ```no_run
+/// # use armory_lib::{PublishOptions, RetryPolicy};
+/// let options = PublishOptions::new()
+///     .dry_run(true)
+///     .registry("internal")
+///     .retry(RetryPolicy { retries: 2, ..Default::default() })
+///     .exclude(["examples"]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PublishOptions {
+    dry_run: bool,
+    token: Option<String>,
+    verify: bool,
+    registry: Option<String>,
+    features: Option<Features>,
+    targets: Vec<String>,
+    retry: RetryPolicy,
+    only: Vec<String>,
+    exclude: Vec<String>,
+    keep_going: bool,
+    timeout: Option<Duration>,
+    deadline: Option<Duration>,
+    step: Option<fn(&str, &str) -> bool>,
+    progress: Option<fn(&str, Progress)>,
+    interrupted: Option<fn() -> bool>,
+    abort: Option<fn() -> bool>,
+}
+
+impl PublishOptions {
+    /// Options that publish every crate as armory.toml says.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Package each crate without uploading it.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// The token to publish every crate with, taking precedence over the
+    /// token sources in armory.toml.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Build each crate from its packaged sources before uploading it,
+    /// even if armory.toml doesn't ask to.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// The registry to publish every crate to, taking precedence over the
+    /// ones in armory.toml. Mirrors are still published to.
+    pub fn registry(mut self, registry: impl Into<String>) -> Self {
+        self.registry = Some(registry.into());
+        self
+    }
+
+    /// The features to package every crate with, taking precedence over
+    /// the ones in armory.toml.
+    pub fn features(mut self, features: Features) -> Self {
+        self.features = Some(features);
+        self
+    }
+
+    /// Targets to verify every crate for, taking precedence over the ones
+    /// in armory.toml.
+    pub fn targets(mut self, targets: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.targets = targets.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// How failed uploads are retried.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Only publish these crates and the local dependencies they need, if
+    /// any are given.
+    pub fn only(mut self, crates: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.only = crates.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Never publish these crates.
+    pub fn exclude(mut self, crates: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.exclude = crates.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Carry on publishing crates that don't depend on a failed one,
+    /// instead of stopping at the first failure.
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// How long a single upload may take, taking precedence over
+    /// armory.toml.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// How long the whole publish may take, taking precedence over
+    /// armory.toml.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Called after each crate is published with its name and the crate
+    /// to publish next. Returning `false` stops the publish there, leaving
+    /// the rest for [`resume`].
+    pub fn step(mut self, step: fn(&str, &str) -> bool) -> Self {
+        self.step = Some(step);
+        self
+    }
+
+    /// Called with a crate's name whenever it moves on to another
+    /// [`Progress`] step, e.g. to show the publish's progress.
+    pub fn progress(mut self, progress: fn(&str, Progress)) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Called before each crate is published. Returning `true` stops the
+    /// publish there, e.g. on Ctrl-C, leaving the rest for [`resume`].
+    pub fn interrupted(mut self, interrupted: fn() -> bool) -> Self {
+        self.interrupted = Some(interrupted);
+        self
+    }
+
+    /// Called while a crate uploads. Returning `true` gives up on the
+    /// upload, e.g. on a second Ctrl-C, and stops the publish with the
+    /// crate among the failed ones, since there's no telling whether it
+    /// made it. [`resume`] checks the registry before publishing it again.
+    pub fn abort(mut self, abort: fn() -> bool) -> Self {
+        self.abort = Some(abort);
+        self
+    }
+
+    /// `armory_toml` with the registry of these options in place of its
+    /// own, which the bump stamps on dependencies too.
+    fn configure<'a>(&self, armory_toml: &'a ArmoryTOML) -> Cow<'a, ArmoryTOML> {
+        let Some(registry) = &self.registry else {
+            return Cow::Borrowed(armory_toml);
+        };
+        let mut armory_toml = armory_toml.clone();
+        armory_toml.registry = Some(registry.clone());
+        armory_toml.crate_registries.clear();
+        for config in armory_toml.crates.values_mut() {
+            config.registry = None;
+        }
+        Cow::Owned(armory_toml)
+    }
+
+    /// The features to package `crate_name` with.
+    fn features_for(&self, armory_toml: &ArmoryTOML, crate_name: &str) -> Features {
+        self.features.clone().unwrap_or_else(|| armory_toml.features_for(crate_name))
+    }
+}
+
+/// How [`publish_members`] retries a crate whose upload failed, which it
+/// doesn't on a dry run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times to try again after the first attempt.
+    pub retries: usize,
+    /// The wait before the first retry. The waits after it grow along the
+    /// Fibonacci sequence.
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { retries: 5, delay: Duration::from_secs(4) }
+    }
+}
+
+impl RetryPolicy {
+    /// The waits between attempts at publishing a crate.
+    fn delays(&self) -> std::iter::Take<delay::Fibonacci> {
+        delay::Fibonacci::from_millis(self.delay.as_millis() as u64).take(self.retries)
+    }
+}
+
+/// Where a crate is at in [`publish_members`]. Crates start out pending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// Being packaged by a dry run.
+    Packaging,
+    /// Being packaged, verified and uploaded.
+    Uploading,
+    /// Published, waiting for the index to have it before publishing the
+    /// crates that depend on it.
+    WaitingForIndex,
+    /// Turned away by the registry's rate limit, trying again after the
+    /// wait.
+    RateLimited(Duration),
+    /// Failed an attempt, trying again after the wait.
+    Retrying(Duration),
+    /// Published, or on the registry already.
+    Done,
+    /// Failed to publish, or held back by a dependency that did.
+    Failed,
+}
+
+/// Publishes `crates` at the versions already in their manifests, local
+/// dependencies first, leaving out those the filters of `options` don't
+/// pick. Manifests are left untouched and excluded members are never
+/// published.
+///
+/// Progress is saved to `.armory/state.json` as crates are published, so
+/// if this fails halfway the rest can be published with [`resume`].
+/// Returns what was published.
+pub fn publish_members(
+    dir: &Path,
+    armory_toml: &ArmoryTOML,
+    crates: &HashSet<String>,
+    options: &PublishOptions,
+) -> Result<PublishState, ArmoryError> {
+    let selected = select_members(dir, armory_toml, &options.only, &options.exclude)?;
+    let mut state = PublishState {
+        crates: workspace_members(dir)?
+            .into_iter()
+            .filter(|member| crates.contains(&member.name) && selected.contains_key(&member.name))
+            .filter_map(|member| Some((member.name, member.version?)))
+            .collect(),
+        ..Default::default()
+    };
+    publish_state(dir, armory_toml, &mut state, options)?;
+    Ok(state)
+}
+
+/// Publishes the crates an interrupted [`publish_members`] didn't get to.
+/// Returns what was published, including before the interruption.
+pub fn resume(dir: &Path, armory_toml: &ArmoryTOML, options: &PublishOptions) -> Result<PublishState, ArmoryError> {
+    let mut state = PublishState::load(dir)?
+        .ok_or_else(|| "There's no unfinished publish to resume".to_string())?;
+
+    // publishing from manifests edited since would release something else
+    let members = workspace_members(dir)?;
+    for (name, version) in state.pending() {
+        let member = members.iter()
+            .find(|member| member.name == *name)
+            .ok_or_else(|| format!("{} is no longer a workspace member", name))?;
+        if member.version.as_ref() != Some(version) {
+            return Err(format!(
+                "{} was being published at {}, but its manifest is now at {}",
+                name,
+                version,
+                member.version.as_ref().map(|version| version.to_string()).unwrap_or_default()
+            ).into());
+        }
+    }
+
+    // give the crates that failed last time another go
+    state.failed.clear();
+    publish_state(dir, armory_toml, &mut state, options)?;
+    Ok(state)
+}
+
+fn publish_state(
+    dir: &Path,
+    armory_toml: &ArmoryTOML,
+    state: &mut PublishState,
+    options: &PublishOptions,
+) -> Result<(), ArmoryError> {
+    let armory_toml = &options.configure(armory_toml);
+    // the order `plan` shows, publish-after included
+    let graph = with_publish_after(dependency_graph(dir)?, armory_toml);
+    if let Some(cycle) = dependency_cycle(&graph) {
+        return Err(ArmoryError::Cycle(cycle));
+    }
+    let members = workspace_members(dir)?;
+
+    // crates that aren't being released count as already published,
+    // so dependents don't try to publish them first
+    let mut already_published: HashSet<String> = members.iter()
+        .filter(|member| !state.crates.contains_key(&member.name) || armory_toml.is_excluded(member))
+        .map(|member| member.name.clone())
+        .chain(state.published.iter().cloned())
+        .collect();
+
+    // and so do the ones whose manifest doesn't allow publishing them
+    for member in &members {
+        let registry = armory_toml.registry_for(&member.name);
+        if already_published.contains(&member.name) || member.can_publish_to(registry) {
+            continue;
+        }
+        match member.publish.as_deref() {
+            Some([]) => say!("ARMORY: skipping {}, which has publish = false", member.name),
+            _ => say!("ARMORY: skipping {}, which may not be published to {}",
+                member.name, registry.unwrap_or(CRATES_IO_REGISTRY)),
+        }
+        already_published.insert(member.name.clone());
+    }
+
+    armory_toml.check_registries_allowed(state.crates.keys().filter(|name| !already_published.contains(*name)))?;
+
+    if !options.dry_run {
+        state.save(dir)?;
+    }
+
+    let mut publisher = Publisher {
+        dir,
+        graph: &graph,
+        members: &members,
+        armory_toml,
+        options,
+        already_published,
+        state,
+        throttles: HashMap::new(),
+        started: Instant::now(),
+    };
+    let order = publish_order(&graph);
+    for (index, current_package) in order.iter().enumerate() {
+        let was_published = publisher.already_published.contains(current_package);
+        publisher.publish_crate(current_package)?;
+
+        let Some(step) = options.step.filter(|_| !options.dry_run) else {
+            continue;
+        };
+        if was_published || !publisher.already_published.contains(current_package) {
+            continue;
+        }
+        let next = order[index + 1..].iter().find(|name| {
+            !publisher.already_published.contains(*name) && !publisher.state.failed.contains_key(*name)
+        });
+        if let Some(next) = next {
+            if !step(current_package, next) {
+                return Err(ArmoryError::Stopped(current_package.clone()));
+            }
+        }
+    }
+
+    if !state.failed.is_empty() {
+        return Err(ArmoryError::Unpublished(state.failed.clone()));
+    }
+
+    if !options.dry_run {
+        PublishState::remove(dir)?;
+    }
+    Ok(())
+}
+
+/// A walk of the dependency graph publishing crates.
+struct Publisher<'a> {
+    dir: &'a Path,
+    graph: &'a HashMap<String, HashSet<String>>,
+    members: &'a [Member],
+    armory_toml: &'a ArmoryTOML,
+    options: &'a PublishOptions,
+    already_published: HashSet<String>,
+    state: &'a mut PublishState,
+    /// The rate limits of the registries published to so far.
+    throttles: HashMap<Option<String>, Throttle>,
+    started: Instant,
+}
+
+impl Publisher<'_> {
+    fn progress(&self, current_package: &str, progress: Progress) {
+        if let Some(report) = self.options.progress {
+            report(current_package, progress);
+        }
+    }
+
+    /// Whether the publish was asked to stop.
+    fn interrupted(&self) -> bool {
+        self.options.interrupted.is_some_and(|interrupted| interrupted())
+    }
+
+    /// Whether the upload under way was given up on.
+    fn aborted(&self) -> bool {
+        self.options.abort.is_some_and(|abort| abort())
+    }
+
+    /// Runs a publish hook for `crate_name`.
+    fn hook(&self, hook: Hook, crate_name: &str) -> Result<(), ArmoryError> {
+        let crates: HashMap<String, Version> = self.state.crates.clone().into_iter().collect();
+        self.armory_toml.run_hook(self.dir, hook, &crates, Some(crate_name))
+    }
+
+    fn publish_crate(&mut self, current_package: &str) -> Result<(), ArmoryError> {
+        if self.state.failed.contains_key(current_package) {
+            return Ok(());
+        }
+        // past the deadline, stop even with --keep-going
+        if self.remaining().is_some_and(|remaining| remaining.is_zero()) {
+            return Err(ArmoryError::TimedOut(current_package.to_string()));
+        }
+        // a resumed publish may have stopped between registries
+        if self.already_published.contains(current_package) {
+            return self.publish_mirrors(current_package);
+        }
+        // publish all the local dependencies first
+        let deps = &self.graph[current_package];
+        for local_dep in deps {
+            if !self.already_published.contains(local_dep) {
+                self.publish_crate(local_dep)?;
+            }
+        }
+        // with --keep-going, a failed dependency only holds back its dependents
+        if let Some(failed_dep) = deps.iter().find(|dep| self.state.failed.contains_key(*dep)) {
+            say!("ARMORY: skipping {}, which depends on {}", current_package, failed_dep);
+            self.state.failed.insert(current_package.to_string(), format!("depends on {}, which wasn't published", failed_dep));
+            self.progress(current_package, Progress::Failed);
+            return Ok(());
+        }
+
+        if self.interrupted() {
+            return Err(ArmoryError::Interrupted(current_package.to_string()));
+        }
+        let registry = self.armory_toml.registry_for(current_package);
+        let start = Instant::now();
+        let result = if self.is_on_registry(current_package, registry) {
+            say!("ARMORY: skipping {}, which is already on {}",
+                current_package, registry.unwrap_or(CRATES_IO_REGISTRY));
+            self.state.skipped.insert(current_package.to_string());
+            Ok(())
+        } else {
+            self.progress(current_package, if self.options.dry_run { Progress::Packaging } else { Progress::Uploading });
+            let result = self.hook(Hook::PrePublish, current_package)
+                .and_then(|()| self.throttle(registry).upload(current_package, registry));
+            let elapsed = start.elapsed().as_secs_f64();
+            self.state.durations.insert(current_package.to_string(), elapsed);
+            if result.is_ok() {
+                verbose!("ARMORY: published {} in {:.1}s", current_package, elapsed);
+            }
+            result
+        };
+        if let Err(e) = result {
+            self.progress(current_package, Progress::Failed);
+            // the upload may have made it, so resume has to look
+            if self.aborted() && !self.options.dry_run {
+                self.state.failed.insert(current_package.to_string(), e.to_string());
+                self.state.save(self.dir)?;
+                return Err(e);
+            }
+            if !self.options.keep_going {
+                return Err(e);
+            }
+            self.state.failed.insert(current_package.to_string(), e.to_string());
+            if !self.options.dry_run {
+                self.state.save(self.dir)?;
+            }
+            return Ok(());
+        }
+
+        self.already_published.insert(current_package.to_string());
+        if !self.options.dry_run {
+            self.state.published.insert(current_package.to_string());
+            self.state.save(self.dir)?;
+            self.hook(Hook::PostPublish, current_package)?;
+        }
+        self.publish_mirrors(current_package)?;
+
+        // dependents fail to resolve the new version until the index has it
+        let has_pending_dependents = self.graph
+            .iter()
+            .any(|(name, deps)| !self.already_published.contains(name) && deps.contains(current_package));
+        // nor is there any point waiting for the index once interrupted
+        let has_pending_dependents = has_pending_dependents && !self.interrupted();
+        if let (false, true, Some(version)) = (self.options.dry_run, has_pending_dependents, self.state.crates.get(current_package)) {
+            say!("ARMORY: waiting for {} {} to appear in the index", current_package, version);
+            self.progress(current_package, Progress::WaitingForIndex);
+            let timeout = self.remaining().map_or(INDEX_TIMEOUT, |remaining| remaining.min(INDEX_TIMEOUT));
+            let start = Instant::now();
+            if registry::wait_for_version(current_package, version, registry, timeout, || self.interrupted()) {
+                verbose!("ARMORY: {} {} appeared in the index after {:.1}s",
+                    current_package, version, start.elapsed().as_secs_f64());
+            } else if !self.interrupted() {
+                warning!("ARMORY: {} {} isn't in the index after {}s, publishing its dependents anyway",
+                    current_package, version, timeout.as_secs());
+            }
+        }
+
+        self.progress(current_package, Progress::Done);
+        Ok(())
+    }
+
+    /// Publishes a crate that made it to its own registry to the mirrors it
+    /// isn't on yet. Failing to do so doesn't hold back its dependents.
+    fn publish_mirrors(&mut self, current_package: &str) -> Result<(), ArmoryError> {
+        if !self.state.crates.contains_key(current_package) {
+            return Ok(());
+        }
+        let member = self.members.iter().find(|member| member.name == current_package);
+
+        for mirror in self.armory_toml.mirrors_for(current_package) {
+            let name = mirror.unwrap_or(CRATES_IO_REGISTRY);
+            if self.state.mirrored.get(current_package).is_some_and(|mirrored| mirrored.contains(name)) {
+                continue;
+            }
+            if member.is_some_and(|member| !member.can_publish_to(mirror)) {
+                say!("ARMORY: not mirroring {} to {}, where it may not be published", current_package, name);
+                continue;
+            }
+
+            if self.is_on_registry(current_package, mirror) {
+                say!("ARMORY: not mirroring {} to {}, which already has it", current_package, name);
+            } else if let Err(e) = self.throttle(mirror).upload(current_package, mirror) {
+                if !self.options.keep_going {
+                    return Err(e);
+                }
+                self.state.failed.insert(format!("{}@{}", current_package, name), e.to_string());
+            } else {
+                self.state.mirrored
+                    .entry(current_package.to_string())
+                    .or_default()
+                    .insert(name.to_string());
+            }
+            if !self.options.dry_run {
+                self.state.save(self.dir)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The time left before the publish's deadline, if it has one.
+    fn remaining(&self) -> Option<Duration> {
+        let deadline = self.options.deadline
+            .or(self.armory_toml.release_timeout.map(Duration::from_secs))?;
+        Some(deadline.saturating_sub(self.started.elapsed()))
+    }
+
+    /// Waits until `registry`'s rate limit allows another publish.
+    fn throttle(&mut self, registry: Option<&str>) -> &mut Self {
+        if !self.options.dry_run {
+            if let Some(limit) = self.armory_toml.rate_limit_for(registry) {
+                self.throttles
+                    .entry(registry.map(|registry| registry.to_string()))
+                    .or_insert_with(|| Throttle::new(limit))
+                    .wait();
+            }
+        }
+        self
+    }
+
+    /// Whether the version being released of `current_package` is on
+    /// `registry` already, e.g. from an earlier attempt at this release.
+    /// When the index can't be checked, assumes it isn't.
+    fn is_on_registry(&self, current_package: &str, registry: Option<&str>) -> bool {
+        let Some(version) = self.state.crates.get(current_package) else {
+            return false;
+        };
+        registry::is_published(current_package, version, registry).unwrap_or_else(|e| {
+            warning!("ARMORY: failed to check the index for {}: {}", current_package, e);
+            false
+        })
+    }
+
+    /// Publishes a single crate, retrying failed attempts, and records how
+    /// many it took.
+    fn upload(&mut self, current_package: &str, registry: Option<&str>) -> Result<(), ArmoryError> {
+        let (dir, armory_toml, options) = (self.dir, self.armory_toml, self.options);
+        if !armory_toml.is_registry_allowed(registry) {
+            return Err(format!("Refusing to publish {} to {}, which isn't in registries.allowed",
+                current_package, registry.unwrap_or(CRATES_IO_REGISTRY)).into());
+        }
+        let token = resolve_token(armory_toml, options.token.as_deref(), registry)
+            .map_err(|e| format!("Failed to get the token for {}: {}", current_package, e))?;
+
+        let verify = armory_toml.verify_for(current_package, options.verify || armory_toml.verify);
+        let targets = if options.targets.is_empty() {
+            armory_toml.targets_for(current_package).to_vec()
+        } else {
+            options.targets.clone()
+        };
+
+        let features = options.features_for(armory_toml, current_package);
+        let timeout = options.timeout.or(armory_toml.publish_timeout.map(Duration::from_secs));
+        let mut upload = Upload {
+            dir,
+            package: current_package,
+            registry,
+            token: token.as_deref(),
+            verify,
+            dry_run: options.dry_run,
+            targets: &targets,
+            features: &features,
+            timeout,
+            local_deps: &[],
+            abort: options.abort,
+        };
+
+        let mut attempts = 0;
+        let result = retry_with_index(options.retry.delays(), |current_try| {
+            attempts = current_try as usize;
+            // an attempt can't run past the publish's deadline
+            if let Some(remaining) = self.remaining() {
+                if remaining.is_zero() {
+                    return OperationResult::Err(ArmoryError::TimedOut(current_package.to_string()));
+                }
+                upload.timeout = Some(timeout.map_or(remaining, |timeout| timeout.min(remaining)));
+            }
+            if current_try > 1 && self.interrupted() {
+                return OperationResult::Err(ArmoryError::Interrupted(current_package.to_string()));
+            }
+            // a failed or timed out attempt may have made it anyway
+            if current_try > 1 && !options.dry_run && self.is_on_registry(current_package, registry) {
+                return OperationResult::Ok(());
+            }
+
+            verbose!("ARMORY: publishing {} to {}, attempt {}",
+                current_package, registry.unwrap_or(CRATES_IO_REGISTRY), current_try);
+            // going over the rate limit doesn't count as a failed attempt,
+            // but it doesn't go on forever or past the deadline either
+            let mut throttled = 0;
+            let result = loop {
+                let result = armory_toml.backend.publish(&upload);
+                let Some(wait) = result.as_ref().err().and_then(|e| throttle::rate_limit_delay(&e.to_string())) else {
+                    break result;
+                };
+                throttled += 1;
+                if !throttle::retry_rate_limited(throttled, wait, self.remaining()) {
+                    let message = format!("still rate limited after {} tries", throttled);
+                    return OperationResult::Err(ArmoryError::at(Stage::Publish, current_package, message));
+                }
+                say!("ARMORY: the registry is rate limiting publishes, retrying {} in {}s",
+                    current_package, wait.as_secs());
+                self.progress(current_package, Progress::RateLimited(wait));
+                std::thread::sleep(wait);
+            };
+
+            match result {
+                Ok(_) => OperationResult::Ok(()),
+                Err(e) => {
+                    let reason = match &e {
+                        ArmoryError::Crate { message, .. } => message.clone(),
+                        e => e.to_string(),
+                    };
+                    warning!("ARMORY: failed to publish {} after {} attempts: {}",
+                        current_package, current_try, reason);
+                    // nothing changes between attempts of a dry run, so
+                    // there's no point retrying
+                    if options.dry_run || self.aborted() {
+                        return OperationResult::Err(e);
+                    }
+                    if let Some(wait) = options.retry.delays().nth(current_try as usize - 1) {
+                        self.progress(current_package, Progress::Retrying(wait));
+                    }
+                    OperationResult::Retry(e)
+                }
+            }
+        })
+        .map_err(|e| match e.error {
+            ArmoryError::Crate { name, stage, message } if e.tries > 1 => {
+                ArmoryError::Crate { name, stage, message: format!("gave up after {} attempts: {}", e.tries, message) }
+            }
+            error => error,
+        });
+        self.state.attempts.insert(current_package.to_string(), attempts);
+        result
+    }
+}
+
+/// How long to wait for a published crate to show up in the index.
+const INDEX_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(crate) fn armory_toml(source: &str) -> ArmoryTOML {
+        config::parse(Path::new("armory.toml"), source).unwrap()
+    }
+
+    /// A workspace of `files` in a fresh directory named after the test.
+    pub(crate) fn workspace(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("armory-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn graph(edges: &[(&str, &[&str])]) -> HashMap<String, HashSet<String>> {
+        edges
+            .iter()
+            .map(|(name, deps)| (name.to_string(), deps.iter().map(|dep| dep.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn publish_after_changes_the_publish_order() {
+        let graph = graph(&[("a", &[]), ("b", &["a"]), ("c", &[])]);
+        assert_eq!(publish_order(&graph), ["a", "b", "c"]);
+
+        let armory_toml = armory_toml("version = \"0.1.0\"\n[crate.a]\npublish-after = [\"c\", \"not-a-member\"]\n");
+        let graph = with_publish_after(graph, &armory_toml);
+        assert_eq!(graph["a"], HashSet::from(["c".to_string()]));
+        assert_eq!(publish_order(&graph), ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn publish_chains_follow_the_longest_dependencies() {
+        let workspace = graph(&[
+            ("cli", &["lib", "util"]),
+            ("lib", &["macros", "util"]),
+            ("macros", &["util"]),
+            ("derive", &["util"]),
+            ("util", &[]),
+        ]);
+        let chains = |name: &str| publish_chains(&workspace, name);
+        assert_eq!(chains("cli"), [["cli", "lib", "macros", "util"]]);
+        assert_eq!(chains("derive"), [["derive", "util"]]);
+        assert!(chains("util").is_empty());
+        assert!(chains("not-a-member").is_empty());
+
+        let diamond = graph(&[("a", &["b", "c"]), ("b", &["d"]), ("c", &["d"]), ("d", &[])]);
+        assert_eq!(publish_chains(&diamond, "a"), [["a", "b", "d"], ["a", "c", "d"]]);
+    }
+
+    #[test]
+    fn members_inheriting_the_version_must_agree_on_it() {
+        let dir = workspace("inherited-version", &[
+            ("Cargo.toml", "[workspace]\nmembers = [\"a\", \"b\"]\n\n[workspace.package]\nversion = \"0.1.0\"\n"),
+            ("a/Cargo.toml", "[package]\nname = \"a\"\nversion.workspace = true\n"),
+            ("b/Cargo.toml", "[package]\nname = \"b\"\nversion.workspace = true\n"),
+        ]);
+        let armory_toml = armory_toml("version = \"0.1.0\"\n");
+        let version = |version: &str| version.parse::<Version>().unwrap();
+
+        let same = HashMap::from([("a".to_string(), version("0.2.0")), ("b".to_string(), version("0.2.0"))]);
+        let edits = plan_bump(&dir, &armory_toml, &same).unwrap();
+        assert!(edits.iter().any(|edit| edit.path == dir.join("Cargo.toml") && edit.after.contains("version = \"0.2.0\"")));
+
+        let different = HashMap::from([("a".to_string(), version("0.2.0")), ("b".to_string(), version("0.3.0"))]);
+        let error = plan_bump(&dir, &armory_toml, &different).unwrap_err().to_string();
+        assert!(error.contains("both inherit the workspace version"), "{}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn interrupted_publishes_stop_before_the_next_crate() {
+        let dir = workspace("interrupted", &[
+            ("Cargo.toml", "[workspace]\nmembers = [\"a\"]\n"),
+            ("a/Cargo.toml", "[package]\nname = \"a\"\nversion = \"0.1.0\"\n"),
+            ("a/src/lib.rs", ""),
+        ]);
+        let options = PublishOptions { dry_run: true, interrupted: Some(|| true), ..Default::default() };
+        let crates = HashSet::from(["a".to_string()]);
+        let error = publish_members(&dir, &armory_toml("version = \"0.1.0\"\n"), &crates, &options).unwrap_err();
+        assert!(matches!(&error, ArmoryError::Interrupted(name) if name == "a"), "{}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pinned_members_dont_move_the_shared_version() {
+        let mut armory_toml = armory_toml("version = \"0.1.0\"\n\n[overrides]\na = \"2.0.0\"\n");
+        let pinned = Member {
+            name: "a".to_string(),
+            path: "a".to_string(),
+            version: Some(Version::new(2, 0, 0)),
+            publish: None,
+        };
+        assert!(armory_toml.sync_from_registry(&[pinned]).unwrap().is_empty());
+        assert_eq!(armory_toml.version, Version::new(0, 1, 0));
+    }
+
+    #[test]
+    fn publish_options_default_to_what_armory_toml_says() {
+        let options = PublishOptions::new();
+        assert!(!options.dry_run && !options.verify && !options.keep_going);
+        assert_eq!((options.token, options.registry, options.features), (None, None, None));
+        assert!(options.targets.is_empty() && options.only.is_empty() && options.exclude.is_empty());
+        assert_eq!(options.retry, RetryPolicy { retries: 5, delay: Duration::from_secs(4) });
+        assert_eq!((options.timeout, options.deadline), (None, None));
+        assert!(options.step.is_none() && options.progress.is_none() && options.interrupted.is_none());
+
+        let options = PublishOptions::new()
+            .dry_run(true)
+            .token("secret")
+            .registry("internal")
+            .targets(["wasm32-unknown-unknown"])
+            .retry(RetryPolicy { retries: 2, ..Default::default() })
+            .only(["a"])
+            .exclude(["b"])
+            .timeout(Duration::from_secs(60));
+        assert!(options.dry_run && !options.verify);
+        assert_eq!((options.token.as_deref(), options.registry.as_deref()), (Some("secret"), Some("internal")));
+        assert_eq!(options.targets, ["wasm32-unknown-unknown"]);
+        assert_eq!((options.only, options.exclude), (vec!["a".to_string()], vec!["b".to_string()]));
+        assert_eq!(options.retry.retries, 2);
+        assert_eq!((options.timeout, options.deadline), (Some(Duration::from_secs(60)), None));
+    }
+
+    #[test]
+    fn publish_options_pick_the_crates_to_bump_and_publish() {
+        let dir = workspace("publish-options", &[
+            ("Cargo.toml", "[workspace]\nmembers = [\"a\", \"b\", \"c\"]\n"),
+            ("a/Cargo.toml", "[package]\nname = \"a\"\nversion = \"0.1.0\"\n"),
+            (
+                "b/Cargo.toml",
+                "[package]\nname = \"b\"\nversion = \"0.1.0\"\n\n[dependencies]\na = { path = \"../a\" }\n",
+            ),
+            ("c/Cargo.toml", "[package]\nname = \"c\"\nversion = \"0.1.0\"\n"),
+        ]);
+        let selected = select_members(&dir, &armory_toml("version = \"0.1.0\"\n"), &["b".to_string()], &[]).unwrap();
+        assert_eq!(selected.into_keys().collect::<BTreeSet<_>>(), BTreeSet::from(["a".to_string(), "b".to_string()]));
+
+        let options = PublishOptions::new().dry_run(true).only(["b"]).interrupted(|| true);
+        let error = publish_workspace(&dir, &Version::new(0, 2, 0), &options).unwrap_err();
+        assert!(matches!(&error, ArmoryError::Interrupted(name) if name == "a"), "{}", error);
+        assert!(fs::read_to_string(dir.join("b/Cargo.toml")).unwrap().contains("version = \"0.2.0\""));
+        assert!(fs::read_to_string(dir.join("c/Cargo.toml")).unwrap().contains("version = \"0.1.0\""));
+
+        let retry = RetryPolicy { retries: 3, delay: Duration::from_secs(1) };
+        let delays: Vec<u64> = retry.delays().map(|delay| delay.as_secs()).collect();
+        assert_eq!(delays, [1, 1, 2]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn broken_members_are_errors_naming_their_manifest() {
+        let dir = workspace("broken-member", &[
+            ("Cargo.toml", "[workspace]\nmembers = [\"a\", \"b\"]\n"),
+            ("a/Cargo.toml", "[package]\nname = \"a\"\nversion = \"0.1.0\"\n"),
+            ("b/Cargo.toml", "[lib]\npath = \"lib.rs\"\n"),
+        ]);
+        let error = workspace_members(&dir).unwrap_err();
+        assert!(matches!(&error, ArmoryError::Parse { path, .. } if *path == dir.join("b/Cargo.toml")), "{}", error);
+        assert!(publish_graph(&dir, &armory_toml("version = \"0.1.0\"\n")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reverting_edits_removes_the_files_they_created() {
+        let dir = workspace("revert-new-files", &[("Cargo.toml", "[workspace]\nmembers = []\n")]);
+        let edits = [
+            ManifestEdit {
+                path: dir.join("Cargo.toml"),
+                existed: true,
+                before: "[workspace]\nmembers = []\n".to_string(),
+                after: "[workspace]\nmembers = [\"crates/c\"]\n".to_string(),
+            },
+            ManifestEdit {
+                path: dir.join("crates/c/src/lib.rs"),
+                existed: false,
+                before: String::new(),
+                after: "pub fn c() {}\n".to_string(),
+            },
+        ];
+        apply_edits(&edits).unwrap();
+        assert!(dir.join("crates/c/src/lib.rs").exists());
+
+        revert_edits(&edits).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("Cargo.toml")).unwrap(), "[workspace]\nmembers = []\n");
+        assert!(!dir.join("crates").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn registries_include_mirrors_once() {
+        let armory_toml = armory_toml(
+            "version = \"0.1.0\"\nregistry = \"internal\"\nmirrors = [\"crates-io\", \"internal\"]\n\n\
+             [crate-registries]\npublic = \"crates-io\"\n",
+        );
+        let crates = ["public".to_string(), "private".to_string()];
+        assert_eq!(armory_toml.registries_for(&crates), BTreeSet::from([None, Some("internal")]));
+        assert_eq!(armory_toml.registries_for(&crates[..1]), BTreeSet::from([None, Some("internal")]));
+        assert_eq!(armory_toml.registries_for([]), BTreeSet::new());
+    }
+
+    #[test]
+    fn publish_after_can_close_a_cycle() {
+        let graph = graph(&[("a", &[]), ("b", &["a"])]);
+        assert_eq!(dependency_cycle(&graph), None);
+
+        let armory_toml = armory_toml("version = \"0.1.0\"\n[crate.a]\npublish-after = [\"b\"]\n");
+        assert_eq!(dependency_cycle(&with_publish_after(graph, &armory_toml)).unwrap(), ["a", "b", "a"]);
+    }
 }
@@ -0,0 +1,265 @@
+//! Turning another release tool's config into an armory.toml.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use semver::Version;
+use serde::Deserialize;
+
+use crate::{workspace_members, ArmoryError, ArmoryTOML, CrateConfig};
+
+/// An armory.toml generated from another tool's config, with notes on
+/// what couldn't be carried over as it was.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub armory_toml: ArmoryTOML,
+    pub notes: Vec<String>,
+}
+
+/// cargo-release's settings, from a `release.toml` or a
+/// `[workspace.metadata.release]` / `[package.metadata.release]` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ReleaseConfig {
+    allow_branch: Option<Vec<String>>,
+    sign_commit: Option<bool>,
+    sign_tag: Option<bool>,
+    registry: Option<String>,
+    release: Option<bool>,
+    publish: Option<bool>,
+    verify: Option<bool>,
+    push: Option<bool>,
+    push_remote: Option<String>,
+    tag: Option<bool>,
+    tag_prefix: Option<String>,
+    tag_name: Option<String>,
+    pre_release_commit_message: Option<String>,
+    pre_release_hook: Option<HookCommand>,
+    /// `true`, or the name of a group of crates sharing a version.
+    shared_version: Option<toml::Value>,
+    enable_features: Option<Vec<String>>,
+    enable_all_features: Option<bool>,
+    target: Option<String>,
+    /// Everything armory has no equivalent for.
+    #[serde(flatten)]
+    other: BTreeMap<String, toml::Value>,
+}
+
+/// A hook, as a shell command or a program followed by its arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+enum HookCommand {
+    Shell(String),
+    Args(Vec<String>),
+}
+
+impl HookCommand {
+    fn to_shell(&self) -> String {
+        match self {
+            HookCommand::Shell(command) => command.clone(),
+            HookCommand::Args(args) => args
+                .iter()
+                .map(|arg| {
+                    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c)) {
+                        arg.clone()
+                    } else {
+                        format!("'{}'", arg.replace('\'', r"'\''"))
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+impl ReleaseConfig {
+    /// Reads the table at `keys` of the TOML file at `path`, or the whole
+    /// file if `keys` is empty. Missing files and tables are empty configs.
+    fn read(path: &Path, keys: &[&str]) -> Result<Self, ArmoryError> {
+        let Ok(source) = fs::read_to_string(path) else {
+            return Ok(ReleaseConfig::default());
+        };
+        let mut value: toml::Value = toml::from_str(&source).map_err(|e| ArmoryError::parse(path, e))?;
+        for key in keys {
+            match value.get(key) {
+                Some(table) => value = table.clone(),
+                None => return Ok(ReleaseConfig::default()),
+            }
+        }
+        value.try_into().map_err(|e| ArmoryError::parse(path, format!("Invalid cargo-release config: {}", e)))
+    }
+
+    /// This config with `other`'s settings replacing its own.
+    fn overlay(mut self, other: ReleaseConfig) -> Self {
+        self.allow_branch = other.allow_branch.or(self.allow_branch);
+        self.sign_commit = other.sign_commit.or(self.sign_commit);
+        self.sign_tag = other.sign_tag.or(self.sign_tag);
+        self.registry = other.registry.or(self.registry);
+        self.release = other.release.or(self.release);
+        self.publish = other.publish.or(self.publish);
+        self.verify = other.verify.or(self.verify);
+        self.push = other.push.or(self.push);
+        self.push_remote = other.push_remote.or(self.push_remote);
+        self.tag = other.tag.or(self.tag);
+        self.tag_prefix = other.tag_prefix.or(self.tag_prefix);
+        self.tag_name = other.tag_name.or(self.tag_name);
+        self.pre_release_commit_message = other.pre_release_commit_message.or(self.pre_release_commit_message);
+        self.pre_release_hook = other.pre_release_hook.or(self.pre_release_hook);
+        self.shared_version = other.shared_version.or(self.shared_version);
+        self.enable_features = other.enable_features.or(self.enable_features);
+        self.enable_all_features = other.enable_all_features.or(self.enable_all_features);
+        self.target = other.target.or(self.target);
+        self.other.extend(other.other);
+        self
+    }
+
+    fn is_shared(&self) -> bool {
+        !matches!(self.shared_version, None | Some(toml::Value::Boolean(false)))
+    }
+}
+
+/// Generates an armory.toml equivalent to the cargo-release config of the
+/// workspace at `dir`: its `release.toml` files and `metadata.release`
+/// tables, with cargo-release's defaults for whatever they leave out.
+pub fn from_cargo_release(dir: &Path) -> Result<Migration, ArmoryError> {
+    let workspace = ReleaseConfig::read(&dir.join("Cargo.toml"), &["workspace", "metadata", "release"])?
+        .overlay(ReleaseConfig::read(&dir.join("release.toml"), &[])?);
+    let members = workspace_members(dir)?;
+    let mut crates = Vec::new();
+    for member in &members {
+        let member_dir = dir.join(&member.path);
+        let mut own = ReleaseConfig::read(&member_dir.join("Cargo.toml"), &["package", "metadata", "release"])?;
+        // the root package's release.toml is the workspace's
+        if member.path != "." {
+            own = own.overlay(ReleaseConfig::read(&member_dir.join("release.toml"), &[])?);
+        }
+        crates.push((member, own.clone(), workspace.clone().overlay(own)));
+    }
+    let mut notes = Vec::new();
+
+    // cargo-release versions crates on their own unless they share one
+    let shared = members.len() == 1 || crates.iter().all(|(_, _, config)| config.is_shared());
+    if !shared && crates.iter().any(|(_, _, config)| config.is_shared()) {
+        notes.push("only some crates had shared-version, so every crate is versioned on its own".to_string());
+    }
+    let versions: BTreeMap<String, Version> = members
+        .iter()
+        .filter_map(|member| Some((member.name.clone(), member.version.clone()?)))
+        .collect();
+    let version = versions.values().max().cloned().unwrap_or_else(|| Version::new(0, 1, 0));
+    let mut armory_toml = ArmoryTOML::new(version.clone());
+    if shared {
+        if versions.values().any(|other| *other != version) {
+            notes.push(format!("the crates have different versions, the next bump takes them all from {}", version));
+        }
+    } else {
+        armory_toml.independent = true;
+        armory_toml.versions = versions;
+    }
+
+    let branches = workspace.allow_branch.clone().unwrap_or_default();
+    for negated in branches.iter().filter(|branch| branch.starts_with('!') && *branch != "!HEAD") {
+        notes.push(format!("allow-branch can't exclude branches in armory, `{}` was dropped", negated));
+    }
+    armory_toml.release_branches = branches.into_iter().filter(|branch| !branch.starts_with('!')).collect();
+    if armory_toml.release_branches.iter().any(|branch| branch == "*") {
+        armory_toml.release_branches.clear();
+    }
+
+    // cargo-release commits, tags, pushes and verifies unless told not to
+    armory_toml.create_commit = true;
+    armory_toml.create_tags = workspace.tag.unwrap_or(true);
+    armory_toml.push = workspace.push.unwrap_or(true);
+    armory_toml.push_remote = workspace.push_remote.clone();
+    armory_toml.verify = workspace.verify.unwrap_or(true);
+    armory_toml.sign_git = workspace.sign_commit.unwrap_or(false) || workspace.sign_tag.unwrap_or(false);
+    armory_toml.registry = workspace.registry.clone();
+    armory_toml.targets = workspace.target.iter().cloned().collect();
+    if workspace.publish == Some(false) {
+        notes.push("publish = false has no armory equivalent, use `cargo armory bump` to release without publishing"
+            .to_string());
+    }
+
+    let default_prefix = if members.len() > 1 { "{{crate_name}}-" } else { "" };
+    let tag = workspace.tag_name.as_deref().unwrap_or("{{prefix}}v{{version}}")
+        .replace("{{prefix}}", workspace.tag_prefix.as_deref().unwrap_or(default_prefix));
+    armory_toml.tag = placeholders(&tag, "tag-name", &["version", "crate_name"], &mut notes);
+    if let Some(message) = &workspace.pre_release_commit_message {
+        armory_toml.commit_message =
+            Some(placeholders(message, "pre-release-commit-message", &["version"], &mut notes));
+    }
+    if let Some(hook) = &workspace.pre_release_hook {
+        armory_toml.hooks.post_bump = Some(hook.to_shell());
+        notes.push(
+            "pre-release-hook is now the post-bump hook, which runs once from the workspace root with \
+             ARMORY_VERSION and ARMORY_CRATES set instead of cargo-release's variables"
+                .to_string(),
+        );
+    }
+    unsupported(&workspace, "the workspace", &mut notes);
+
+    for (member, own, config) in &crates {
+        if config.release == Some(false) {
+            armory_toml.exclude.push(member.name.clone());
+            continue;
+        }
+        let mut crate_config = CrateConfig::default();
+        if config.registry != workspace.registry {
+            crate_config.registry = config.registry.clone();
+        }
+        if config.verify != workspace.verify {
+            crate_config.verify = config.verify;
+        }
+        if config.enable_features.is_some() || config.enable_all_features.is_some() {
+            crate_config.features = config.enable_features.clone();
+            crate_config.all_features = config.enable_all_features;
+        }
+        if crate_config != CrateConfig::default() {
+            armory_toml.crates.insert(member.name.clone(), crate_config);
+        }
+
+        if config.publish == Some(false) && workspace.publish != Some(false) {
+            notes.push(format!(
+                "{} had publish = false, set `publish = false` in its manifest or add it to `exclude`",
+                member.name
+            ));
+        }
+        let workspace_only = [
+            ("tag-name", own.tag_name.is_some() || own.tag_prefix.is_some()),
+            ("pre-release-commit-message", own.pre_release_commit_message.is_some()),
+            ("pre-release-hook", own.pre_release_hook.is_some()),
+            ("push", own.push.is_some() || own.push_remote.is_some()),
+            ("tag", own.tag.is_some()),
+        ];
+        for (key, set) in workspace_only {
+            if set {
+                notes.push(format!(
+                    "{} of {} wasn't carried over, armory only has it for the whole workspace",
+                    key, member.name
+                ));
+            }
+        }
+        unsupported(own, &member.name, &mut notes);
+    }
+
+    Ok(Migration { armory_toml, notes })
+}
+
+/// `template` with cargo-release's `{{placeholders}}` turned into
+/// armory's, noting the ones among `known` it can't carry over.
+fn placeholders(template: &str, key: &str, known: &[&str], notes: &mut Vec<String>) -> String {
+    let translated = template.replace("{{version}}", "{version}").replace("{{crate_name}}", "{crate}");
+    for placeholder in template.split("{{").skip(1).filter_map(|rest| rest.split_once("}}")).map(|(name, _)| name) {
+        if !known.contains(&placeholder) {
+            notes.push(format!("{} uses {{{{{}}}}}, which armory has no equivalent for", key, placeholder));
+        }
+    }
+    translated
+}
+
+/// Notes the keys of `config` that armory has no equivalent for.
+fn unsupported(config: &ReleaseConfig, owner: &str, notes: &mut Vec<String>) {
+    // armory always makes a single commit for a release
+    for key in config.other.keys().filter(|key| *key != "consolidate-commits") {
+        notes.push(format!("{} of {} has no armory equivalent", key, owner));
+    }
+}
@@ -0,0 +1,300 @@
+//! Where armory's progress lines go, and how many of them.
+//!
+//! They're printed to stdout, unless a caller wants stdout to itself, e.g.
+//! to print machine-readable results there, in which case they go to
+//! stderr. Lines more detailed than the [`Level`] set are left out, and
+//! from [`Level::Verbose`] on each one starts with the time since armory
+//! started, so slow steps stand out. A caller drawing its own progress
+//! display can take the lines, and cargo's output, with [`set_sink`].
+//!
+//! With [`log_to`], every line also goes to a log file, whatever the level,
+//! along with cargo's output, each stamped with the time of day.
+
+use std::{
+    fmt,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Instant,
+};
+
+use time::OffsetDateTime;
+
+use crate::ArmoryError;
+
+/// How much armory prints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Only warnings, along with cargo's errors.
+    Quiet,
+    /// Progress, along with cargo's own output.
+    #[default]
+    Normal,
+    /// Every publish step, timestamped.
+    Verbose,
+    /// The commands armory runs too, and cargo's verbose output.
+    Trace,
+}
+
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+static START: OnceLock<Instant> = OnceLock::new();
+static SINK: Mutex<Option<fn(&str)>> = Mutex::new(None);
+static LOG: Mutex<Option<(PathBuf, File)>> = Mutex::new(None);
+
+/// Sends progress lines to stderr rather than stdout, or back.
+pub fn progress_to_stderr(to_stderr: bool) {
+    TO_STDERR.store(to_stderr, Ordering::Relaxed);
+}
+
+/// Hands every line that would be printed, cargo's output included, to
+/// `sink` instead, or goes back to printing them with `None`.
+pub fn set_sink(sink: Option<fn(&str)>) {
+    *SINK.lock().unwrap() = sink;
+}
+
+/// Whether lines go to a sink rather than being printed.
+pub fn has_sink() -> bool {
+    SINK.lock().unwrap().is_some()
+}
+
+fn sink() -> Option<fn(&str)> {
+    *SINK.lock().unwrap()
+}
+
+/// Sets how much is printed from now on. Timestamps count from the first
+/// call.
+pub fn set_level(level: Level) {
+    START.get_or_init(Instant::now);
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Quiet,
+        1 => Level::Normal,
+        2 => Level::Verbose,
+        _ => Level::Trace,
+    }
+}
+
+/// `line` with the time since armory started in front, when verbose.
+fn stamped(line: fmt::Arguments) -> String {
+    if level() < Level::Verbose {
+        return line.to_string();
+    }
+    let elapsed = START.get_or_init(Instant::now).elapsed().as_secs_f64();
+    let (minutes, seconds) = ((elapsed / 60.0) as u64, elapsed % 60.0);
+    format!("[{:02}:{:02}:{:04.1}] {}", minutes / 60, minutes % 60, seconds, line)
+}
+
+/// Where the logs of the runs in the workspace at `dir` go, one file per
+/// run named after when it started.
+pub fn log_dir(dir: &Path) -> PathBuf {
+    dir.join(".armory").join("logs")
+}
+
+/// A log file in [`log_dir`] for a run starting now. Creating the
+/// directory gives it a `.gitignore`, so the logs don't count as
+/// uncommitted changes.
+pub fn new_log(dir: &Path) -> Result<PathBuf, ArmoryError> {
+    let logs = log_dir(dir);
+    if !logs.exists() {
+        fs::create_dir_all(&logs).map_err(|e| ArmoryError::io("create", &logs, e))?;
+        let ignore = logs.join(".gitignore");
+        fs::write(&ignore, "*\n").map_err(|e| ArmoryError::io("write", &ignore, e))?;
+    }
+    // colons aren't allowed in file names on Windows
+    let name = format!("{}.log", timestamp(OffsetDateTime::now_utc()).replace(':', "-"));
+    Ok(logs.join(name))
+}
+
+/// The UTC `time` in RFC 3339, to the second, such as
+/// `2024-05-01T09:30:00Z`. Written out by hand, unlike `time`'s formatter,
+/// it can't fail.
+pub(crate) fn timestamp(time: OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )
+}
+
+/// Also writes every line to the file at `path` from now on, starting with
+/// `header`. Lines are appended if the file exists, and the directories
+/// it's in are created if they don't.
+pub fn log_to(path: &Path, header: &str) -> Result<(), ArmoryError> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| ArmoryError::io("create", parent, e))?;
+    }
+    let mut file = File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| ArmoryError::io("open", path, e))?;
+    writeln!(file, "{} {}", timestamp(OffsetDateTime::now_utc()), header).map_err(|e| ArmoryError::io("write", path, e))?;
+    *LOG.lock().unwrap() = Some((path.to_path_buf(), file));
+    Ok(())
+}
+
+/// The file lines are logged to, if any.
+pub fn log_file() -> Option<PathBuf> {
+    LOG.lock().unwrap().as_ref().map(|(path, _)| path.clone())
+}
+
+/// Writes `line` to the log file only, without any styling. Failing to
+/// write is ignored rather than failing the release.
+pub fn log(line: &str) {
+    if let Some((_, file)) = LOG.lock().unwrap().as_mut() {
+        let now = OffsetDateTime::now_utc();
+        let _ = writeln!(
+            file,
+            "[{:02}:{:02}:{:02}.{:03}] {}",
+            now.hour(),
+            now.minute(),
+            now.second(),
+            now.millisecond(),
+            unstyled(line)
+        );
+    }
+}
+
+/// `line` without the escape codes coloring it.
+fn unstyled(line: &str) -> String {
+    let mut plain = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // a control sequence ends with its first letter
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// Prints a line of progress, if it's at or below the level set.
+pub fn line(level: Level, args: fmt::Arguments) {
+    log(&args.to_string());
+    if level > self::level() {
+        return;
+    }
+    let line = stamped(args);
+    if let Some(sink) = sink() {
+        sink(&line);
+    } else if TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Passes on what cargo printed to stderr, where it always goes, unless
+/// armory is quiet. Its errors are reported by armory either way.
+pub fn cargo_output(output: &str) {
+    for line in output.lines() {
+        log(line);
+    }
+    if level() == Level::Quiet {
+        return;
+    }
+    for line in output.lines() {
+        let line = stamped(format_args!("{}", line));
+        match sink() {
+            Some(sink) => sink(&line),
+            None => eprintln!("{}", line),
+        }
+    }
+}
+
+/// The flags that make a cargo command as quiet or verbose as armory.
+pub fn cargo_flags() -> &'static [&'static str] {
+    match level() {
+        Level::Quiet => &["--quiet"],
+        Level::Normal | Level::Verbose => &[],
+        Level::Trace => &["--verbose"],
+    }
+}
+
+/// [`cargo_flags`] for a cargo whose output goes through [`cargo_output`],
+/// which leaves it out of the terminal when quiet, so it still has its say
+/// in the log file.
+pub fn captured_cargo_flags() -> &'static [&'static str] {
+    match level() {
+        Level::Quiet if log_file().is_some() => &[],
+        _ => cargo_flags(),
+    }
+}
+
+/// `println!` for progress lines, which go wherever [`progress_to_stderr`]
+/// sends them and are left out with `--quiet`.
+#[macro_export]
+macro_rules! say {
+    () => {
+        $crate::output::line($crate::output::Level::Normal, format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::line($crate::output::Level::Normal, format_args!($($arg)*))
+    };
+}
+
+/// [`say!`] for details only shown with `--verbose`.
+#[macro_export]
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        $crate::output::line($crate::output::Level::Verbose, format_args!($($arg)*))
+    };
+}
+
+/// [`say!`] for details only shown with `-vv`.
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::output::line($crate::output::Level::Trace, format_args!($($arg)*))
+    };
+}
+
+/// [`say!`] for warnings, which are shown even with `--quiet`.
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::output::line($crate::output::Level::Quiet, format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logged_lines_lose_their_styles() {
+        assert_eq!(unstyled("\x1b[32m✔\x1b[0m my-lib \x1b[2m21.7s\x1b[0m"), "✔ my-lib 21.7s");
+        assert_eq!(unstyled("plain"), "plain");
+    }
+
+    #[test]
+    fn timestamps_are_rfc_3339() {
+        let time = OffsetDateTime::from_unix_timestamp(1_714_555_800).unwrap();
+        assert_eq!(timestamp(time), "2024-05-01T09:30:00Z");
+    }
+
+    #[test]
+    fn logs_are_named_for_when_they_started() {
+        let dir = std::env::temp_dir().join(format!("armory-new-log-{}", std::process::id()));
+        let log = new_log(&dir).unwrap();
+        assert_eq!(log.parent(), Some(log_dir(&dir).as_path()));
+        let name = log.file_name().unwrap().to_str().unwrap();
+        assert!(name.ends_with("Z.log") && !name.contains(':'), "{}", name);
+        assert_eq!(fs::read_to_string(log_dir(&dir).join(".gitignore")).unwrap(), "*\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
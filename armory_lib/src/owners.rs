@@ -0,0 +1,90 @@
+//! Who owns crates on crates.io and other registries.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ArmoryError;
+
+const CRATES_IO_API: &str = "https://crates.io/api/v1";
+/// crates.io, GitHub and GitLab turn away requests without a user agent naming the
+/// client.
+pub(crate) const USER_AGENT: &str = concat!("armory/", env!("CARGO_PKG_VERSION"), " (https://github.com/framework-tools/armory)");
+
+/// An owner of a crate, either a user or a GitHub team.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Owner {
+    /// The user's login, or `github:org:team` for teams.
+    pub login: String,
+    pub kind: String,
+}
+
+impl Owner {
+    pub fn is_team(&self) -> bool {
+        self.kind == "team"
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Owners {
+    users: Vec<Owner>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Me {
+    user: User,
+}
+
+#[derive(Debug, Deserialize)]
+struct User {
+    login: String,
+}
+
+/// The owners of `crate_name` on crates.io, or `None` if it was never
+/// published there.
+pub fn owners(crate_name: &str) -> Result<Option<Vec<Owner>>, ArmoryError> {
+    registry_owners(CRATES_IO_API, crate_name, None)
+}
+
+/// [`owners`] on the registry whose web API is at `api`, e.g.
+/// `https://crates.io/api/v1`, asking with `token` if it wants one.
+pub fn registry_owners(api: &str, crate_name: &str, token: Option<&str>) -> Result<Option<Vec<Owner>>, ArmoryError> {
+    let url = format!("{}/crates/{}/owners", api, crate_name);
+    let mut request = ureq::get(&url).set("User-Agent", USER_AGENT);
+    if let Some(token) = token {
+        request = request.set("Authorization", token);
+    }
+    let body = match request.call() {
+        Ok(response) => response
+            .into_string()
+            .map_err(|e| format!("Failed to read the owners of {}: {}", crate_name, e))?,
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(e) => return Err(format!("Failed to fetch the owners of {}: {}", crate_name, e).into()),
+    };
+    serde_json::from_str::<Owners>(&body)
+        .map(|owners| Some(owners.users))
+        .map_err(|e| format!("Failed to parse the owners of {}: {}", crate_name, e).into())
+}
+
+/// The login of the user `token` belongs to.
+pub fn token_owner(token: &str) -> Result<String, ArmoryError> {
+    let body = ureq::get(&format!("{}/me", CRATES_IO_API))
+        .set("User-Agent", USER_AGENT)
+        .set("Authorization", token)
+        .call()
+        .map_err(|e| format!("Failed to look up the owner of the token: {}", e))?
+        .into_string()
+        .map_err(|e| format!("Failed to read the owner of the token: {}", e))?;
+    serde_json::from_str::<Me>(&body)
+        .map(|me| me.user.login)
+        .map_err(|e| format!("Failed to parse the owner of the token: {}", e).into())
+}
+
+/// Why a crate couldn't be published with a token, if it couldn't.
+/// Crates owned by a team are given the benefit of the doubt, as
+/// crates.io doesn't say who's in it.
+pub fn rejection(login: &str, owners: &[Owner]) -> Option<String> {
+    if owners.iter().any(|owner| owner.is_team() || owner.login.eq_ignore_ascii_case(login)) {
+        return None;
+    }
+    let logins: Vec<&str> = owners.iter().map(|owner| owner.login.as_str()).collect();
+    Some(format!("{} isn't an owner, it's owned by {}", login, logins.join(", ")))
+}
@@ -0,0 +1,149 @@
+//! Queries against a registry's sparse index.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use cargo::Config;
+use semver::Version;
+use serde::Deserialize;
+
+use crate::ArmoryError;
+
+const CRATES_IO_INDEX: &str = "https://index.crates.io/";
+
+#[derive(Debug, Deserialize)]
+struct IndexEntry {
+    vers: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+/// The part of an index's config.json armory needs.
+#[derive(Debug, Deserialize)]
+struct IndexConfig {
+    api: Option<String>,
+}
+
+/// A version of a crate as listed in the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishedVersion {
+    pub version: Version,
+    pub yanked: bool,
+}
+
+/// The sparse index URL of `registry`, or crates.io if `None`.
+pub fn index_url(registry: Option<&str>) -> Result<String, ArmoryError> {
+    let registry = match registry {
+        Some(registry) => registry,
+        None => return Ok(CRATES_IO_INDEX.to_string()),
+    };
+
+    let cfg = Config::default().map_err(|e| ArmoryError::Cargo(format!("{:#}", e)))?;
+    let url = cfg
+        .get_registry_index(registry)
+        .map_err(|e| format!("Failed to find the index of registry {}: {}", registry, e))?
+        .to_string();
+
+    match url.strip_prefix("sparse+") {
+        Some(url) if url.ends_with('/') => Ok(url.to_string()),
+        Some(url) => Ok(format!("{}/", url)),
+        None => Err(format!(
+            "Registry {} doesn't use a sparse index ({}), which armory needs to query it",
+            registry, url
+        ).into()),
+    }
+}
+
+/// The URL of the web API of `registry`, or crates.io if `None`, from its
+/// index's config.json.
+pub fn api_url(registry: Option<&str>) -> Result<String, ArmoryError> {
+    let url = format!("{}config.json", index_url(registry)?);
+    let body = ureq::get(&url)
+        .call()
+        .map_err(|e| format!("Failed to fetch {}: {}", url, e))?
+        .into_string()
+        .map_err(|e| format!("Failed to read {}: {}", url, e))?;
+    let config: IndexConfig = serde_json::from_str(&body).map_err(|e| format!("Failed to parse {}: {}", url, e))?;
+    config
+        .api
+        .map(|api| api.trim_end_matches('/').to_string())
+        .ok_or_else(|| format!("Registry {} has no web API", registry.unwrap_or(crate::CRATES_IO_REGISTRY)).into())
+}
+
+/// Where a crate's file lives in the index, following cargo's layout.
+fn index_path(crate_name: &str) -> String {
+    let name = crate_name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+/// Every version of `crate_name` in the index, oldest first. A crate that
+/// was never published has none.
+pub fn published_versions(crate_name: &str, registry: Option<&str>) -> Result<Vec<PublishedVersion>, ArmoryError> {
+    let url = format!("{}{}", index_url(registry)?, index_path(crate_name));
+
+    let body = match ureq::get(&url).call() {
+        Ok(response) => response
+            .into_string()
+            .map_err(|e| format!("Failed to read {}: {}", url, e))?,
+        Err(ureq::Error::Status(404 | 410 | 451, _)) => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to fetch {}: {}", url, e).into()),
+    };
+
+    body.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let entry: IndexEntry = serde_json::from_str(line)
+                .map_err(|e| format!("Failed to parse index entry for {}: {}", crate_name, e))?;
+            let version = entry.vers.parse()
+                .map_err(|e| format!("Invalid version {} of {}: {}", entry.vers, crate_name, e))?;
+            Ok(PublishedVersion { version, yanked: entry.yanked })
+        })
+        .collect()
+}
+
+/// The highest version of `crate_name` that hasn't been yanked.
+pub fn latest_version(crate_name: &str, registry: Option<&str>) -> Result<Option<Version>, ArmoryError> {
+    Ok(published_versions(crate_name, registry)?
+        .into_iter()
+        .filter(|published| !published.yanked)
+        .map(|published| published.version)
+        .max())
+}
+
+/// Whether `version` of `crate_name` is in the index, yanked or not.
+pub fn is_published(crate_name: &str, version: &Version, registry: Option<&str>) -> Result<bool, ArmoryError> {
+    Ok(published_versions(crate_name, registry)?
+        .iter()
+        .any(|published| published.version == *version))
+}
+
+/// Polls the index until `version` of `crate_name` shows up, so crates
+/// depending on it can be published. Returns whether it did within
+/// `timeout`, giving up early once `stop` returns `true`.
+pub fn wait_for_version(
+    crate_name: &str,
+    version: &Version,
+    registry: Option<&str>,
+    timeout: Duration,
+    stop: impl Fn() -> bool,
+) -> bool {
+    let start = Instant::now();
+    loop {
+        match is_published(crate_name, version, registry) {
+            Ok(true) => return true,
+            Ok(false) => {}
+            Err(e) => warning!("ARMORY: failed to check the index for {}: {}", crate_name, e),
+        }
+        if start.elapsed() >= timeout || stop() {
+            return false;
+        }
+        thread::sleep(Duration::from_secs(2));
+    }
+}
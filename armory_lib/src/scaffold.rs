@@ -0,0 +1,275 @@
+//! New workspace members, set up the way armory releases them: listed in
+//! `[workspace.members]`, at armory's version, and with their local
+//! dependencies carrying a `path` and a `version` so they can be
+//! published from the start.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use toml_edit::{value, Document, InlineTable};
+
+use crate::{dependency_name, workspace_members, ArmoryError, ArmoryTOML, ManifestEdit};
+
+/// A member to add to the workspace.
+#[derive(Debug, Clone, Default)]
+pub struct NewMember {
+    /// The crate's name.
+    pub name: String,
+    /// Where it goes, relative to the workspace root.
+    pub path: String,
+    /// Whether it's a binary rather than a library. Ignored with a
+    /// template.
+    pub bin: bool,
+    /// A directory to copy the crate's files from, relative to the
+    /// workspace root, with `{crate}` in them replaced by its name.
+    pub template: Option<PathBuf>,
+    /// The members it depends on, by crate name.
+    pub dependencies: Vec<String>,
+}
+
+/// Works out the files of `member` and the edit to the root Cargo.toml
+/// adding it to the workspace. Nothing is written.
+pub fn plan(dir: &Path, armory_toml: &ArmoryTOML, member: &NewMember) -> Result<Vec<ManifestEdit>, ArmoryError> {
+    let members = workspace_members(dir)?;
+    let path = member.path.trim().trim_end_matches('/');
+    if members.iter().any(|existing| existing.name == member.name) {
+        return Err(format!("The workspace already has a member named {}", member.name).into());
+    }
+    if members.iter().any(|existing| existing.path.trim_end_matches('/') == path) {
+        return Err(format!("{} is already a member of the workspace", path).into());
+    }
+    let member_dir = dir.join(path);
+    if member_dir.exists() {
+        return Err(format!("{} already exists", member_dir.display()).into());
+    }
+    let mut dependencies = Vec::new();
+    for name in &member.dependencies {
+        let dep = members
+            .iter()
+            .find(|existing| existing.name == *name)
+            .ok_or_else(|| format!("{} isn't a member of the workspace", name))?;
+        dependencies.push((dep.name.as_str(), dep.path.trim().trim_end_matches('/')));
+    }
+
+    let root_path = dir.join("Cargo.toml");
+    let root_source = fs::read_to_string(&root_path).map_err(|e| ArmoryError::io("read", &root_path, e))?;
+    let mut root_toml = root_source.parse::<Document>().map_err(|e| ArmoryError::parse(&root_path, e))?;
+
+    let mut files = match &member.template {
+        Some(template) => template_files(&dir.join(template))?,
+        None if member.bin => {
+            vec![(PathBuf::from("src/main.rs"), "fn main() {\n    println!(\"Hello, world!\");\n}\n".to_string())]
+        }
+        None => vec![(PathBuf::from("src/lib.rs"), String::new())],
+    };
+    for (_, source) in &mut files {
+        *source = source.replace("{crate}", &member.name);
+    }
+    let manifest = match files.iter().position(|(file, _)| file == Path::new("Cargo.toml")) {
+        Some(index) => files.remove(index).1,
+        None => "[package]\n".to_string(),
+    };
+    let manifest = member_manifest(&manifest, &root_toml, armory_toml, member, path, &dependencies)?;
+
+    let members_array = root_toml["workspace"]["members"]
+        .as_array_mut()
+        .ok_or_else(|| ArmoryError::parse(&root_path, "there's no [workspace] members list"))?;
+    // one a line if the list already is
+    let decor = members_array.iter().last().map(|last| last.decor().clone());
+    members_array.push(path);
+    if let (Some(decor), Some(added)) = (decor, members_array.iter_mut().last()) {
+        *added.decor_mut() = decor;
+    }
+
+    let root_edit = ManifestEdit { path: root_path, existed: true, before: root_source, after: root_toml.to_string() };
+    let mut edits = vec![root_edit];
+    edits.push(ManifestEdit {
+        path: member_dir.join("Cargo.toml"),
+        existed: false,
+        before: String::new(),
+        after: manifest,
+    });
+    for (file, source) in files {
+        edits.push(ManifestEdit { path: member_dir.join(file), existed: false, before: String::new(), after: source });
+    }
+    Ok(edits)
+}
+
+/// The manifest of `member` at `path`, from the template's `manifest`: its
+/// name, armory's version, what `[workspace.package]` has to inherit, and
+/// its local `dependencies`, as `(name, path)`.
+fn member_manifest(
+    manifest: &str,
+    root_toml: &Document,
+    armory_toml: &ArmoryTOML,
+    member: &NewMember,
+    path: &str,
+    dependencies: &[(&str, &str)],
+) -> Result<String, ArmoryError> {
+    let template_manifest = member.template.clone().unwrap_or_default().join("Cargo.toml");
+    let mut manifest = manifest.parse::<Document>().map_err(|e| ArmoryError::parse(&template_manifest, e))?;
+    let inherit = || {
+        let mut table = InlineTable::new();
+        table.insert("workspace", true.into());
+        table.set_dotted(true);
+        value(table)
+    };
+
+    let package = manifest["package"]
+        .or_insert(toml_edit::table())
+        .as_table_mut()
+        .ok_or_else(|| ArmoryError::parse(&template_manifest, "[package] isn't a table"))?;
+    // the name and version go first, ahead of what the template has
+    let rest: Vec<(String, toml_edit::Item)> = package
+        .iter()
+        .filter(|(key, _)| !["name", "version"].contains(key))
+        .map(|(key, item)| (key.to_string(), item.clone()))
+        .collect();
+    package.clear();
+    package["name"] = value(&member.name);
+    let version = armory_toml.version_of(&member.name);
+    let workspace_package = root_toml.get("workspace").and_then(|workspace| workspace.get("package"));
+    let workspace_version =
+        workspace_package.and_then(|package| package.get("version")).and_then(|version| version.as_str());
+    package["version"] = match workspace_version == Some(version.to_string().as_str()) {
+        true => inherit(),
+        false => value(version.to_string()),
+    };
+    for (key, item) in rest {
+        package.insert(&key, item);
+    }
+    let inherited: Vec<&str> = workspace_package
+        .and_then(|package| package.as_table_like())
+        .map(|package| package.iter().map(|(key, _)| key).filter(|key| *key != "version").collect())
+        .unwrap_or_default();
+    for key in &inherited {
+        if !package.contains_key(key) {
+            package[key] = inherit();
+        }
+    }
+    if !package.contains_key("edition") {
+        package["edition"] = value("2021");
+    }
+    if let Some(rust_version) = &armory_toml.rust_version {
+        if !package.contains_key("rust-version") {
+            package["rust-version"] = value(rust_version);
+        }
+    }
+
+    if dependencies.is_empty() {
+        return Ok(manifest.to_string());
+    }
+    let table = manifest["dependencies"]
+        .or_insert(toml_edit::table())
+        .as_table_mut()
+        .ok_or_else(|| ArmoryError::parse(&template_manifest, "[dependencies] isn't a table"))?;
+    // local crates the workspace declares once, keyed by crate name
+    let workspace_deps: HashMap<String, String> = root_toml
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"))
+        .and_then(|deps| deps.as_table_like())
+        .map(|deps| {
+            deps.iter()
+                .filter_map(|(key, dep)| {
+                    let dep = dep.as_table_like()?;
+                    dep.get("path")?;
+                    Some((dependency_name(key, dep), key.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let up = "../".repeat(Path::new(path).components().count());
+    for (name, dep_path) in dependencies {
+        if let Some(key) = workspace_deps.get(*name) {
+            table[key] = inherit();
+            continue;
+        }
+        let mut dep = InlineTable::new();
+        dep.insert("path", format!("{}{}", up, dep_path).into());
+        let requirement = armory_toml.dependency_requirement.format(None, armory_toml.version_of(name));
+        dep.insert("version", requirement.into());
+        if let Some(registry) = armory_toml.registry_for(name) {
+            dep.insert("registry", registry.into());
+        }
+        table[*name] = value(dep);
+    }
+    Ok(manifest.to_string())
+}
+
+/// Every text file under `template`, relative to it, with its contents.
+/// Files that aren't UTF-8 are left out with a warning, since the edits
+/// armory shows and writes are text.
+fn template_files(template: &Path) -> Result<Vec<(PathBuf, String)>, ArmoryError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(relative) = dirs.pop() {
+        let read_dir = |e| ArmoryError::io("read", template.join(&relative), e);
+        for entry in fs::read_dir(template.join(&relative)).map_err(read_dir)? {
+            let entry = entry.map_err(read_dir)?;
+            let file = relative.join(entry.file_name());
+            if entry.file_type().map_err(read_dir)?.is_dir() {
+                dirs.push(file);
+            } else {
+                let bytes = fs::read(entry.path()).map_err(|e| ArmoryError::io("read", entry.path(), e))?;
+                match String::from_utf8(bytes) {
+                    Ok(source) => files.push((file, source)),
+                    Err(_) => warning!("ARMORY: leaving out {}, which isn't UTF-8 text", file.display()),
+                }
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{armory_toml, workspace};
+
+    #[test]
+    fn new_members_depend_on_others_by_path_and_version() {
+        let dir = workspace("new-member", &[
+            (
+                "Cargo.toml",
+                "[workspace]\nmembers = [\n    \"a\",\n    \"b\",\n]\n\n[workspace.dependencies]\nb = { path = \"b\" }\n",
+            ),
+            ("a/Cargo.toml", "[package]\nname = \"a\"\nversion = \"0.3.0\"\n"),
+            ("b/Cargo.toml", "[package]\nname = \"b\"\nversion = \"0.3.0\"\n"),
+        ]);
+        let armory_toml = armory_toml("version = \"0.3.0\"\ndependency-requirement = \"exact\"\n");
+        let member = NewMember {
+            name: "c".to_string(),
+            path: "crates/c".to_string(),
+            dependencies: vec!["a".to_string(), "b".to_string()],
+            ..Default::default()
+        };
+        let edits = plan(&dir, &armory_toml, &member).unwrap();
+        assert!(edits[0].after.contains("    \"b\",\n    \"crates/c\",\n]"), "{}", edits[0].after);
+        assert_eq!(edits[1].path, dir.join("crates/c/Cargo.toml"));
+        assert_eq!(
+            edits[1].after,
+            "[package]\nname = \"c\"\nversion = \"0.3.0\"\nedition = \"2021\"\n\n\
+             [dependencies]\na = { path = \"../../a\", version = \"=0.3.0\" }\nb.workspace = true\n"
+        );
+        assert_eq!(edits[2].path, dir.join("crates/c/src/lib.rs"));
+
+        let member = NewMember { dependencies: vec!["d".to_string()], ..member };
+        let error = plan(&dir, &armory_toml, &member).unwrap_err();
+        assert_eq!(error.to_string(), "d isn't a member of the workspace");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn templates_leave_out_files_that_arent_text() {
+        let template = workspace("binary-template", &[("src/lib.rs", "//! {crate}\n")]);
+        fs::write(template.join("logo.png"), [0x89, b'P', b'N', b'G', 0xff, 0xfe]).unwrap();
+
+        let files = template_files(&template).unwrap();
+        assert_eq!(files, [(PathBuf::from("src/lib.rs"), "//! {crate}\n".to_string())]);
+        fs::remove_dir_all(template).unwrap();
+    }
+}
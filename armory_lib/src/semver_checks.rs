@@ -0,0 +1,43 @@
+//! Integration with [cargo-semver-checks](https://github.com/obi1kenobi/cargo-semver-checks).
+
+use std::{path::Path, process::Command};
+
+use semver::Version;
+
+use crate::{ArmoryError, Bump};
+
+/// Whether `cargo semver-checks` is installed.
+pub fn is_installed() -> bool {
+    Command::new("cargo")
+        .args(["semver-checks", "--version"])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Checks `crate_name` against its last published version and returns the
+/// smallest bump from `current` that keeps it semver compatible.
+pub fn required_bump(dir: &Path, crate_name: &str, current: &Version) -> Result<Bump, ArmoryError> {
+    let output = Command::new("cargo")
+        .current_dir(dir)
+        .args(["semver-checks", "check-release", "--package", crate_name, "--release-type", "patch"])
+        .output()
+        .map_err(|e| format!("Failed to run cargo semver-checks: {}", e))?;
+
+    if output.status.success() {
+        return Ok(Bump::Patch);
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let text = format!("{}{}", stdout, stderr);
+    // semver-checks speaks in cargo's terms, where a 0.x minor is already
+    // breaking, so map it back onto the plain semver fields we bump
+    if text.contains("requires new major version") {
+        Ok(Bump::Major.for_version(current))
+    } else if text.contains("requires new minor version") {
+        Ok(Bump::Minor.for_version(current))
+    } else {
+        Err(format!("cargo semver-checks failed for {}: {}", crate_name, stderr.trim()).into())
+    }
+}
@@ -0,0 +1,130 @@
+//! Progress of an unfinished publish, kept in `.armory/state.json` so it
+//! can be resumed, and what an unfinished release changed, kept in
+//! `.armory/snapshot.json` so it can be rolled back.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::ArmoryError;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PublishState {
+    /// Every crate being published, with its version.
+    pub crates: BTreeMap<String, Version>,
+    /// The crates published so far.
+    pub published: BTreeSet<String>,
+    /// The mirrors each crate was published to so far, by crate name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mirrored: BTreeMap<String, BTreeSet<String>>,
+    /// The crates that failed to publish, or were held back by a
+    /// dependency that did, with the reason. Failures to publish to a
+    /// mirror are keyed by `crate@mirror`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub failed: BTreeMap<String, String>,
+    /// How long each crate took to publish, in seconds, by crate name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub durations: BTreeMap<String, f64>,
+    /// How many attempts at uploading each crate it took, by crate name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attempts: BTreeMap<String, usize>,
+    /// The crates whose version was on the registry already, and so
+    /// weren't uploaded again.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub skipped: BTreeSet<String>,
+}
+
+impl PublishState {
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(".armory").join("state.json")
+    }
+
+    /// The state of the unfinished publish in `dir`, if there is one.
+    pub fn load(dir: &Path) -> Result<Option<Self>, ArmoryError> {
+        load(&Self::path(dir))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), ArmoryError> {
+        save(&Self::path(dir), self)
+    }
+
+    /// Deletes the state once the publish is done.
+    pub fn remove(dir: &Path) -> Result<(), ArmoryError> {
+        remove(&Self::path(dir))
+    }
+
+    /// The crates still to publish.
+    pub fn pending(&self) -> impl Iterator<Item = (&String, &Version)> {
+        self.crates.iter().filter(|(name, _)| !self.published.contains(*name))
+    }
+}
+
+/// How the workspace was before a release, kept until the release
+/// finishes so an aborted one can be undone with `armory rollback`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReleaseSnapshot {
+    /// The commit the release started from.
+    pub head: String,
+    /// Every crate being released, with its new version.
+    pub crates: BTreeMap<String, Version>,
+    /// The files the bump rewrote, relative to the workspace root, with
+    /// their contents before it. Empty ones didn't exist.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<PathBuf, String>,
+    /// The tags the release creates.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// The message of the commit the release creates.
+    pub commit_message: String,
+}
+
+impl ReleaseSnapshot {
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(".armory").join("snapshot.json")
+    }
+
+    /// The snapshot of the unfinished release in `dir`, if there is one.
+    pub fn load(dir: &Path) -> Result<Option<Self>, ArmoryError> {
+        load(&Self::path(dir))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), ArmoryError> {
+        save(&Self::path(dir), self)
+    }
+
+    /// Deletes the snapshot once the release is done or rolled back.
+    pub fn remove(dir: &Path) -> Result<(), ArmoryError> {
+        remove(&Self::path(dir))
+    }
+}
+
+fn load<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<Option<T>, ArmoryError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let source = fs::read_to_string(path).map_err(|e| ArmoryError::io("read", path, e))?;
+    serde_json::from_str(&source).map(Some).map_err(|e| ArmoryError::parse(path, e))
+}
+
+fn save<T: Serialize>(path: &Path, value: &T) -> Result<(), ArmoryError> {
+    let dir = path.parent().unwrap_or(path);
+    fs::create_dir_all(dir).map_err(|e| ArmoryError::io("create", dir, e))?;
+    let json = serde_json::to_string_pretty(value).map_err(|e| ArmoryError::io("write", path, e.into()))?;
+    fs::write(path, json).map_err(|e| ArmoryError::io("write", path, e))
+}
+
+fn remove(path: &Path) -> Result<(), ArmoryError> {
+    if path.exists() {
+        fs::remove_file(path).map_err(|e| ArmoryError::io("remove", path, e))?;
+    }
+    // only clean up the directory if nothing else lives there
+    if let Some(dir) = path.parent() {
+        let _ = fs::remove_dir(dir);
+    }
+    Ok(())
+}
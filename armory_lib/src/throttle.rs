@@ -0,0 +1,126 @@
+//! Pacing publishes to stay within a registry's rate limits.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc2822, OffsetDateTime};
+
+/// How many crates a registry accepts in a row, and how quickly it lets
+/// more through after that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub burst: u32,
+    /// Seconds between publishes once the burst is used up.
+    pub interval: u64,
+}
+
+impl RateLimit {
+    /// crates.io's limit on new versions of existing crates.
+    pub const CRATES_IO: RateLimit = RateLimit { burst: 30, interval: 60 };
+}
+
+/// A token bucket following a [`RateLimit`].
+#[derive(Debug)]
+pub struct Throttle {
+    limit: RateLimit,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Throttle {
+    pub fn new(limit: RateLimit) -> Self {
+        Throttle { limit, tokens: limit.burst as f64, last: Instant::now() }
+    }
+
+    /// Blocks until the next publish fits in the limit, and counts it.
+    pub fn wait(&mut self) {
+        self.refill();
+        if self.tokens < 1.0 {
+            let wait = Duration::from_secs_f64((1.0 - self.tokens) * self.limit.interval as f64);
+            say!("ARMORY: waiting {}s to stay within the registry's rate limit", wait.as_secs_f64().ceil());
+            thread::sleep(wait);
+            self.refill();
+        }
+        self.tokens -= 1.0;
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        if self.limit.interval > 0 {
+            let refilled = now.duration_since(self.last).as_secs_f64() / self.limit.interval as f64;
+            self.tokens = (self.tokens + refilled).min(self.limit.burst as f64);
+        } else {
+            self.tokens = self.limit.burst.max(1) as f64;
+        }
+        self.last = now;
+    }
+}
+
+/// How many times in a row a publish is tried while the registry rate
+/// limits it, before giving up.
+pub const RATE_LIMITED_TRIES: u32 = 10;
+
+/// Whether to wait `wait` and try a publish again after the registry
+/// rate limited it `tries` times in a row, with `remaining` left before
+/// the publish's deadline if it has one.
+pub fn retry_rate_limited(tries: u32, wait: Duration, remaining: Option<Duration>) -> bool {
+    tries < RATE_LIMITED_TRIES && remaining.is_none_or(|remaining| remaining >= wait)
+}
+
+/// If `error` is the registry turning a publish down for going over its
+/// rate limit, how long to wait before trying again: what the registry
+/// asked for if it said, otherwise a minute.
+pub fn rate_limit_delay(error: &str) -> Option<Duration> {
+    let lower = error.to_lowercase();
+    if !lower.contains("429") && !lower.contains("too many requests") {
+        return None;
+    }
+
+    if let Some(seconds) = after(&lower, "retry-after:")
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|seconds| seconds.parse().ok())
+    {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    // crates.io: "Please try again after Fri, 08 Mar 2024 10:11:12 GMT"
+    if let Some(date) = after(error, "try again after ") {
+        let date = date.split(" or ").next().unwrap_or(date).trim().trim_end_matches('.');
+        if let Ok(date) = OffsetDateTime::parse(date, &Rfc2822) {
+            let wait = date - OffsetDateTime::now_utc();
+            return Some(Duration::try_from(wait).unwrap_or_default() + Duration::from_secs(1));
+        }
+    }
+
+    Some(Duration::from_secs(60))
+}
+
+fn after<'a>(text: &'a str, marker: &str) -> Option<&'a str> {
+    text.find(marker).map(|start| &text[start + marker.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit_delay_reads_what_the_registry_asked_for() {
+        assert_eq!(rate_limit_delay("error: 500 Internal Server Error"), None);
+        assert_eq!(rate_limit_delay("status 429, Retry-After: 17"), Some(Duration::from_secs(17)));
+        assert_eq!(rate_limit_delay("429 Too Many Requests"), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn rate_limited_publishes_give_up() {
+        let wait = Duration::from_secs(60);
+        assert!(retry_rate_limited(1, wait, None));
+        assert!(!retry_rate_limited(RATE_LIMITED_TRIES, wait, None));
+        // waiting would go past the deadline
+        assert!(!retry_rate_limited(1, wait, Some(Duration::from_secs(30))));
+        assert!(retry_rate_limited(1, wait, Some(Duration::from_secs(90))));
+    }
+}